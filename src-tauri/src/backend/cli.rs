@@ -0,0 +1,505 @@
+use super::{
+    CommitHeader, DiffAlgorithm, DiffSettings, FilePatch, GitBackend, NewBranch, RestoreSource, SignatureKind,
+    Trailer, Whitespace, HIDDEN_REFS,
+};
+use crate::error::GitError;
+use crate::status::{parse_porcelain_v2, StatusEntry};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::{Command, Output};
+
+// run `git` in `directory` and hand back the raw output, whatever its exit status
+pub fn run_git_unchecked(directory: &str, args: &[&str]) -> Result<Output, GitError> {
+    run_git_with_env(directory, args, &[])
+}
+
+// run `git` with extra environment variables, such as GIT_INDEX_FILE, whatever its exit status.
+// Errors are classified from git's messages, so it always runs in the C locale, untranslated.
+pub fn run_git_with_env(directory: &str, args: &[&str], env: &[(&str, &str)]) -> Result<Output, GitError> {
+    Command::new("git")
+        .args(args)
+        .env("LC_ALL", "C")
+        .envs(env.iter().copied())
+        .current_dir(directory)
+        .output()
+        .map_err(|err| match err.kind() {
+            ErrorKind::NotFound if Path::new(directory).is_dir() => {
+                GitError::GitBinaryMissing {
+                    message: err.to_string(),
+                }
+            }
+            ErrorKind::NotFound => GitError::RepoNotFound {
+                path: directory.to_string(),
+                message: err.to_string(),
+            },
+            _ => GitError::from(err),
+        })
+}
+
+// run `git` in `directory` and return its stdout, or a classified error on failure
+pub fn run_git(directory: &str, args: &[&str]) -> Result<String, GitError> {
+    let output = run_git_unchecked(directory, args)?;
+
+    if !output.status.success() {
+        return Err(command_error(directory, args, &output));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+// classify a failed invocation, filling in the repository path git does not print
+pub fn command_error(directory: &str, args: &[&str], output: &Output) -> GitError {
+    match GitError::from_output(args, output) {
+        GitError::RepoNotFound { message, .. } => GitError::RepoNotFound {
+            path: directory.to_string(),
+            message,
+        },
+        err => err,
+    }
+}
+
+// a `git diff` or `git show` command line for the settings. The algorithm is always named so
+// diff.algorithm in the user's config cannot replace the one asked for, and files above the size
+// limit are diffed as binary through core.bigFileThreshold. Paths are left unquoted and the
+// prefixes fixed, so `split_patches` can read them back.
+fn diff_command(command: &str, settings: &DiffSettings) -> Vec<String> {
+    let mut args = vec![
+        "-c".to_string(),
+        format!("core.bigFileThreshold={}", settings.max_file_size()),
+        "-c".to_string(),
+        "core.quotePath=false".to_string(),
+        command.to_string(),
+        format!("--unified={}", settings.context()),
+        "--src-prefix=a/".to_string(),
+        "--dst-prefix=b/".to_string(),
+    ];
+    match settings.whitespace {
+        Whitespace::Show => {}
+        Whitespace::IgnoreAll => args.push("--ignore-all-space".to_string()),
+        Whitespace::IgnoreChange => args.push("--ignore-space-change".to_string()),
+        Whitespace::IgnoreAtEol => args.push("--ignore-space-at-eol".to_string()),
+    }
+    if settings.ignore_blank_lines {
+        args.push("--ignore-blank-lines".to_string());
+    }
+    let algorithm = match settings.algorithm {
+        DiffAlgorithm::Myers => "myers",
+        DiffAlgorithm::Minimal => "minimal",
+        DiffAlgorithm::Patience => "patience",
+        DiffAlgorithm::Histogram => "histogram",
+    };
+    args.push(format!("--diff-algorithm={}", algorithm));
+    args
+}
+
+// the status of a file from the header lines of its patch
+fn patch_status(patch: &str) -> &'static str {
+    for line in patch.lines() {
+        if line.starts_with("@@") {
+            break;
+        }
+        if line.starts_with("new file mode") {
+            return "added";
+        }
+        if line.starts_with("deleted file mode") {
+            return "deleted";
+        }
+        if line.starts_with("rename from") {
+            return "renamed";
+        }
+        if line.starts_with("copy from") {
+            return "copied";
+        }
+    }
+    "modified"
+}
+
+// the path a patch is listed under, from its header lines: the new side, or the old one for a
+// deletion. Binary and mode-only changes have no `---`/`+++` lines, and there the path is the
+// half of `diff --git a/<path> b/<path>` both sides share.
+fn patch_path(header: &[&str]) -> String {
+    let mut old_path = None;
+    for line in header.iter().skip(1) {
+        if line.starts_with("@@") {
+            break;
+        }
+        // git ends the `---`/`+++` lines of paths with spaces with a tab
+        if let Some(path) = line.strip_prefix("+++ b/") {
+            return path.trim_end_matches('\t').to_string();
+        }
+        if let Some(path) = line.strip_prefix("rename to ").or_else(|| line.strip_prefix("copy to ")) {
+            return path.to_string();
+        }
+        if let Some(path) = line.strip_prefix("--- a/") {
+            old_path = Some(path.trim_end_matches('\t').to_string());
+        }
+    }
+    if let Some(path) = old_path {
+        return path;
+    }
+
+    let names = header[0].strip_prefix("diff --git ").unwrap_or_default();
+    let half = names.len().saturating_sub(5) / 2;
+    match (names.get(2..2 + half), names.get(2 + half..)) {
+        (Some(path), Some(rest)) if rest.strip_prefix(" b/") == Some(path) => path.to_string(),
+        _ => names.rsplit(" b/").next().unwrap_or_default().to_string(),
+    }
+}
+
+// split `git show`/`git diff` output into one patch per file
+fn split_patches(output: &str) -> Vec<FilePatch> {
+    let mut patches = Vec::new();
+    let mut current_diff: Vec<&str> = Vec::new();
+
+    let mut flush = |lines: &[&str]| {
+        if lines.is_empty() {
+            return;
+        }
+        let mut patch = lines.join("\n");
+        patch.push('\n');
+        patches.push(FilePatch {
+            path: patch_path(lines),
+            status: patch_status(&patch),
+            patch,
+        });
+    };
+
+    for line in output.lines() {
+        if line.starts_with("diff --git") {
+            flush(&current_diff);
+            current_diff.clear();
+        }
+        if !current_diff.is_empty() || line.starts_with("diff --git") {
+            current_diff.push(line);
+        }
+    }
+    flush(&current_diff);
+
+    patches
+}
+
+// the git CLI, for what libgit2 does not support (partial clone, some credential helpers, ...)
+pub struct CliBackend {
+    directory: String,
+}
+
+impl CliBackend {
+    pub fn new(directory: &str) -> Self {
+        CliBackend {
+            directory: directory.to_string(),
+        }
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String, GitError> {
+        run_git(&self.directory, args)
+    }
+
+    // git reports most porcelain messages on stderr and leaves stdout empty
+    fn git_message(&self, args: &[&str]) -> Result<String, GitError> {
+        let output = run_git_unchecked(&self.directory, args)?;
+        if !output.status.success() {
+            return Err(command_error(&self.directory, args, &output));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if stdout.is_empty() {
+            Ok(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        } else {
+            Ok(stdout)
+        }
+    }
+}
+
+impl GitBackend for CliBackend {
+    fn status(&self, include_ignored: bool) -> Result<Vec<StatusEntry>, GitError> {
+        let mut args = vec!["status", "--porcelain=v2", "-z", "--untracked-files=all"];
+        if include_ignored {
+            args.push("--ignored=matching");
+        }
+        let output = run_git_unchecked(&self.directory, &args)?;
+        if !output.status.success() {
+            return Err(command_error(&self.directory, &args, &output));
+        }
+
+        let mut entries = parse_porcelain_v2(&output.stdout);
+        for entry in &mut entries {
+            entry.inspect_worktree_file(Path::new(&self.directory));
+        }
+        Ok(entries)
+    }
+
+    fn unstaged_patch(&self, path: &str, settings: &DiffSettings) -> Result<String, GitError> {
+        let command = diff_command("diff", settings);
+        let untracked = self.git(&["ls-files", "--others", "--exclude-standard", "--", path])?;
+        let mut args: Vec<&str> = command.iter().map(String::as_str).collect();
+        if untracked.trim().is_empty() {
+            args.extend(["--", path]);
+            return self.git(&args);
+        }
+
+        // `--no-index` exits with 1 when the files differ
+        args.extend(["--no-index", "/dev/null", path]);
+        let output = run_git_unchecked(&self.directory, &args)?;
+        match output.status.code() {
+            Some(0) | Some(1) => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
+            _ => Err(command_error(&self.directory, &args, &output)),
+        }
+    }
+
+    fn staged_patch(&self, path: &str, settings: &DiffSettings) -> Result<String, GitError> {
+        let command = diff_command("diff", settings);
+        let mut args: Vec<&str> = command.iter().map(String::as_str).collect();
+        args.extend(["--cached", "--", path]);
+        self.git(&args)
+    }
+
+    fn commit_header(&self, revision: &str) -> Result<CommitHeader, GitError> {
+        // NUL-separated, since the body and trailers span lines
+        let format = "--pretty=format:%H%x00%P%x00%s%x00%b%x00%an%x00%ae%x00%aI%x00%at\
+                      %x00%cn%x00%ce%x00%cI%x00%ct%x00%(trailers:only,unfold)";
+        // peeled, or `git show` would print an annotated tag's own header before the commit's
+        let commit = format!("{}^{{commit}}", revision);
+        let output = self.git(&["show", "--no-patch", format, &commit, "--"])?;
+        let fields: Vec<&str> = output.split('\0').collect();
+        let field = |index: usize| fields.get(index).copied().unwrap_or("").trim().to_string();
+        let id = field(0);
+
+        let trailers = field(12)
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .map(|(key, value)| Trailer {
+                key: key.trim().to_string(),
+                value: value.trim().to_string(),
+            })
+            .collect();
+
+        // the signature is the `gpgsig` header, continued on the lines starting with a space
+        let raw = self.git(&["cat-file", "commit", &id])?;
+        let mut signature = String::new();
+        for line in raw.lines().take_while(|line| !line.is_empty()) {
+            if let Some(first) = line.strip_prefix("gpgsig ") {
+                signature.push_str(first);
+            } else if let Some(rest) = line.strip_prefix(' ').filter(|_| !signature.is_empty()) {
+                signature.push('\n');
+                signature.push_str(rest);
+            } else if !signature.is_empty() {
+                break;
+            }
+        }
+
+        let refs = self
+            .git(&["for-each-ref", "--format=%(refname)", &format!("--points-at={}", id)])?
+            .lines()
+            .filter(|name| !name.starts_with(HIDDEN_REFS))
+            .map(str::to_string)
+            .collect();
+
+        Ok(CommitHeader {
+            parents: field(1).split_whitespace().map(str::to_string).collect(),
+            summary: field(2),
+            body: field(3),
+            author: field(4),
+            email: field(5),
+            date: field(6),
+            timestamp: field(7).parse().unwrap_or(0),
+            committer: field(8),
+            committer_email: field(9),
+            committer_date: field(10),
+            committer_timestamp: field(11).parse().unwrap_or(0),
+            trailers,
+            signature: SignatureKind::from_armor(&signature),
+            refs,
+            id,
+        })
+    }
+
+    fn commit_patches(
+        &self,
+        revision: &str,
+        parent: usize,
+        path: Option<&str>,
+        settings: &DiffSettings,
+    ) -> Result<Vec<FilePatch>, GitError> {
+        let commit = format!("{}^{{commit}}", revision);
+        let ids = self.git(&["log", "-1", "--format=%H %P", &commit])?;
+        let ids: Vec<&str> = ids.split_whitespace().collect();
+        let parents = &ids[1..];
+        if parent >= parents.len().max(1) {
+            return Err(GitError::InvalidSelection {
+                message: format!("commit {} has {} parents, there is no parent {}", ids[0], parents.len(), parent),
+            });
+        }
+
+        // a root commit is shown against nothing
+        let command = match parents.get(parent) {
+            Some(_) => diff_command("diff", settings),
+            None => diff_command("show", settings),
+        };
+        let mut args: Vec<&str> = command.iter().map(String::as_str).collect();
+        match parents.get(parent) {
+            Some(parent) => args.extend([*parent, ids[0]]),
+            None => args.extend(["--pretty=format:", ids[0]]),
+        }
+        args.extend(["--find-renames", "--find-copies"]);
+        if let Some(path) = path {
+            args.extend(["--", path]);
+        }
+
+        Ok(split_patches(&self.git(&args)?))
+    }
+
+    fn stage(&self, files: &[String]) -> Result<(), GitError> {
+        let mut args = vec!["add", "--"];
+        args.extend(files.iter().map(String::as_str));
+        self.git(&args)?;
+        Ok(())
+    }
+
+    fn unstage(&self, files: &[String]) -> Result<(), GitError> {
+        // `git reset` needs a commit to reset to; on an unborn branch the entries are just dropped
+        let has_head = run_git_unchecked(&self.directory, &["rev-parse", "--verify", "-q", "HEAD"])?
+            .status
+            .success();
+        let mut args = if has_head {
+            vec!["reset", "-q", "--"]
+        } else {
+            vec!["rm", "--cached", "-q", "-r", "--ignore-unmatch", "--"]
+        };
+        args.extend(files.iter().map(String::as_str));
+        self.git(&args)?;
+        Ok(())
+    }
+
+    fn restore(&self, files: &[String], source: RestoreSource) -> Result<(), GitError> {
+        let mut args = vec!["restore", "--worktree"];
+        if source == RestoreSource::Head {
+            args.push("--source=HEAD");
+        }
+        args.push("--");
+        args.extend(files.iter().map(String::as_str));
+        self.git(&args)?;
+        Ok(())
+    }
+
+    fn remove_untracked(&self, files: &[String]) -> Result<(), GitError> {
+        // `git clean` silently skips tracked files, so refuse them up front like the libgit2 backend
+        let mut args = vec!["ls-files", "--cached", "--"];
+        args.extend(files.iter().map(String::as_str));
+        let tracked: Vec<String> = self.git(&args)?.lines().map(str::to_string).collect();
+        if !tracked.is_empty() {
+            return Err(GitError::InvalidSelection {
+                message: format!("tracked files cannot be deleted as untracked: {}", tracked.join(", ")),
+            });
+        }
+
+        let mut args = vec!["clean", "-f", "-q", "--"];
+        args.extend(files.iter().map(String::as_str));
+        self.git(&args)?;
+        Ok(())
+    }
+
+    fn commit(&self, message: &str) -> Result<String, GitError> {
+        let output = run_git_unchecked(&self.directory, &["commit", "-m", message])?;
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+
+        // git exits with 1 and explains on stdout when the index matches HEAD
+        if !output.status.success() && stdout.contains("nothing") && stdout.contains("to commit") {
+            return Err(GitError::NothingToCommit {
+                message: stdout.trim().to_string(),
+            });
+        }
+        if !output.status.success() {
+            return Err(command_error(&self.directory, &["commit", "-m", message], &output));
+        }
+
+        Ok(stdout.lines().next().unwrap_or("").to_string())
+    }
+
+    fn create_branch(&self, branch_name: &str, options: &NewBranch) -> Result<String, GitError> {
+        let Some(upstream) = &options.upstream else {
+            let mut args = if options.checkout {
+                vec!["checkout", "-b", branch_name]
+            } else {
+                vec!["branch", branch_name]
+            };
+            args.extend(options.start_point.as_deref());
+            return self.git_message(&args);
+        };
+
+        // neither command takes an arbitrary upstream, so it is set between creating and switching
+        let mut args = vec!["branch", "--no-track", branch_name];
+        args.extend(options.start_point.as_deref());
+        self.git(&args)?;
+        let tracking = match self.git_message(&["branch", &format!("--set-upstream-to={}", upstream), branch_name]) {
+            Ok(tracking) => tracking,
+            Err(err) => {
+                self.git(&["branch", "-D", branch_name])?;
+                return Err(err);
+            }
+        };
+        if !options.checkout {
+            return Ok(tracking);
+        }
+        match self.git_message(&["checkout", branch_name]) {
+            Ok(_) => Ok(format!("{}\nSwitched to a new branch '{}'", tracking, branch_name)),
+            Err(err) => {
+                self.git(&["branch", "-D", branch_name])?;
+                Err(err)
+            }
+        }
+    }
+
+    fn switch_branch(&self, branch_name: &str) -> Result<String, GitError> {
+        self.git_message(&["checkout", branch_name])
+    }
+
+    fn delete_branch(&self, branch_name: &str, force: bool) -> Result<String, GitError> {
+        let flag = if force { "-D" } else { "-d" };
+        match self.git_message(&["branch", flag, branch_name]) {
+            Err(GitError::CommandFailed { stderr, .. }) if stderr.contains("not fully merged") => {
+                Err(GitError::BranchNotMerged {
+                    name: branch_name.to_string(),
+                    message: stderr,
+                })
+            }
+            result => result,
+        }
+    }
+
+    fn delete_remote_branch(&self, remote: &str, branch_name: &str) -> Result<String, GitError> {
+        self.git_message(&["push", remote, &format!(":refs/heads/{}", branch_name)])
+    }
+
+    fn rename_remote_branch(&self, remote: &str, old_name: &str, new_name: &str) -> Result<String, GitError> {
+        self.git_message(&[
+            "push",
+            remote,
+            &format!("refs/remotes/{}/{}:refs/heads/{}", remote, old_name, new_name),
+            &format!(":refs/heads/{}", old_name),
+        ])
+    }
+
+    fn merge(&self, branch_name: &str) -> Result<String, GitError> {
+        self.git_message(&["merge", branch_name])
+    }
+
+    fn discard(&self) -> Result<(), GitError> {
+        self.git(&["reset", "--hard"])?;
+        Ok(())
+    }
+
+    fn fetch(&self) -> Result<(), GitError> {
+        self.git(&["fetch"])?;
+        Ok(())
+    }
+
+    fn pull(&self) -> Result<(), GitError> {
+        self.git(&["pull", "--rebase"])?;
+        Ok(())
+    }
+
+    fn push(&self) -> Result<(), GitError> {
+        self.git(&["push"])?;
+        Ok(())
+    }
+}
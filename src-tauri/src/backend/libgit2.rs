@@ -0,0 +1,1237 @@
+// git operations implemented on libgit2, so they work without a `git` binary on PATH
+use super::{
+    CommitHeader, DiffAlgorithm, DiffSettings, FilePatch, GitBackend, NewBranch, RestoreSource, SignatureKind,
+    Trailer, Whitespace, HIDDEN_REFS,
+};
+use crate::error::GitError;
+use crate::status::{ConflictState, FileState, StatusEntry, SubmoduleState};
+use chrono::{DateTime, FixedOffset, SecondsFormat};
+use git2::{
+    build::CheckoutBuilder, AutotagOption, Branch, BranchType, Commit, Cred, CredentialType, Delta,
+    Diff, DiffFindOptions, DiffFormat, DiffOptions, ErrorClass, ErrorCode, FetchOptions,
+    IndexAddOption, MergeAnalysis, Oid, PushOptions, RemoteCallbacks, Repository, ResetType, Status,
+    StatusOptions, SubmoduleIgnore, SubmoduleStatus, Tree,
+};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+// the tree HEAD points at, or None on an unborn branch
+pub fn head_tree(repo: &Repository) -> Result<Option<Tree<'_>>, GitError> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_tree()?)),
+        Err(err) if err.code() == ErrorCode::UnbornBranch => Ok(None),
+        Err(err) if err.code() == ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+// format a git timestamp as ISO 8601 in its own timezone, like `%aI`
+pub fn format_time(time: &git2::Time) -> String {
+    let offset = FixedOffset::east_opt(time.offset_minutes() * 60)
+        .unwrap_or_else(|| FixedOffset::east_opt(0).unwrap());
+    DateTime::from_timestamp(time.seconds(), 0)
+        .map(|date| date.with_timezone(&offset).to_rfc3339_opts(SecondsFormat::Secs, false))
+        .unwrap_or_default()
+}
+
+// the full names of the refs that end up at each commit, annotated tags peeled
+pub fn refs_by_commit(repo: &Repository) -> Result<HashMap<Oid, Vec<String>>, GitError> {
+    let mut refs: HashMap<Oid, Vec<String>> = HashMap::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        let Some(name) = reference.name().filter(|name| !name.starts_with(HIDDEN_REFS)) else {
+            continue;
+        };
+        if let Ok(commit) = reference.peel_to_commit() {
+            refs.entry(commit.id()).or_default().push(name.to_string());
+        }
+    }
+    Ok(refs)
+}
+
+// everything about a commit but its changes; `refs` are the refs pointing at it
+pub fn commit_header(commit: &Commit, refs: Vec<String>) -> CommitHeader {
+    let author = commit.author();
+    let committer = commit.committer();
+    let trailers = git2::message_trailers_strs(commit.message().unwrap_or(""))
+        .map(|trailers| {
+            trailers
+                .iter()
+                .map(|(key, value)| Trailer {
+                    key: key.to_string(),
+                    value: value.to_string(),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let signature = commit
+        .header_field_bytes("gpgsig")
+        .ok()
+        .and_then(|signature| SignatureKind::from_armor(&String::from_utf8_lossy(&signature)));
+
+    CommitHeader {
+        id: commit.id().to_string(),
+        parents: commit.parent_ids().map(|parent| parent.to_string()).collect(),
+        summary: commit.summary().unwrap_or("").to_string(),
+        body: commit.body().unwrap_or("").to_string(),
+        author: author.name().unwrap_or("").to_string(),
+        email: author.email().unwrap_or("").to_string(),
+        date: format_time(&author.when()),
+        timestamp: author.when().seconds(),
+        committer: committer.name().unwrap_or("").to_string(),
+        committer_email: committer.email().unwrap_or("").to_string(),
+        committer_date: format_time(&committer.when()),
+        committer_timestamp: committer.when().seconds(),
+        trailers,
+        signature,
+        refs,
+    }
+}
+
+// the label the UI uses for a diff delta
+pub fn delta_label(delta: Delta) -> &'static str {
+    match delta {
+        Delta::Added => "added",
+        Delta::Deleted => "deleted",
+        Delta::Modified => "modified",
+        Delta::Renamed => "renamed",
+        Delta::Copied => "copied",
+        Delta::Typechange => "typechange",
+        Delta::Untracked => "untracked",
+        Delta::Conflicted => "conflicted",
+        _ => "unknown",
+    }
+}
+
+// the state of one side of a status entry from its libgit2 flags
+fn file_state(
+    status: Status,
+    new: Status,
+    modified: Status,
+    deleted: Status,
+    renamed: Status,
+    typechange: Status,
+) -> FileState {
+    if status.intersects(new) {
+        FileState::Added
+    } else if status.intersects(deleted) {
+        FileState::Deleted
+    } else if status.intersects(renamed) {
+        FileState::Renamed
+    } else if status.intersects(typechange) {
+        FileState::TypeChanged
+    } else if status.intersects(modified) {
+        FileState::Modified
+    } else {
+        FileState::Unmodified
+    }
+}
+
+// which sides of the merge still have a stage for a conflicted path
+fn conflict_state(index: &git2::Index, path: &str) -> Option<ConflictState> {
+    let conflicts = index.conflicts().ok()?;
+    for conflict in conflicts.flatten() {
+        let entry = conflict.our.as_ref().or(conflict.their.as_ref()).or(conflict.ancestor.as_ref())?;
+        if entry.path != path.as_bytes() {
+            continue;
+        }
+
+        return Some(match (conflict.ancestor.is_some(), conflict.our.is_some(), conflict.their.is_some()) {
+            (true, false, false) => ConflictState::BothDeleted,
+            (false, true, false) => ConflictState::AddedByUs,
+            (true, true, false) => ConflictState::DeletedByThem,
+            (false, false, true) => ConflictState::AddedByThem,
+            (true, false, true) => ConflictState::DeletedByUs,
+            (false, true, true) => ConflictState::BothAdded,
+            _ => ConflictState::BothModified,
+        });
+    }
+    None
+}
+
+fn submodule_state(repo: &Repository, path: &str) -> Option<SubmoduleState> {
+    let status = repo.submodule_status(path, SubmoduleIgnore::None).ok()?;
+    Some(SubmoduleState {
+        commit_changed: status.is_wd_modified(),
+        has_tracked_changes: status.intersects(SubmoduleStatus::WD_WD_MODIFIED | SubmoduleStatus::WD_INDEX_MODIFIED),
+        has_untracked_changes: status.is_wd_untracked(),
+    })
+}
+
+fn file_mode(file: git2::DiffFile) -> u32 {
+    if file.id().is_zero() && file.mode() == git2::FileMode::Unreadable {
+        0
+    } else {
+        u32::from(file.mode())
+    }
+}
+
+// the status of every changed, staged, conflicted or untracked file, every libgit2 flag accounted for
+pub fn status_entries(repo: &Repository, include_ignored: bool) -> Result<Vec<StatusEntry>, GitError> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(include_ignored)
+        .recurse_ignored_dirs(include_ignored)
+        .renames_head_to_index(true);
+
+    let statuses = repo.statuses(Some(&mut opts))?;
+    let index = repo.index()?;
+
+    let mut entries = Vec::new();
+    for entry in statuses.iter() {
+        let Some(path) = entry.path() else {
+            continue;
+        };
+        let status = entry.status();
+
+        let staged = file_state(
+            status,
+            Status::INDEX_NEW,
+            Status::INDEX_MODIFIED,
+            Status::INDEX_DELETED,
+            Status::INDEX_RENAMED,
+            Status::INDEX_TYPECHANGE,
+        );
+        let unstaged = if status.is_wt_new() {
+            FileState::Untracked
+        } else if status.is_ignored() {
+            FileState::Ignored
+        } else {
+            file_state(
+                status,
+                Status::empty(),
+                Status::WT_MODIFIED,
+                Status::WT_DELETED,
+                Status::WT_RENAMED,
+                Status::WT_TYPECHANGE,
+            )
+        };
+
+        let mut item = StatusEntry::new(path.to_string(), staged, unstaged);
+
+        if status.is_conflicted() {
+            item.index = FileState::Unmerged;
+            item.worktree = FileState::Unmerged;
+            item.conflict = conflict_state(&index, path).or(Some(ConflictState::BothModified));
+        }
+
+        if let Some(delta) = entry.head_to_index().filter(|delta| delta.status() == Delta::Renamed) {
+            item.path = delta.new_file().path().map(|p| p.to_string_lossy().into_owned()).unwrap_or(item.path);
+            item.old_path = delta.old_file().path().map(|p| p.to_string_lossy().into_owned());
+        }
+
+        let head_to_index = entry.head_to_index();
+        let index_to_workdir = entry.index_to_workdir();
+        let head_mode = head_to_index
+            .as_ref()
+            .map(|delta| file_mode(delta.old_file()))
+            .or_else(|| index_to_workdir.as_ref().map(|delta| file_mode(delta.old_file())))
+            .unwrap_or(0);
+        let index_mode = head_to_index
+            .as_ref()
+            .map(|delta| file_mode(delta.new_file()))
+            .or_else(|| index_to_workdir.as_ref().map(|delta| file_mode(delta.old_file())))
+            .unwrap_or(0);
+        let worktree_mode = index_to_workdir
+            .as_ref()
+            .map(|delta| file_mode(delta.new_file()))
+            .unwrap_or(index_mode);
+        item.set_modes(head_mode, index_mode, worktree_mode);
+
+        if let Some(workdir) = repo.workdir() {
+            item.inspect_worktree_file(workdir);
+        }
+        // a file deleted from the working tree is described by its last staged or committed blob
+        if item.size.is_none() {
+            let blob_id = head_to_index
+                .as_ref()
+                .map(|delta| delta.new_file().id())
+                .filter(|id| !id.is_zero())
+                .or_else(|| head_to_index.as_ref().map(|delta| delta.old_file().id()))
+                .or_else(|| index_to_workdir.as_ref().map(|delta| delta.old_file().id()))
+                .filter(|id| !id.is_zero());
+            if let Some(blob) = blob_id.and_then(|id| repo.find_blob(id).ok()) {
+                item.size = Some(blob.size() as u64);
+                item.binary = Some(blob.is_binary());
+            }
+        }
+
+        let is_submodule = entry
+            .index_to_workdir()
+            .or_else(|| entry.head_to_index())
+            .is_some_and(|delta| delta.new_file().mode() == git2::FileMode::Commit);
+        if is_submodule {
+            item.submodule = submodule_state(repo, &item.path);
+        }
+
+        entries.push(item);
+    }
+
+    Ok(entries)
+}
+
+// diff options for the settings, optionally limited to one path. libgit2 has no histogram
+// algorithm, so asking for it is refused rather than quietly running another one.
+pub fn diff_options(path: Option<&str>, settings: &DiffSettings) -> Result<DiffOptions, GitError> {
+    if settings.algorithm == DiffAlgorithm::Histogram {
+        return Err(GitError::InvalidSelection {
+            message: "the histogram diff algorithm is only available on the git CLI backend".to_string(),
+        });
+    }
+    let mut opts = DiffOptions::new();
+    opts.context_lines(settings.context())
+        .ignore_whitespace(settings.whitespace == Whitespace::IgnoreAll)
+        .ignore_whitespace_change(settings.whitespace == Whitespace::IgnoreChange)
+        .ignore_whitespace_eol(settings.whitespace == Whitespace::IgnoreAtEol)
+        .ignore_blank_lines(settings.ignore_blank_lines)
+        .minimal(settings.algorithm == DiffAlgorithm::Minimal)
+        .patience(settings.algorithm == DiffAlgorithm::Patience)
+        .max_size(i64::try_from(settings.max_file_size()).unwrap_or(i64::MAX));
+    if let Some(path) = path {
+        opts.pathspec(path).disable_pathspec_match(true);
+    }
+    Ok(opts)
+}
+
+// the diff between HEAD and the index, optionally limited to one path
+pub fn staged_diff<'repo>(
+    repo: &'repo Repository,
+    path: Option<&str>,
+    settings: &DiffSettings,
+) -> Result<Diff<'repo>, GitError> {
+    let tree = head_tree(repo)?;
+    let mut opts = diff_options(path, settings)?;
+    Ok(repo.diff_tree_to_index(tree.as_ref(), None, Some(&mut opts))?)
+}
+
+// the diff between the index and the working tree, untracked files shown in full
+pub fn unstaged_diff<'repo>(
+    repo: &'repo Repository,
+    path: Option<&str>,
+    settings: &DiffSettings,
+) -> Result<Diff<'repo>, GitError> {
+    let mut opts = diff_options(path, settings)?;
+    opts.include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .show_untracked_content(true);
+    Ok(repo.diff_index_to_workdir(None, Some(&mut opts))?)
+}
+
+// the tree of one of a commit's parents, an index into its parent list; None for a root commit
+pub fn parent_tree<'repo>(commit: &Commit<'repo>, parent: usize) -> Result<Option<Tree<'repo>>, GitError> {
+    match commit.parent_count() {
+        0 if parent == 0 => Ok(None),
+        count if parent < count => Ok(Some(commit.parent(parent)?.tree()?)),
+        count => Err(GitError::InvalidSelection {
+            message: format!("commit {} has {} parents, there is no parent {}", commit.id(), count, parent),
+        }),
+    }
+}
+
+// the diff a commit introduces against one of its parents, or against nothing for a root commit,
+// with renames and copies detected
+pub fn commit_diff<'repo>(
+    repo: &'repo Repository,
+    commit: &Commit<'repo>,
+    parent: usize,
+    path: Option<&str>,
+    settings: &DiffSettings,
+) -> Result<Diff<'repo>, GitError> {
+    let parent_tree = parent_tree(commit, parent)?;
+    let mut opts = diff_options(path, settings)?;
+    let mut diff = repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&commit.tree()?), Some(&mut opts))?;
+    diff.find_similar(Some(DiffFindOptions::new().renames(true).copies(true)))?;
+    Ok(diff)
+}
+
+// how much of the old file survives in the new one, as a percentage. libgit2 keeps its own score
+// private, so this is git's measure taken from the patch: the bytes of the old side that were not
+// deleted, over the size of the larger side.
+pub fn similarity(patch: &git2::Patch) -> Result<u32, GitError> {
+    let delta = patch.delta();
+    let (old_file, new_file) = (delta.old_file(), delta.new_file());
+    if old_file.id() == new_file.id() {
+        return Ok(100);
+    }
+    let larger = old_file.size().max(new_file.size());
+    if larger == 0 || delta.flags().is_binary() {
+        return Ok(0);
+    }
+
+    let mut deleted = 0;
+    for hunk in 0..patch.num_hunks() {
+        for line in 0..patch.num_lines_in_hunk(hunk)? {
+            let line = patch.line_in_hunk(hunk, line)?;
+            if line.origin() == '-' {
+                deleted += line.content().len() as u64;
+            }
+        }
+    }
+    Ok((old_file.size().saturating_sub(deleted) * 100 / larger) as u32)
+}
+
+// render a diff as unified patch text, like `git diff` prints it
+pub fn patch_text(diff: &Diff) -> Result<String, GitError> {
+    let mut text = String::new();
+    diff.print(DiffFormat::Patch, |_, _, line| {
+        if let origin @ ('+' | '-' | ' ') = line.origin() {
+            text.push(origin);
+        }
+        text.push_str(&String::from_utf8_lossy(line.content()));
+        true
+    })?;
+    Ok(text)
+}
+
+// the patch of every file in a diff
+pub fn file_patches(diff: &Diff) -> Result<Vec<FilePatch>, GitError> {
+    let mut patches = Vec::new();
+    for index in 0..diff.deltas().len() {
+        let Some(mut patch) = git2::Patch::from_diff(diff, index)? else {
+            continue;
+        };
+        let delta = patch.delta();
+        let path = delta
+            .new_file()
+            .path()
+            .or_else(|| delta.old_file().path())
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let status = delta_label(delta.status());
+        let text = String::from_utf8_lossy(&patch.to_buf()?).into_owned();
+        patches.push(FilePatch {
+            path,
+            status,
+            patch: text,
+        });
+    }
+    Ok(patches)
+}
+
+// stage files like `git add`, including deletions of tracked files
+pub fn stage_files(repo: &Repository, files: &[String]) -> Result<(), GitError> {
+    let mut index = repo.index()?;
+    index.add_all(files, IndexAddOption::DEFAULT, None)?;
+    index.update_all(files, None)?;
+    index.write()?;
+    Ok(())
+}
+
+// reset index entries to HEAD like `git reset -- <files>`, dropping them on an unborn branch
+pub fn unstage_files(repo: &Repository, files: &[String]) -> Result<(), GitError> {
+    let head = match repo.head() {
+        Ok(head) => Some(head.peel(git2::ObjectType::Commit)?),
+        Err(err) if err.code() == ErrorCode::UnbornBranch => None,
+        Err(err) => return Err(err.into()),
+    };
+    repo.reset_default(head.as_ref(), files)?;
+    Ok(())
+}
+
+// overwrite working tree files from the index or HEAD like `git restore [--source=HEAD] -- <files>`;
+// a file HEAD does not have is deleted, one missing from the source entirely is an error
+pub fn restore_files(repo: &Repository, files: &[String], source: RestoreSource) -> Result<(), GitError> {
+    let workdir = repo.workdir().ok_or_else(|| GitError::Internal {
+        message: "cannot restore files in a bare repository".to_string(),
+    })?;
+    let index = repo.index()?;
+    let tree = head_tree(repo)?;
+
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force().disable_pathspec_match(true);
+    let mut to_checkout = 0;
+    let mut to_delete = Vec::new();
+    for file in files {
+        let path = Path::new(file);
+        let in_index = index.get_path(path, 0).is_some();
+        let in_source = match source {
+            RestoreSource::Index => in_index,
+            RestoreSource::Head => tree.as_ref().is_some_and(|tree| tree.get_path(path).is_ok()),
+        };
+
+        if in_source {
+            checkout.path(file);
+            to_checkout += 1;
+        } else if source == RestoreSource::Head && in_index {
+            to_delete.push(workdir.join(path));
+        } else {
+            return Err(GitError::RefNotFound {
+                name: file.clone(),
+                message: format!("pathspec '{}' did not match any file(s) known to git", file),
+            });
+        }
+    }
+
+    if to_checkout > 0 {
+        match (source, &tree) {
+            (RestoreSource::Head, Some(tree)) => {
+                checkout.update_index(false);
+                repo.checkout_tree(tree.as_object(), Some(&mut checkout))?;
+            }
+            _ => repo.checkout_index(None, Some(&mut checkout))?,
+        }
+    }
+    for path in to_delete {
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// delete untracked files like `git clean -f -- <files>`, skipping ignored and missing ones
+pub fn remove_untracked_files(repo: &Repository, files: &[String]) -> Result<(), GitError> {
+    let workdir = repo.workdir().ok_or_else(|| GitError::Internal {
+        message: "cannot clean a bare repository".to_string(),
+    })?;
+    let index = repo.index()?;
+
+    let tracked: Vec<String> = files
+        .iter()
+        .filter(|file| index.get_path(Path::new(file.as_str()), 0).is_some())
+        .cloned()
+        .collect();
+    if !tracked.is_empty() {
+        return Err(GitError::InvalidSelection {
+            message: format!("tracked files cannot be deleted as untracked: {}", tracked.join(", ")),
+        });
+    }
+
+    for file in files {
+        let untracked = match repo.status_file(Path::new(file)) {
+            Ok(status) => status.contains(Status::WT_NEW),
+            Err(err) if err.code() == ErrorCode::NotFound => false,
+            Err(err) => return Err(err.into()),
+        };
+        if untracked {
+            fs::remove_file(workdir.join(file))?;
+        }
+    }
+    Ok(())
+}
+
+// commit the index on top of HEAD and return a `git commit`-style summary
+pub fn commit_index(repo: &Repository, message: &str) -> Result<String, GitError> {
+    let mut index = repo.index()?;
+    if index.has_conflicts() {
+        return Err(GitError::Conflict {
+            files: conflicted_paths(&index)?,
+            stderr: "Committing is not possible because you have unmerged files.".to_string(),
+        });
+    }
+
+    let tree_id = index.write_tree()?;
+    let tree = repo.find_tree(tree_id)?;
+    let parent = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(err) if matches!(err.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    // a merge left to finish by hand records the merged commits as further parents
+    let merged = merge_heads(repo)?
+        .into_iter()
+        .map(|oid| repo.find_commit(oid))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // a merge may keep HEAD's tree, as when every conflict is resolved in favour of HEAD
+    if merged.is_empty() && parent.as_ref().map(|parent| parent.tree_id()) == Some(tree_id) {
+        return Err(GitError::NothingToCommit {
+            message: "nothing added to commit".to_string(),
+        });
+    }
+
+    let signature = repo.signature()?;
+    let parents: Vec<&Commit> = parent.iter().chain(&merged).collect();
+    let oid = repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+    repo.cleanup_state()?;
+
+    let summary = message.lines().next().unwrap_or("");
+    Ok(format!("[{} {}] {}", current_branch_name(repo), short_id(oid), summary))
+}
+
+// the commits in MERGE_HEAD, none outside a merge
+fn merge_heads(repo: &Repository) -> Result<Vec<Oid>, GitError> {
+    // `mergehead_foreach` needs a mutable handle, so it gets one of its own
+    let mut repo = Repository::open(repo.path())?;
+    let mut heads = Vec::new();
+    match repo.mergehead_foreach(|oid| {
+        heads.push(*oid);
+        true
+    }) {
+        Ok(()) => Ok(heads),
+        Err(err) if err.code() == ErrorCode::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn conflicted_paths(index: &git2::Index) -> Result<Vec<String>, GitError> {
+    let mut paths = Vec::new();
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+        if let Some(entry) = entry {
+            paths.push(String::from_utf8_lossy(&entry.path).into_owned());
+        }
+    }
+    Ok(paths)
+}
+
+fn short_id(oid: Oid) -> String {
+    oid.to_string()[..7].to_string()
+}
+
+fn current_branch_name(repo: &Repository) -> String {
+    repo.head()
+        .ok()
+        .and_then(|head| head.shorthand().map(str::to_string))
+        .unwrap_or_else(|| "HEAD".to_string())
+}
+
+// the upstream `git branch` sets up by itself: a remote branch start point, or any branch with
+// `branch.autoSetupMerge=always`
+fn auto_upstream(repo: &Repository, start_point: &str) -> Option<String> {
+    let config = repo.config().ok()?;
+    let always = config.get_string("branch.autoSetupMerge").is_ok_and(|value| value == "always");
+    if !always && !config.get_bool("branch.autoSetupMerge").unwrap_or(true) {
+        return None;
+    }
+
+    let branch = match repo.find_branch(start_point, BranchType::Remote) {
+        Ok(branch) => branch,
+        Err(_) if always => repo.find_branch(start_point, BranchType::Local).ok()?,
+        Err(_) => return None,
+    };
+    branch.name().ok().flatten().map(str::to_string)
+}
+
+// create a branch at a start point, HEAD by default, and switch to it unless told not to, like
+// `git checkout -b <name> <start>` or `git branch <name> <start>`
+pub fn create_branch(repo: &Repository, branch_name: &str, options: &NewBranch) -> Result<String, GitError> {
+    let start_point = options.start_point.as_deref().unwrap_or("HEAD");
+    let commit = repo
+        .revparse_single(start_point)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|err| GitError::ref_not_found(start_point, err))?;
+    let upstream = match &options.upstream {
+        Some(upstream) => Some(upstream.clone()),
+        None => auto_upstream(repo, start_point),
+    };
+
+    let mut branch = repo.branch(branch_name, &commit, false)?;
+    match set_up_branch(repo, &mut branch, &commit, upstream.as_deref(), options.checkout) {
+        Ok(message) => Ok(message),
+        // git leaves no branch behind when it cannot be set up or checked out
+        Err(err) => {
+            branch.delete()?;
+            Err(err)
+        }
+    }
+}
+
+// track the upstream and switch to a branch just created, reporting each step like git
+fn set_up_branch(
+    repo: &Repository,
+    branch: &mut Branch,
+    commit: &Commit,
+    upstream: Option<&str>,
+    checkout: bool,
+) -> Result<String, GitError> {
+    let name = branch.name()?.unwrap_or_default().to_string();
+    let mut messages = Vec::new();
+    if let Some(upstream) = upstream {
+        branch.set_upstream(Some(upstream))?;
+        messages.push(format!("branch '{}' set up to track '{}'.", name, upstream));
+    }
+    if checkout {
+        checkout_tree(repo, commit)?;
+        repo.set_head(branch.get().name().unwrap_or_default())?;
+        messages.push(format!("Switched to a new branch '{}'", name));
+    }
+    Ok(messages.join("\n"))
+}
+
+// what `git checkout <name>` switches to
+pub enum CheckoutTarget<'r> {
+    Local(Branch<'r>),
+    // a remote branch only one remote has under that name, to be tracked by a new local branch
+    Remote(Branch<'r>),
+    Detached(Commit<'r>),
+}
+
+impl<'r> CheckoutTarget<'r> {
+    pub fn commit(&self) -> Result<Commit<'r>, GitError> {
+        match self {
+            CheckoutTarget::Local(branch) | CheckoutTarget::Remote(branch) => Ok(branch.get().peel_to_commit()?),
+            CheckoutTarget::Detached(commit) => Ok(commit.clone()),
+        }
+    }
+}
+
+// a local branch, then a tag, commit or any other revision, then a remote branch
+pub fn checkout_target<'r>(repo: &'r Repository, name: &str) -> Result<CheckoutTarget<'r>, GitError> {
+    if let Ok(branch) = repo.find_branch(name, BranchType::Local) {
+        return Ok(CheckoutTarget::Local(branch));
+    }
+
+    let revision = repo.revparse_single(name).and_then(|object| object.peel_to_commit());
+    let err = match revision {
+        Ok(commit) => return Ok(CheckoutTarget::Detached(commit)),
+        Err(err) => err,
+    };
+
+    // `git checkout <name>` creates a tracking branch when exactly one remote has it
+    let mut candidates = Vec::new();
+    for branch in repo.branches(Some(BranchType::Remote))? {
+        let (branch, _) = branch?;
+        if let Some(branch_name) = branch.name()? {
+            if branch_name.split_once('/').map(|(_, rest)| rest) == Some(name) {
+                candidates.push(branch);
+            }
+        }
+    }
+    if candidates.len() == 1 {
+        return Ok(CheckoutTarget::Remote(candidates.remove(0)));
+    }
+    Err(GitError::ref_not_found(name, err))
+}
+
+// point HEAD at a target whose tree is already checked out, reporting it like git
+pub fn switch_head(repo: &Repository, name: &str, target: CheckoutTarget) -> Result<String, GitError> {
+    match target {
+        CheckoutTarget::Local(branch) => {
+            repo.set_head(branch.get().name().unwrap_or_default())?;
+            Ok(format!("Switched to branch '{}'", name))
+        }
+        CheckoutTarget::Remote(remote_branch) => {
+            let upstream = remote_branch.name()?.unwrap_or_default().to_string();
+            let commit = remote_branch.get().peel_to_commit()?;
+            let mut branch = repo.branch(name, &commit, false)?;
+            branch.set_upstream(Some(&upstream))?;
+            repo.set_head(branch.get().name().unwrap_or_default())?;
+            Ok(format!(
+                "branch '{}' set up to track '{}'.\nSwitched to a new branch '{}'",
+                name, upstream, name
+            ))
+        }
+        CheckoutTarget::Detached(commit) => {
+            repo.set_head_detached(commit.id())?;
+            Ok(format!("HEAD is now at {} {}", short_id(commit.id()), commit.summary().unwrap_or("")))
+        }
+    }
+}
+
+// switch to a local branch, a remote branch (creating a tracking branch) or a commit
+pub fn switch_branch(repo: &Repository, branch_name: &str) -> Result<String, GitError> {
+    let target = checkout_target(repo, branch_name)?;
+    checkout_tree(repo, &target.commit()?)?;
+    switch_head(repo, branch_name, target)
+}
+
+// update the index and working tree to a commit, refusing to overwrite local changes
+pub fn checkout_tree(repo: &Repository, commit: &Commit) -> Result<(), GitError> {
+    let mut checkout = CheckoutBuilder::new();
+    checkout.safe();
+    repo.checkout_tree(commit.as_object(), Some(&mut checkout))?;
+    Ok(())
+}
+
+// where a branch's tip is already merged: its upstream's short name, or "HEAD"
+pub fn merged_into(repo: &Repository, branch: &Branch) -> Result<Option<String>, GitError> {
+    let tip = branch.get().peel_to_commit()?.id();
+    let contains = |target: Option<Oid>| -> bool {
+        target.is_some_and(|target| target == tip || repo.graph_descendant_of(target, tip).unwrap_or(false))
+    };
+
+    if let Ok(upstream) = branch.upstream() {
+        if contains(upstream.get().target()) {
+            return Ok(upstream.name()?.map(str::to_string));
+        }
+    }
+    if contains(repo.head().ok().and_then(|head| head.target())) {
+        return Ok(Some("HEAD".to_string()));
+    }
+    Ok(None)
+}
+
+// delete a local branch, refusing when it is not merged into HEAD or its upstream unless forced
+pub fn delete_branch(repo: &Repository, branch_name: &str, force: bool) -> Result<String, GitError> {
+    let mut branch = repo
+        .find_branch(branch_name, BranchType::Local)
+        .map_err(|err| GitError::ref_not_found(branch_name, err))?;
+    let tip = branch.get().peel_to_commit()?.id();
+
+    if !force && merged_into(repo, &branch)?.is_none() {
+        return Err(GitError::BranchNotMerged {
+            name: branch_name.to_string(),
+            message: format!("The branch '{}' is not fully merged.", branch_name),
+        });
+    }
+
+    branch.delete()?;
+    Ok(format!("Deleted branch {} (was {}).", branch_name, short_id(tip)))
+}
+
+// push refspecs to a remote as they are, failing on any the remote rejects; returns its url
+fn push_refspecs(repo: &Repository, remote_name: &str, refspecs: &[String]) -> Result<String, GitError> {
+    let mut remote = repo
+        .find_remote(remote_name)
+        .map_err(|err| GitError::ref_not_found(remote_name, err))?;
+
+    let mut rejection = None;
+    {
+        let mut callbacks = remote_callbacks(repo);
+        callbacks.push_update_reference(|name, status| {
+            if let Some(status) = status {
+                rejection = Some(format!("! [remote rejected] {} ({})", name, status));
+            }
+            Ok(())
+        });
+        let mut opts = PushOptions::new();
+        opts.remote_callbacks(callbacks);
+        remote.push(refspecs, Some(&mut opts))?;
+    }
+    if let Some(message) = rejection {
+        return Err(git2::Error::new(ErrorCode::GenericError, ErrorClass::Reference, message).into());
+    }
+    Ok(remote.url().unwrap_or(remote_name).to_string())
+}
+
+// delete a branch on a remote with an empty-source refspec, like `git push <remote> :<branch>`
+pub fn delete_remote_branch(repo: &Repository, remote_name: &str, branch_name: &str) -> Result<String, GitError> {
+    let url = push_refspecs(repo, remote_name, &[format!(":refs/heads/{}", branch_name)])?;
+
+    // `git push` drops the remote-tracking branch along with the remote one
+    if let Ok(mut tracking) = repo.find_reference(&format!("refs/remotes/{}/{}", remote_name, branch_name)) {
+        tracking.delete()?;
+    }
+    Ok(format!("To {}\n - [deleted]         {}", url, branch_name))
+}
+
+// rename a branch on a remote: push its tip under the new name and delete the old one in one go,
+// then move the remote-tracking branch to match
+pub fn rename_remote_branch(
+    repo: &Repository,
+    remote_name: &str,
+    old_name: &str,
+    new_name: &str,
+) -> Result<String, GitError> {
+    let tracking = format!("refs/remotes/{}/{}", remote_name, old_name);
+    let tip = repo
+        .refname_to_id(&tracking)
+        .map_err(|err| GitError::ref_not_found(&tracking, err))?;
+    let refspecs = [
+        format!("{}:refs/heads/{}", tracking, new_name),
+        format!(":refs/heads/{}", old_name),
+    ];
+    let url = push_refspecs(repo, remote_name, &refspecs)?;
+
+    let renamed = format!("refs/remotes/{}/{}", remote_name, new_name);
+    repo.reference(&renamed, tip, true, "push: renamed on the remote")?;
+    if let Ok(mut tracking) = repo.find_reference(&tracking) {
+        tracking.delete()?;
+    }
+    Ok(format!(
+        "To {}\n * [new branch]      {}/{} -> {}\n - [deleted]         {}",
+        url, remote_name, old_name, new_name, old_name
+    ))
+}
+
+// merge a branch into HEAD: fast-forward when possible, otherwise create a merge commit
+pub fn merge_branch(repo: &Repository, branch_name: &str) -> Result<String, GitError> {
+    let their_ref = repo
+        .resolve_reference_from_short_name(branch_name)
+        .map_err(|err| GitError::ref_not_found(branch_name, err))?;
+    let their_commit = repo.reference_to_annotated_commit(&their_ref)?;
+    let (analysis, _) = repo.merge_analysis(&[&their_commit])?;
+
+    if analysis.contains(MergeAnalysis::ANALYSIS_UP_TO_DATE) {
+        return Ok("Already up to date.".to_string());
+    }
+
+    if analysis.contains(MergeAnalysis::ANALYSIS_FASTFORWARD) {
+        let target = repo.find_commit(their_commit.id())?;
+        checkout_tree(repo, &target)?;
+        let mut head = repo.head()?;
+        head.set_target(target.id(), &format!("merge {}: Fast-forward", branch_name))?;
+        return Ok("Fast-forward".to_string());
+    }
+
+    let mut checkout = CheckoutBuilder::new();
+    checkout.safe().allow_conflicts(true).conflict_style_merge(true);
+    repo.merge(&[&their_commit], None, Some(&mut checkout))?;
+
+    let mut index = repo.index()?;
+    if index.has_conflicts() {
+        return Err(GitError::Conflict {
+            files: conflicted_paths(&index)?,
+            stderr: "Automatic merge failed; fix conflicts and then commit the result.".to_string(),
+        });
+    }
+
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let head = repo.head()?.peel_to_commit()?;
+    let theirs = repo.find_commit(their_commit.id())?;
+    let signature = repo.signature()?;
+    let message = format!("Merge branch '{}'", branch_name);
+    repo.commit(Some("HEAD"), &signature, &signature, &message, &tree, &[&head, &theirs])?;
+    repo.cleanup_state()?;
+
+    Ok(message)
+}
+
+// reset the index and working tree to HEAD, like `git reset --hard`
+pub fn reset_hard(repo: &Repository) -> Result<(), GitError> {
+    let head = repo.head()?.peel_to_commit()?;
+    repo.reset(head.as_object(), ResetType::Hard, None)?;
+    Ok(())
+}
+
+// credentials from the ssh agent or the configured credential helper, giving up after a few tries
+fn remote_callbacks(repo: &Repository) -> RemoteCallbacks<'static> {
+    let config = repo.config().ok();
+    let mut attempts = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        attempts += 1;
+        if attempts > 3 {
+            return Err(git2::Error::new(ErrorCode::Auth, ErrorClass::Net, "Authentication failed"));
+        }
+        let username = username.unwrap_or("git");
+        if allowed.contains(CredentialType::SSH_KEY) {
+            return Cred::ssh_key_from_agent(username);
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if let Some(config) = &config {
+                return Cred::credential_helper(config, url, Some(username));
+            }
+        }
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+        Cred::default()
+    });
+    callbacks
+}
+
+// the checked out branch, which pull and push need
+fn head_branch(repo: &Repository) -> Result<Branch<'_>, GitError> {
+    let head = repo.head()?;
+    if !head.is_branch() {
+        return Err(GitError::RefNotFound {
+            name: "HEAD".to_string(),
+            message: "You are not currently on a branch.".to_string(),
+        });
+    }
+    Ok(Branch::wrap(head))
+}
+
+fn no_upstream(branch: &Branch) -> GitError {
+    let name = branch.name().ok().flatten().unwrap_or("HEAD").to_string();
+    GitError::RefNotFound {
+        message: format!("The current branch {} has no upstream branch.", name),
+        name,
+    }
+}
+
+// the remote the current branch tracks, origin otherwise
+fn tracking_remote(repo: &Repository) -> String {
+    repo.head()
+        .ok()
+        .and_then(|head| head.name().map(str::to_string))
+        .and_then(|refname| repo.branch_upstream_remote(&refname).ok())
+        .and_then(|remote| remote.as_str().map(str::to_string))
+        .unwrap_or_else(|| "origin".to_string())
+}
+
+// fetch the tracked remote with its configured refspecs, like `git fetch`
+pub fn fetch_remote(repo: &Repository) -> Result<(), GitError> {
+    let remote_name = tracking_remote(repo);
+    let mut remote = repo
+        .find_remote(&remote_name)
+        .map_err(|err| GitError::ref_not_found(&remote_name, err))?;
+
+    let mut opts = FetchOptions::new();
+    opts.remote_callbacks(remote_callbacks(repo))
+        .download_tags(AutotagOption::Auto);
+    remote.fetch::<&str>(&[], Some(&mut opts), None)?;
+    Ok(())
+}
+
+// push the current branch to its upstream, like `git push`
+pub fn push_branch(repo: &Repository) -> Result<(), GitError> {
+    let branch = head_branch(repo)?;
+    let refname = branch.get().name().unwrap_or_default().to_string();
+    let branch_name = branch.name()?.unwrap_or_default().to_string();
+    let remote_name = repo.branch_upstream_remote(&refname).map_err(|_| no_upstream(&branch))?;
+    let remote_name = remote_name.as_str().unwrap_or("origin");
+    let merge_ref = repo
+        .config()?
+        .get_string(&format!("branch.{}.merge", branch_name))
+        .map_err(|_| no_upstream(&branch))?;
+    let refspec = format!("{}:{}", refname, merge_ref);
+
+    let mut remote = repo
+        .find_remote(remote_name)
+        .map_err(|err| GitError::ref_not_found(remote_name, err))?;
+
+    let mut rejection = None;
+    {
+        let mut callbacks = remote_callbacks(repo);
+        callbacks.push_update_reference(|name, status| {
+            if let Some(status) = status {
+                rejection = Some(format!("! [rejected] {} ({})", name, status));
+            }
+            Ok(())
+        });
+        let mut opts = PushOptions::new();
+        opts.remote_callbacks(callbacks);
+        remote.push(&[refspec.as_str()], Some(&mut opts))?;
+    }
+
+    match rejection {
+        Some(stderr) => Err(GitError::NonFastForward { stderr }),
+        None => Ok(()),
+    }
+}
+
+// the tracked files with uncommitted changes
+pub fn dirty_files(repo: &Repository) -> Result<Vec<String>, GitError> {
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(false).include_ignored(false);
+    let statuses = repo.statuses(Some(&mut opts))?;
+    Ok(statuses
+        .iter()
+        .filter_map(|entry| entry.path().map(str::to_string))
+        .collect())
+}
+
+// fetch and replay local commits on top of the upstream, like `git pull --rebase`
+pub fn pull_rebase(repo: &Repository) -> Result<(), GitError> {
+    fetch_remote(repo)?;
+
+    let branch = head_branch(repo)?;
+    let upstream = branch.upstream().map_err(|_| no_upstream(&branch))?;
+
+    let files = dirty_files(repo)?;
+    if !files.is_empty() {
+        return Err(GitError::DirtyWorktree {
+            files,
+            stderr: "cannot pull with rebase: You have unstaged changes.".to_string(),
+        });
+    }
+
+    let upstream_commit = repo.reference_to_annotated_commit(upstream.get())?;
+    let (analysis, _) = repo.merge_analysis(&[&upstream_commit])?;
+
+    if analysis.contains(MergeAnalysis::ANALYSIS_UP_TO_DATE) {
+        return Ok(());
+    }
+
+    if analysis.contains(MergeAnalysis::ANALYSIS_FASTFORWARD) {
+        let target = repo.find_commit(upstream_commit.id())?;
+        checkout_tree(repo, &target)?;
+        repo.head()?.set_target(target.id(), "pull: Fast-forward")?;
+        return Ok(());
+    }
+
+    let branch_commit = repo.reference_to_annotated_commit(branch.get())?;
+    let mut rebase = repo.rebase(Some(&branch_commit), Some(&upstream_commit), None, None)?;
+    let signature = repo.signature()?;
+
+    while let Some(operation) = rebase.next() {
+        operation?;
+        let index = repo.index()?;
+        if index.has_conflicts() {
+            // like git, the rebase stays in progress so it can be resolved or aborted
+            return Err(GitError::Conflict {
+                files: conflicted_paths(&index)?,
+                stderr: "Could not apply commit while rebasing onto upstream.".to_string(),
+            });
+        }
+        match rebase.commit(None, &signature, None) {
+            // the change is already upstream, nothing to replay
+            Err(err) if err.code() == ErrorCode::Applied => {}
+            result => {
+                result?;
+            }
+        }
+    }
+    rebase.finish(Some(&signature))?;
+
+    Ok(())
+}
+
+pub struct Git2Backend {
+    repo: Repository,
+}
+
+impl Git2Backend {
+    pub fn new(repo: Repository) -> Self {
+        Git2Backend { repo }
+    }
+}
+
+impl GitBackend for Git2Backend {
+    fn status(&self, include_ignored: bool) -> Result<Vec<StatusEntry>, GitError> {
+        status_entries(&self.repo, include_ignored)
+    }
+
+    fn unstaged_patch(&self, path: &str, settings: &DiffSettings) -> Result<String, GitError> {
+        patch_text(&unstaged_diff(&self.repo, Some(path), settings)?)
+    }
+
+    fn staged_patch(&self, path: &str, settings: &DiffSettings) -> Result<String, GitError> {
+        patch_text(&staged_diff(&self.repo, Some(path), settings)?)
+    }
+
+    fn commit_header(&self, revision: &str) -> Result<CommitHeader, GitError> {
+        let commit = crate::gitfunction::find_commit(&self.repo, revision)?;
+        let refs = refs_by_commit(&self.repo)?.remove(&commit.id()).unwrap_or_default();
+        Ok(commit_header(&commit, refs))
+    }
+
+    fn commit_patches(
+        &self,
+        revision: &str,
+        parent: usize,
+        path: Option<&str>,
+        settings: &DiffSettings,
+    ) -> Result<Vec<FilePatch>, GitError> {
+        let commit = crate::gitfunction::find_commit(&self.repo, revision)?;
+        file_patches(&commit_diff(&self.repo, &commit, parent, path, settings)?)
+    }
+
+    fn stage(&self, files: &[String]) -> Result<(), GitError> {
+        stage_files(&self.repo, files)
+    }
+
+    fn unstage(&self, files: &[String]) -> Result<(), GitError> {
+        unstage_files(&self.repo, files)
+    }
+
+    fn restore(&self, files: &[String], source: RestoreSource) -> Result<(), GitError> {
+        restore_files(&self.repo, files, source)
+    }
+
+    fn remove_untracked(&self, files: &[String]) -> Result<(), GitError> {
+        remove_untracked_files(&self.repo, files)
+    }
+
+    fn commit(&self, message: &str) -> Result<String, GitError> {
+        commit_index(&self.repo, message)
+    }
+
+    fn create_branch(&self, branch_name: &str, options: &NewBranch) -> Result<String, GitError> {
+        create_branch(&self.repo, branch_name, options)
+    }
+
+    fn switch_branch(&self, branch_name: &str) -> Result<String, GitError> {
+        switch_branch(&self.repo, branch_name)
+    }
+
+    fn delete_branch(&self, branch_name: &str, force: bool) -> Result<String, GitError> {
+        delete_branch(&self.repo, branch_name, force)
+    }
+
+    fn delete_remote_branch(&self, remote: &str, branch_name: &str) -> Result<String, GitError> {
+        delete_remote_branch(&self.repo, remote, branch_name)
+    }
+
+    fn rename_remote_branch(&self, remote: &str, old_name: &str, new_name: &str) -> Result<String, GitError> {
+        rename_remote_branch(&self.repo, remote, old_name, new_name)
+    }
+
+    fn merge(&self, branch_name: &str) -> Result<String, GitError> {
+        merge_branch(&self.repo, branch_name)
+    }
+
+    fn discard(&self) -> Result<(), GitError> {
+        reset_hard(&self.repo)
+    }
+
+    fn fetch(&self) -> Result<(), GitError> {
+        fetch_remote(&self.repo)
+    }
+
+    fn pull(&self) -> Result<(), GitError> {
+        pull_rebase(&self.repo)
+    }
+
+    fn push(&self) -> Result<(), GitError> {
+        push_branch(&self.repo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    #[test]
+    fn commits_a_resolved_merge_with_both_parents() {
+        let test = TestRepo::new();
+        test.commit_file("file", "base\n", "base");
+        test.git(&["checkout", "-q", "-b", "feature"]);
+        let theirs = test.commit_file("file", "feature\n", "feature");
+        test.git(&["checkout", "-q", "main"]);
+        let ours = test.commit_file("file", "main\n", "main");
+        assert!(!test.git_unchecked(&["merge", "feature"]).status.success());
+
+        test.write("file", "resolved\n");
+        test.git(&["add", "file"]);
+        let repo = Repository::open(test.path()).unwrap();
+        commit_index(&repo, "Merge branch 'feature'").unwrap();
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.parent_ids().collect::<Vec<_>>(), [ours, theirs]);
+        assert_eq!(repo.state(), git2::RepositoryState::Clean);
+    }
+
+    #[test]
+    fn commits_a_merge_that_keeps_the_tree_of_head() {
+        let test = TestRepo::new();
+        test.commit_file("file", "base\n", "base");
+        test.git(&["checkout", "-q", "-b", "feature"]);
+        test.commit_file("file", "feature\n", "feature");
+        test.git(&["checkout", "-q", "main"]);
+        test.commit_file("file", "main\n", "main");
+        assert!(!test.git_unchecked(&["merge", "feature"]).status.success());
+
+        test.git(&["checkout", "--ours", "file"]);
+        test.git(&["add", "file"]);
+        let repo = Repository::open(test.path()).unwrap();
+        commit_index(&repo, "Merge branch 'feature'").unwrap();
+        assert_eq!(repo.head().unwrap().peel_to_commit().unwrap().parent_count(), 2);
+    }
+
+    #[test]
+    fn refuses_to_commit_unresolved_conflicts() {
+        let test = TestRepo::new();
+        test.commit_file("file", "base\n", "base");
+        test.git(&["checkout", "-q", "-b", "feature"]);
+        test.commit_file("file", "feature\n", "feature");
+        test.git(&["checkout", "-q", "main"]);
+        test.commit_file("file", "main\n", "main");
+        assert!(!test.git_unchecked(&["merge", "feature"]).status.success());
+
+        let repo = Repository::open(test.path()).unwrap();
+        let err = commit_index(&repo, "Merge branch 'feature'").unwrap_err();
+        assert!(matches!(err, GitError::Conflict { files, .. } if files == ["file"]));
+    }
+
+    #[test]
+    fn makes_the_first_commit_without_a_parent() {
+        let test = TestRepo::new();
+        test.write("file", "first\n");
+        test.stage_all();
+        commit_index(&test.repo, "first").unwrap();
+        assert_eq!(test.repo.head().unwrap().peel_to_commit().unwrap().parent_count(), 0);
+
+        let err = commit_index(&test.repo, "again").unwrap_err();
+        assert!(matches!(err, GitError::NothingToCommit { .. }));
+    }
+}
@@ -0,0 +1,554 @@
+// the operations commands run through, implemented once on libgit2 and once on the git CLI
+use crate::error::GitError;
+use crate::status::StatusEntry;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub mod cli;
+pub mod libgit2;
+
+pub use cli::CliBackend;
+pub use libgit2::Git2Backend;
+
+// refs under this namespace are the app's own bookkeeping, such as safety snapshots
+pub const HIDDEN_REFS: &str = "refs/gitaurora/";
+
+// the unified patch of one file, labelled with its status
+#[derive(Debug, Clone)]
+pub struct FilePatch {
+    pub path: String,
+    pub status: &'static str,
+    pub patch: String,
+}
+
+// which whitespace differences a diff ignores
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Whitespace {
+    #[default]
+    Show,
+    // `--ignore-all-space`
+    IgnoreAll,
+    // `--ignore-space-change`
+    IgnoreChange,
+    // `--ignore-space-at-eol`
+    IgnoreAtEol,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffAlgorithm {
+    #[default]
+    Myers,
+    Minimal,
+    Patience,
+    // the git CLI backend only; libgit2 has no histogram algorithm
+    Histogram,
+}
+
+// how finely changed spans are marked within modified lines
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InlineDiff {
+    Word,
+    Char,
+}
+
+// how a diff is computed; the defaults are those of a plain `git diff`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DiffSettings {
+    // lines of context around each hunk, 3 when absent
+    pub context_lines: Option<u32>,
+    pub whitespace: Whitespace,
+    pub ignore_blank_lines: bool,
+    pub algorithm: DiffAlgorithm,
+    // only structured diffs carry the changed spans
+    pub inline: Option<InlineDiff>,
+    // files larger than this many bytes are diffed as binary, 5 MiB when absent
+    pub max_file_size: Option<u64>,
+}
+
+impl DiffSettings {
+    pub fn context(&self) -> u32 {
+        self.context_lines.unwrap_or(3)
+    }
+
+    pub fn max_file_size(&self) -> u64 {
+        self.max_file_size.unwrap_or(5 * 1024 * 1024)
+    }
+}
+
+// where a new branch starts and what happens once it exists
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct NewBranch {
+    // any revision: a commit, tag, local or remote branch; HEAD when absent
+    pub start_point: Option<String>,
+    // switch to the branch after creating it
+    pub checkout: bool,
+    // the branch to track, e.g. `origin/main`; when absent a remote branch start point is tracked,
+    // as `branch.autoSetupMerge` has `git branch` do
+    pub upstream: Option<String>,
+}
+
+impl Default for NewBranch {
+    fn default() -> Self {
+        NewBranch {
+            start_point: None,
+            checkout: true,
+            upstream: None,
+        }
+    }
+}
+
+// a `Key: value` line from the end of a commit message, such as Signed-off-by
+#[derive(Debug, Clone, Serialize)]
+pub struct Trailer {
+    pub key: String,
+    pub value: String,
+}
+
+// the kind of signature a commit carries; whether it verifies is not checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureKind {
+    Gpg,
+    Ssh,
+    X509,
+    // a signature whose armor none of the above use
+    Unknown,
+}
+
+impl SignatureKind {
+    // tell the kind from the armor of the `gpgsig` header, the way git's gpg.format does
+    pub fn from_armor(signature: &str) -> Option<SignatureKind> {
+        let signature = signature.trim_start();
+        if signature.is_empty() {
+            None
+        } else if signature.starts_with("-----BEGIN PGP SIGNATURE-----")
+            || signature.starts_with("-----BEGIN PGP MESSAGE-----")
+        {
+            Some(SignatureKind::Gpg)
+        } else if signature.starts_with("-----BEGIN SSH SIGNATURE-----") {
+            Some(SignatureKind::Ssh)
+        } else if signature.starts_with("-----BEGIN SIGNED MESSAGE-----") {
+            Some(SignatureKind::X509)
+        } else {
+            Some(SignatureKind::Unknown)
+        }
+    }
+}
+
+// everything about a commit except its changes
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitHeader {
+    pub id: String,
+    pub parents: Vec<String>,
+    pub summary: String,
+    // the message after the summary paragraph
+    pub body: String,
+    pub author: String,
+    pub email: String,
+    // ISO 8601 in the author's own timezone
+    pub date: String,
+    pub timestamp: i64,
+    pub committer: String,
+    pub committer_email: String,
+    pub committer_date: String,
+    pub committer_timestamp: i64,
+    pub trailers: Vec<Trailer>,
+    pub signature: Option<SignatureKind>,
+    // full names of the refs pointing at the commit, annotated tags included
+    pub refs: Vec<String>,
+}
+
+pub trait GitBackend {
+    // every changed, staged, conflicted or untracked file, and ignored ones when asked
+    fn status(&self, include_ignored: bool) -> Result<Vec<StatusEntry>, GitError>;
+    // the patch between the index and the working tree, untracked files shown in full
+    fn unstaged_patch(&self, path: &str, settings: &DiffSettings) -> Result<String, GitError>;
+    // the patch between HEAD and the index
+    fn staged_patch(&self, path: &str, settings: &DiffSettings) -> Result<String, GitError>;
+    fn commit_header(&self, revision: &str) -> Result<CommitHeader, GitError>;
+    // the per-file patches a commit introduces against one of its parents, counted from 0
+    fn commit_patches(
+        &self,
+        revision: &str,
+        parent: usize,
+        path: Option<&str>,
+        settings: &DiffSettings,
+    ) -> Result<Vec<FilePatch>, GitError>;
+    fn stage(&self, files: &[String]) -> Result<(), GitError>;
+    // reset the index entries of files to HEAD, leaving the working tree alone
+    fn unstage(&self, files: &[String]) -> Result<(), GitError>;
+    // overwrite working tree files with their version in the index or in HEAD
+    fn restore(&self, files: &[String], source: RestoreSource) -> Result<(), GitError>;
+    // delete untracked files, refusing any that are tracked
+    fn remove_untracked(&self, files: &[String]) -> Result<(), GitError>;
+    fn commit(&self, message: &str) -> Result<String, GitError>;
+    fn create_branch(&self, branch_name: &str, options: &NewBranch) -> Result<String, GitError>;
+    fn switch_branch(&self, branch_name: &str) -> Result<String, GitError>;
+    // delete a local branch, refusing one merged into neither HEAD nor its upstream unless forced
+    fn delete_branch(&self, branch_name: &str, force: bool) -> Result<String, GitError>;
+    // delete the branch named `branch_name` on `remote`, and its remote-tracking branch
+    fn delete_remote_branch(&self, remote: &str, branch_name: &str) -> Result<String, GitError>;
+    // rename the branch `old_name` on `remote` to `new_name` in one push, moving its remote-tracking
+    // branch along
+    fn rename_remote_branch(&self, remote: &str, old_name: &str, new_name: &str) -> Result<String, GitError>;
+    fn merge(&self, branch_name: &str) -> Result<String, GitError>;
+    fn discard(&self) -> Result<(), GitError>;
+    fn fetch(&self) -> Result<(), GitError>;
+    fn pull(&self) -> Result<(), GitError>;
+    fn push(&self) -> Result<(), GitError>;
+}
+
+// where `restore` takes the content of a file from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RestoreSource {
+    Index,
+    Head,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    Git2,
+    Cli,
+}
+
+impl BackendKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            BackendKind::Git2 => "git2",
+            BackendKind::Cli => "cli",
+        }
+    }
+
+    fn parse(value: &str) -> Option<BackendKind> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "git2" | "libgit2" => Some(BackendKind::Git2),
+            "cli" | "git" => Some(BackendKind::Cli),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Status,
+    Diff,
+    Stage,
+    Commit,
+    Branch,
+    Merge,
+    Discard,
+    Fetch,
+    Pull,
+    Push,
+}
+
+impl Operation {
+    const ALL: [Operation; 10] = [
+        Operation::Status,
+        Operation::Diff,
+        Operation::Stage,
+        Operation::Commit,
+        Operation::Branch,
+        Operation::Merge,
+        Operation::Discard,
+        Operation::Fetch,
+        Operation::Pull,
+        Operation::Push,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Operation::Status => "status",
+            Operation::Diff => "diff",
+            Operation::Stage => "stage",
+            Operation::Commit => "commit",
+            Operation::Branch => "branch",
+            Operation::Merge => "merge",
+            Operation::Discard => "discard",
+            Operation::Fetch => "fetch",
+            Operation::Pull => "pull",
+            Operation::Push => "push",
+        }
+    }
+
+    // `gitaurora.<operation>.backend` overrides `gitaurora.backend` for one operation
+    fn config_key(self) -> String {
+        format!("gitaurora.{}.backend", self.as_str())
+    }
+}
+
+const DEFAULT_BACKEND_KEY: &str = "gitaurora.backend";
+
+// libgit2 refuses repositories using extensions it does not know, such as partial clones
+pub fn unsupported_extension(err: &git2::Error) -> bool {
+    err.message().contains("unsupported extension")
+}
+
+// the repository config the backend keys are read from and written to: through libgit2, or
+// through `git config` where libgit2 cannot open the repository
+enum BackendConfig {
+    Git2(git2::Config),
+    Cli(String),
+}
+
+impl BackendConfig {
+    fn open(directory: &str) -> Result<BackendConfig, GitError> {
+        match Repository::open(directory) {
+            Ok(repo) => Ok(BackendConfig::Git2(repo.config()?)),
+            Err(err) if unsupported_extension(&err) => Ok(BackendConfig::Cli(directory.to_string())),
+            Err(err) => Err(GitError::repo_not_found(directory, err)),
+        }
+    }
+
+    fn read(&self, key: &str) -> Option<BackendKind> {
+        let value = match self {
+            BackendConfig::Git2(config) => config.get_string(key).ok(),
+            // `git config --get` exits with 1 for an unset key
+            BackendConfig::Cli(directory) => cli::run_git(directory, &["config", "--get", key]).ok(),
+        };
+        value.and_then(|value| BackendKind::parse(&value))
+    }
+
+    // set or clear a key in the repository's own config; clearing an unset key is not an error
+    fn write(&self, key: &str, backend: Option<BackendKind>) -> Result<(), GitError> {
+        match self {
+            BackendConfig::Git2(config) => {
+                let mut config = config.open_level(git2::ConfigLevel::Local)?;
+                match backend {
+                    Some(backend) => config.set_str(key, backend.as_str())?,
+                    None => match config.remove(key) {
+                        Err(err) if err.code() != git2::ErrorCode::NotFound => return Err(err.into()),
+                        _ => {}
+                    },
+                }
+            }
+            BackendConfig::Cli(directory) => match backend {
+                Some(backend) => {
+                    cli::run_git(directory, &["config", "--local", key, backend.as_str()])?;
+                }
+                None => {
+                    let args = ["config", "--local", "--unset", key];
+                    let output = cli::run_git_unchecked(directory, &args)?;
+                    // exit status 5 is an unset key
+                    if !output.status.success() && output.status.code() != Some(5) {
+                        return Err(cli::command_error(directory, &args, &output));
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+
+    // the backend configured for an operation, libgit2 unless the repository says otherwise
+    fn backend(&self, operation: Operation) -> BackendKind {
+        self.read(&operation.config_key())
+            .or_else(|| self.read(DEFAULT_BACKEND_KEY))
+            .unwrap_or(BackendKind::Git2)
+    }
+}
+
+// open the backend that handles `operation` in the repository at `directory`. A repository
+// libgit2 cannot open always gets the CLI. The commands that go to libgit2 directly through
+// `gitfunction::open_repository` have no CLI implementation and ignore the backend settings; they
+// fail on such a repository with `GitError::Unsupported`. They are get_all_commits_from_branch,
+// get_changed_files_in_commit, get_commit_page, stream_commits, get_commit_graph, get_working_diff,
+// get_commit_diff, compare_revisions, get_combined_diff, get_file_versions, stage_hunks,
+// unstage_hunks, discard_hunks, list_snapshots, restore_snapshot, checkout, get_branch_list,
+// delete_branches, create_tracking_branch, rename_branch and set_branch_upstream
+pub fn open(directory: &str, operation: Operation) -> Result<Box<dyn GitBackend>, GitError> {
+    match Repository::open(directory) {
+        Ok(repo) => {
+            let backend = repo
+                .config()
+                .map_or(BackendKind::Git2, |config| BackendConfig::Git2(config).backend(operation));
+            match backend {
+                BackendKind::Git2 => Ok(Box::new(Git2Backend::new(repo))),
+                BackendKind::Cli => Ok(Box::new(CliBackend::new(directory))),
+            }
+        }
+        Err(err) if unsupported_extension(&err) => Ok(Box::new(CliBackend::new(directory))),
+        Err(err) => Err(GitError::repo_not_found(directory, err)),
+    }
+}
+
+#[derive(Serialize)]
+pub struct BackendSettings {
+    default: BackendKind,
+    operations: BTreeMap<String, BackendKind>,
+}
+
+// a function to get which backend handles each operation in a repository
+#[tauri::command]
+pub async fn get_backend_settings(directory: String) -> Result<BackendSettings, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let config = BackendConfig::open(&directory)?;
+
+        let operations = Operation::ALL
+            .iter()
+            .map(|operation| (operation.as_str().to_string(), config.backend(*operation)))
+            .collect();
+
+        Ok(BackendSettings {
+            default: config.read(DEFAULT_BACKEND_KEY).unwrap_or(BackendKind::Git2),
+            operations,
+        })
+    })
+    .await?
+}
+
+// a function to choose the backend of a repository, for every operation or a single one
+#[tauri::command]
+pub async fn set_backend(
+    directory: String,
+    backend: Option<BackendKind>,
+    operation: Option<Operation>,
+) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        let key = match operation {
+            Some(operation) => operation.config_key(),
+            None => DEFAULT_BACKEND_KEY.to_string(),
+        };
+
+        BackendConfig::open(&directory)?.write(&key, backend)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gitfunction::open_repository;
+    use crate::testutil::TestRepo;
+
+    // a repository using an extension git knows and libgit2 does not
+    fn partial_clone() -> TestRepo {
+        let test = TestRepo::new();
+        test.commit_file("file", "one\n", "first");
+        test.git(&["config", "core.repositoryformatversion", "1"]);
+        test.git(&["config", "extensions.partialclone", "origin"]);
+        test
+    }
+
+    #[tokio::test]
+    async fn overrides_the_default_backend_for_one_operation() {
+        let test = TestRepo::new();
+        set_backend(test.path(), Some(BackendKind::Cli), None).await.unwrap();
+        set_backend(test.path(), Some(BackendKind::Git2), Some(Operation::Status)).await.unwrap();
+
+        let settings = get_backend_settings(test.path()).await.unwrap();
+        assert_eq!(settings.default, BackendKind::Cli);
+        assert_eq!(settings.operations["status"], BackendKind::Git2);
+        assert_eq!(settings.operations["push"], BackendKind::Cli);
+        assert_eq!(test.git(&["config", "gitaurora.status.backend"]).trim(), "git2");
+
+        // unsetting twice is not an error
+        set_backend(test.path(), None, Some(Operation::Status)).await.unwrap();
+        set_backend(test.path(), None, Some(Operation::Status)).await.unwrap();
+        let settings = get_backend_settings(test.path()).await.unwrap();
+        assert_eq!(settings.operations["status"], BackendKind::Cli);
+    }
+
+    #[tokio::test]
+    async fn reads_and_writes_the_settings_of_a_repository_libgit2_cannot_open() {
+        let test = partial_clone();
+        set_backend(test.path(), Some(BackendKind::Cli), Some(Operation::Diff)).await.unwrap();
+        assert_eq!(test.git(&["config", "gitaurora.diff.backend"]).trim(), "cli");
+        let settings = get_backend_settings(test.path()).await.unwrap();
+        assert_eq!(settings.operations["diff"], BackendKind::Cli);
+        assert_eq!(settings.default, BackendKind::Git2);
+    }
+
+    #[test]
+    fn falls_back_to_the_cli_where_libgit2_cannot_open_the_repository() {
+        let test = partial_clone();
+        test.write("file", "two\n");
+
+        let status = open(&test.path(), Operation::Status).unwrap().status(false).unwrap();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].path, "file");
+
+        // the commands with no CLI implementation say so
+        let err = open_repository(&test.path()).err().unwrap();
+        assert!(matches!(err, GitError::Unsupported { .. }));
+        assert!(err.to_string().contains("no git CLI implementation"));
+    }
+
+    #[test]
+    fn reports_a_missing_repository() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let directory = dir.path().to_string_lossy().into_owned();
+        assert!(matches!(open(&directory, Operation::Status), Err(GitError::RepoNotFound { .. })));
+        assert!(matches!(open_repository(&directory), Err(GitError::RepoNotFound { .. })));
+    }
+
+    #[test]
+    fn tells_the_signature_kind_from_its_armor() {
+        let kinds = [
+            ("-----BEGIN PGP SIGNATURE-----\n\nabc\n-----END PGP SIGNATURE-----", Some(SignatureKind::Gpg)),
+            ("-----BEGIN PGP MESSAGE-----\n\nabc\n-----END PGP MESSAGE-----", Some(SignatureKind::Gpg)),
+            ("\n-----BEGIN SSH SIGNATURE-----\nabc\n-----END SSH SIGNATURE-----", Some(SignatureKind::Ssh)),
+            ("-----BEGIN SIGNED MESSAGE-----\nabc\n-----END SIGNED MESSAGE-----", Some(SignatureKind::X509)),
+            ("-----BEGIN SOMETHING ELSE-----\nabc", Some(SignatureKind::Unknown)),
+            ("not armored at all", Some(SignatureKind::Unknown)),
+            ("  \n", None),
+            ("", None),
+        ];
+        for (armor, kind) in kinds {
+            assert_eq!(SignatureKind::from_armor(armor), kind, "{}", armor);
+        }
+    }
+
+    // a commit with a `gpgsig` header holding `armor`, as `git commit -S` writes it
+    fn signed_commit(test: &TestRepo, armor: &str, message: &str) -> git2::Oid {
+        let tree = test.repo.find_tree(test.repo.index().unwrap().write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::new("Ann", "ann@example.com", &git2::Time::new(1_700_000_000, 120)).unwrap();
+        let parent = test.repo.head().unwrap().peel_to_commit().unwrap();
+        let content = test
+            .repo
+            .commit_create_buffer(&signature, &signature, message, &tree, &[&parent])
+            .unwrap();
+        let oid = test
+            .repo
+            .commit_signed(content.as_str().unwrap(), armor, Some("gpgsig"))
+            .unwrap();
+        test.repo.reference("refs/heads/main", oid, true, "test").unwrap();
+        oid
+    }
+
+    #[test]
+    fn reads_the_same_commit_header_on_both_backends() {
+        let test = TestRepo::new();
+        test.commit_file("file", "one\n", "first");
+        let armor = "-----BEGIN SSH SIGNATURE-----\nU1NIU0lH\n-----END SSH SIGNATURE-----";
+        let message = "Add a file\n\nWith a body.\n\nSigned-off-by: Ann <ann@example.com>\nCo-authored-by: Bob <bob@example.com>\n";
+        let oid = signed_commit(&test, armor, message);
+        test.git(&["tag", "-a", "-m", "release", "v1", &oid.to_string()]);
+
+        let mut headers = Vec::new();
+        for backend in ["git2", "cli"] {
+            test.git(&["config", "gitaurora.backend", backend]);
+            let header = open(&test.path(), Operation::Diff).unwrap().commit_header("v1").unwrap();
+            headers.push(serde_json::to_value(header).unwrap());
+        }
+        assert_eq!(headers[0], headers[1]);
+
+        let header = &headers[0];
+        assert_eq!(header["id"], oid.to_string());
+        assert_eq!(header["summary"], "Add a file");
+        assert_eq!(header["signature"], "ssh");
+        assert_eq!(header["date"], "2023-11-15T00:13:20+02:00");
+        assert_eq!(header["trailers"][1]["key"], "Co-authored-by");
+        assert_eq!(header["trailers"][1]["value"], "Bob <bob@example.com>");
+        let refs: Vec<&str> = header["refs"].as_array().unwrap().iter().map(|name| name.as_str().unwrap()).collect();
+        assert!(refs.contains(&"refs/heads/main") && refs.contains(&"refs/tags/v1"), "{:?}", refs);
+    }
+}
@@ -0,0 +1,247 @@
+// file contents for the UI: telling UTF-16 text from binary, and both versions of a changed file
+// handed over whole so images can be compared side by side
+use crate::backend::libgit2::{head_tree, parent_tree};
+use crate::error::GitError;
+use crate::gitfunction::{find_commit, open_repository};
+use base64::Engine;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE};
+use git2::{ObjectType, Oid, Repository, Tree};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// versions larger than this are described but not sent
+const MAX_BLOB_SIZE: u64 = 20 * 1024 * 1024;
+
+// which two versions of a file to fetch
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileVersions {
+    // the index against the working tree
+    Unstaged,
+    // HEAD against the index
+    Staged,
+    // a commit against one of its parents, the first when absent
+    Commit { commit_hash: String, parent: Option<usize> },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileContent {
+    pub size: u64,
+    // set for the image formats a browser can show
+    pub mime: Option<&'static str>,
+    // the whole file as base64, None when it is over the size limit
+    pub data: Option<String>,
+    pub too_large: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileVersionPair {
+    // None where the file does not exist on that side
+    pub old: Option<FileContent>,
+    pub new: Option<FileContent>,
+}
+
+// UTF-16 from its byte order mark or, without one, from ASCII text with every other byte zero
+pub fn utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    match bytes {
+        [0xff, 0xfe, ..] => return Some(UTF_16LE),
+        [0xfe, 0xff, ..] => return Some(UTF_16BE),
+        _ => {}
+    }
+    if bytes.len() < 4 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+
+    let sample = &bytes[..bytes.len().min(8000)];
+    let zeros = |offset: usize| sample.iter().skip(offset).step_by(2).filter(|byte| **byte == 0).count();
+    let pairs = sample.len() / 2;
+    // nine in ten characters in the ASCII range is a safe sign; binary data rarely looks like that
+    if zeros(1) * 10 >= pairs * 9 && zeros(0) == 0 {
+        Some(UTF_16LE)
+    } else if zeros(0) * 10 >= pairs * 9 && zeros(1) == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+// the image type from the file's first bytes, or its extension for SVG
+fn mime_type(bytes: &[u8], path: &str) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xff, 0xd8, 0xff, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'B', b'M', ..] => Some("image/bmp"),
+        [0, 0, 1, 0, ..] => Some("image/x-icon"),
+        _ if path.to_lowercase().ends_with(".svg") => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+fn content(bytes: Vec<u8>, path: &str) -> FileContent {
+    let size = bytes.len() as u64;
+    let too_large = size > MAX_BLOB_SIZE;
+    FileContent {
+        size,
+        mime: mime_type(&bytes, path),
+        data: (!too_large).then(|| base64::engine::general_purpose::STANDARD.encode(&bytes)),
+        too_large,
+    }
+}
+
+// the file at `path` in a tree; submodules and directories have no contents to show
+fn tree_file(repo: &Repository, tree: Option<&Tree>, path: &str) -> Result<Option<FileContent>, GitError> {
+    let Some(entry) = tree.and_then(|tree| tree.get_path(Path::new(path)).ok()) else {
+        return Ok(None);
+    };
+    if entry.kind() != Some(ObjectType::Blob) {
+        return Ok(None);
+    }
+    blob_file(repo, entry.id(), path)
+}
+
+fn blob_file(repo: &Repository, id: Oid, path: &str) -> Result<Option<FileContent>, GitError> {
+    // the header gives the size without reading a blob that will not be sent
+    let (size, _) = repo.odb()?.read_header(id)?;
+    if size as u64 > MAX_BLOB_SIZE {
+        return Ok(Some(FileContent {
+            size: size as u64,
+            mime: None,
+            data: None,
+            too_large: true,
+        }));
+    }
+    Ok(Some(content(repo.find_blob(id)?.content().to_vec(), path)))
+}
+
+fn index_file(repo: &Repository, path: &str) -> Result<Option<FileContent>, GitError> {
+    match repo.index()?.get_path(Path::new(path), 0) {
+        Some(entry) => blob_file(repo, entry.id, path),
+        None => Ok(None),
+    }
+}
+
+fn worktree_file(repo: &Repository, path: &str) -> Result<Option<FileContent>, GitError> {
+    let Some(workdir) = repo.workdir() else {
+        return Ok(None);
+    };
+    let file = workdir.join(path);
+    let Ok(metadata) = fs::metadata(&file) else {
+        return Ok(None);
+    };
+    if !metadata.is_file() {
+        return Ok(None);
+    }
+    if metadata.len() > MAX_BLOB_SIZE {
+        return Ok(Some(FileContent {
+            size: metadata.len(),
+            mime: None,
+            data: None,
+            too_large: true,
+        }));
+    }
+    Ok(Some(content(fs::read(file)?, path)))
+}
+
+// a function to get the old and new version of a file as base64, for comparing images side by
+// side; `old_filename` is where a renamed file used to be
+#[tauri::command]
+pub async fn get_file_versions(
+    directory: String,
+    filename: String,
+    old_filename: Option<String>,
+    versions: FileVersions,
+) -> Result<FileVersionPair, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let old_filename = old_filename.unwrap_or_else(|| filename.clone());
+
+        let (old, new) = match versions {
+            FileVersions::Unstaged => (index_file(&repo, &old_filename)?, worktree_file(&repo, &filename)?),
+            FileVersions::Staged => {
+                let head = head_tree(&repo)?;
+                (tree_file(&repo, head.as_ref(), &old_filename)?, index_file(&repo, &filename)?)
+            }
+            FileVersions::Commit { commit_hash, parent } => {
+                let commit = find_commit(&repo, &commit_hash)?;
+                let parent_tree = parent_tree(&commit, parent.unwrap_or(0))?;
+                (
+                    tree_file(&repo, parent_tree.as_ref(), &old_filename)?,
+                    tree_file(&repo, Some(&commit.tree()?), &filename)?,
+                )
+            }
+        };
+
+        Ok(FileVersionPair { old, new })
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0, 0, 0, 0x0d];
+
+    fn decoded(content: &Option<FileContent>) -> Option<Vec<u8>> {
+        let data = content.as_ref()?.data.as_ref()?;
+        Some(base64::engine::general_purpose::STANDARD.decode(data).unwrap())
+    }
+
+    #[test]
+    fn tells_utf16_from_binary() {
+        let le: Vec<u8> = "hello world".encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let be: Vec<u8> = "hello world".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(utf16(&le), Some(UTF_16LE));
+        assert_eq!(utf16(&be), Some(UTF_16BE));
+        assert_eq!(utf16(&[0xff, 0xfe, 0x00, 0xd8]), Some(UTF_16LE));
+        assert_eq!(utf16(PNG), None);
+        assert_eq!(utf16(&[0, 1, 2, 3, 0, 0, 5, 0]), None);
+        // an odd length cannot be UTF-16
+        assert_eq!(utf16(&le[1..]), None);
+    }
+
+    #[tokio::test]
+    async fn hands_over_both_versions_of_each_kind() {
+        let test = TestRepo::new();
+        std::fs::write(test.join("image.png"), PNG).unwrap();
+        let first = test.commit("first");
+        let staged = [PNG, b"staged"].concat();
+        std::fs::write(test.join("image.png"), &staged).unwrap();
+        test.stage_all();
+        let unstaged = [PNG, b"unstaged"].concat();
+        std::fs::write(test.join("image.png"), &unstaged).unwrap();
+
+        let versions = |versions| get_file_versions(test.path(), "image.png".into(), None, versions);
+        let pair = versions(FileVersions::Unstaged).await.unwrap();
+        assert_eq!((decoded(&pair.old), decoded(&pair.new)), (Some(staged.clone()), Some(unstaged)));
+        assert_eq!(pair.new.as_ref().unwrap().mime, Some("image/png"));
+        let pair = versions(FileVersions::Staged).await.unwrap();
+        assert_eq!((decoded(&pair.old), decoded(&pair.new)), (Some(PNG.to_vec()), Some(staged)));
+
+        let commit = FileVersions::Commit { commit_hash: first.to_string(), parent: None };
+        let pair = versions(commit).await.unwrap();
+        assert!(pair.old.is_none());
+        assert_eq!(decoded(&pair.new), Some(PNG.to_vec()));
+        assert_eq!(pair.new.unwrap().size, PNG.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn reads_the_old_version_of_a_renamed_file_from_its_old_path() {
+        let test = TestRepo::new();
+        test.commit_file("logo.svg", "<svg/>\n", "first");
+        test.git(&["mv", "logo.svg", "icon.svg"]);
+        let renamed = test.commit("rename");
+
+        let commit = FileVersions::Commit { commit_hash: renamed.to_string(), parent: None };
+        let pair = get_file_versions(test.path(), "icon.svg".into(), Some("logo.svg".into()), commit)
+            .await
+            .unwrap();
+        assert_eq!(decoded(&pair.old), Some(b"<svg/>\n".to_vec()));
+        assert_eq!(decoded(&pair.new), Some(b"<svg/>\n".to_vec()));
+        assert_eq!(pair.old.unwrap().mime, Some("image/svg+xml"));
+    }
+}
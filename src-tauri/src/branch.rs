@@ -0,0 +1,741 @@
+// the branch list: each branch with its upstream, how far it has drifted from it, its tip commit
+// and whether it is already merged into the default branch; and creating, renaming and deleting
+// branches beyond what the backends' single-branch operations cover
+use crate::backend::libgit2::{format_time, merged_into};
+use crate::backend::{self, GitBackend, NewBranch, Operation};
+use crate::error::GitError;
+use crate::gitfunction::open_repository;
+use git2::{Branch as GitBranch, BranchType, Oid, Repository};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BranchTip {
+    id: String,
+    summary: String,
+    author: String,
+    email: String,
+    // ISO 8601 in the author's own timezone
+    date: String,
+    timestamp: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Branch {
+    // the short name, e.g. `main` or `origin/main`
+    name: String,
+    full_name: String,
+    is_remote: bool,
+    is_head: bool,
+    // the configured upstream's short name, kept when the upstream itself no longer exists
+    upstream: Option<String>,
+    // an upstream is configured but its ref is missing, usually deleted on the remote and pruned
+    upstream_gone: bool,
+    // commits only on the branch and only on the upstream; None without a live upstream
+    ahead: Option<usize>,
+    behind: Option<usize>,
+    tip: Option<BranchTip>,
+    // the default branch itself, locally or on a remote
+    is_default: bool,
+    // whether the tip is reachable from the default branch; None when there is no default branch
+    merged: Option<bool>,
+}
+
+// the branch everything is eventually merged into, and the tips that stand for it
+pub struct DefaultBranch {
+    // full names of the local branch and its remote-tracking counterparts that exist
+    pub refs: Vec<String>,
+    pub tips: Vec<Oid>,
+}
+
+impl DefaultBranch {
+    // the commit is on the default branch, locally or on a remote
+    pub fn contains(&self, repo: &Repository, commit: Oid) -> bool {
+        self.tips
+            .iter()
+            .any(|&tip| tip == commit || repo.graph_descendant_of(tip, commit).unwrap_or(false))
+    }
+}
+
+// what a remote's HEAD points at, origin first, or else `init.defaultBranch`, `main` or `master`
+// when that exists locally
+pub fn default_branch(repo: &Repository) -> Result<Option<DefaultBranch>, GitError> {
+    let mut remotes: Vec<String> = repo.remotes()?.iter().flatten().map(str::to_string).collect();
+    remotes.sort_by_key(|remote| remote != "origin");
+
+    let from_remote = remotes.iter().find_map(|remote| {
+        let head = repo.find_reference(&format!("refs/remotes/{}/HEAD", remote)).ok()?;
+        let target = head.symbolic_target()?;
+        target.strip_prefix(&format!("refs/remotes/{}/", remote)).map(str::to_string)
+    });
+    let configured = repo.config()?.get_string("init.defaultBranch").ok();
+    let name = from_remote.or_else(|| {
+        configured
+            .into_iter()
+            .chain(["main".to_string(), "master".to_string()])
+            .find(|name| repo.find_branch(name, BranchType::Local).is_ok())
+    });
+    let Some(name) = name else {
+        return Ok(None);
+    };
+
+    let mut refs = Vec::new();
+    let mut tips = Vec::new();
+    let candidates = std::iter::once(format!("refs/heads/{}", name))
+        .chain(remotes.iter().map(|remote| format!("refs/remotes/{}/{}", remote, name)));
+    for refname in candidates {
+        if let Ok(tip) = repo.refname_to_id(&refname) {
+            refs.push(refname);
+            tips.push(tip);
+        }
+    }
+
+    Ok(Some(DefaultBranch { refs, tips }))
+}
+
+// the upstream's short name from the branch's config, and whether its ref still exists
+fn upstream(repo: &Repository, full_name: &str) -> (Option<String>, Option<Oid>) {
+    let Ok(upstream) = repo.branch_upstream_name(full_name) else {
+        return (None, None);
+    };
+    let Some(upstream) = upstream.as_str() else {
+        return (None, None);
+    };
+    let short = upstream
+        .strip_prefix("refs/remotes/")
+        .or_else(|| upstream.strip_prefix("refs/heads/"))
+        .unwrap_or(upstream);
+    (Some(short.to_string()), repo.refname_to_id(upstream).ok())
+}
+
+fn branch_tip(branch: &GitBranch) -> Option<BranchTip> {
+    let commit = branch.get().peel_to_commit().ok()?;
+    let author = commit.author();
+    Some(BranchTip {
+        id: commit.id().to_string(),
+        summary: commit.summary().unwrap_or_default().to_string(),
+        author: author.name().unwrap_or_default().to_string(),
+        email: author.email().unwrap_or_default().to_string(),
+        date: format_time(&author.when()),
+        timestamp: author.when().seconds(),
+    })
+}
+
+pub fn branch_list(repo: &Repository) -> Result<Vec<Branch>, GitError> {
+    let default = default_branch(repo)?;
+
+    let mut branch_list = Vec::new();
+    for branch_result in repo.branches(None)? {
+        let (branch, branch_type) = branch_result?;
+        let Some(name) = branch.name()? else {
+            continue;
+        };
+        let full_name = branch.get().name().unwrap_or_default().to_string();
+        let is_remote = branch_type == BranchType::Remote;
+        let tip_id = branch.get().peel_to_commit().ok().map(|commit| commit.id());
+
+        let (upstream, upstream_tip) = if is_remote { (None, None) } else { upstream(repo, &full_name) };
+        let (ahead, behind) = match (tip_id, upstream_tip) {
+            (Some(tip), Some(upstream_tip)) => {
+                let (ahead, behind) = repo.graph_ahead_behind(tip, upstream_tip)?;
+                (Some(ahead), Some(behind))
+            }
+            _ => (None, None),
+        };
+
+        branch_list.push(Branch {
+            name: name.to_string(),
+            is_remote,
+            is_head: branch.is_head(),
+            upstream_gone: upstream.is_some() && upstream_tip.is_none(),
+            upstream,
+            ahead,
+            behind,
+            tip: branch_tip(&branch),
+            is_default: default.as_ref().is_some_and(|default| default.refs.contains(&full_name)),
+            merged: default
+                .as_ref()
+                .zip(tip_id)
+                .map(|(default, tip)| default.contains(repo, tip)),
+            full_name,
+        });
+    }
+
+    Ok(branch_list)
+}
+
+// a function to list the local and remote branches with their upstream, tip and merged state
+#[tauri::command]
+pub async fn get_branch_list(directory: String) -> Result<Vec<Branch>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        branch_list(&repo)
+    })
+    .await?
+}
+
+#[derive(Debug, Serialize)]
+pub struct BranchDeletion {
+    name: String,
+    // the upstream's short name or "HEAD" when the tip is merged into it, None when it is in neither
+    merged_into: Option<String>,
+    deleted: bool,
+    // the branch on the remote, e.g. `origin/feature`, when it was to be deleted as well
+    remote_branch: Option<String>,
+    remote_deleted: bool,
+    // git's report of each step that succeeded
+    messages: Vec<String>,
+    // why the deletion stopped; the steps before it still happened
+    error: Option<GitError>,
+}
+
+// the remote and branch a local branch is pushed to, from its upstream config
+fn remote_counterpart(repo: &Repository, full_name: &str, name: &str) -> Option<(String, String)> {
+    let remote = repo.branch_upstream_remote(full_name).ok()?.as_str()?.to_string();
+    // "." tracks a local branch
+    if remote == "." {
+        return None;
+    }
+    let merge = repo.config().ok()?.get_string(&format!("branch.{}.merge", name)).ok()?;
+    let branch = merge.strip_prefix("refs/heads/").unwrap_or(&merge).to_string();
+    Some((remote, branch))
+}
+
+// what deleting a batch of branches shares: the backends for local and remote deletions, and the options
+struct Deleter<'a> {
+    repo: &'a Repository,
+    local: &'a dyn GitBackend,
+    remote: &'a dyn GitBackend,
+    force: bool,
+    delete_remote: bool,
+}
+
+impl Deleter<'_> {
+    fn delete(&self, name: String) -> BranchDeletion {
+        let mut deletion = BranchDeletion {
+            name: name.clone(),
+            merged_into: None,
+            deleted: false,
+            remote_branch: None,
+            remote_deleted: false,
+            messages: Vec::new(),
+            error: None,
+        };
+        let result = if let Ok(branch) = self.repo.find_branch(&name, BranchType::Local) {
+            self.delete_local(branch, &mut deletion)
+        } else {
+            match self.repo.find_branch(&name, BranchType::Remote) {
+                Ok(branch) => self.delete_on_remote(branch, &mut deletion),
+                Err(_) => Err(GitError::RefNotFound {
+                    name: name.clone(),
+                    message: format!("branch '{}' not found.", name),
+                }),
+            }
+        };
+        deletion.error = result.err();
+        deletion
+    }
+
+    fn delete_local(&self, branch: GitBranch, deletion: &mut BranchDeletion) -> Result<(), GitError> {
+        let full_name = branch.get().name().unwrap_or_default().to_string();
+        deletion.merged_into = merged_into(self.repo, &branch)?;
+        // the upstream config goes with the branch, so look it up first
+        let counterpart = if self.delete_remote {
+            remote_counterpart(self.repo, &full_name, &deletion.name)
+        } else {
+            None
+        };
+
+        deletion.messages.push(self.local.delete_branch(&deletion.name, self.force)?);
+        deletion.deleted = true;
+
+        if let Some((remote_name, branch_name)) = counterpart {
+            deletion.remote_branch = Some(format!("{}/{}", remote_name, branch_name));
+            deletion.messages.push(self.remote.delete_remote_branch(&remote_name, &branch_name)?);
+            deletion.remote_deleted = true;
+        }
+        Ok(())
+    }
+
+    // a remote-tracking branch stands for the branch on the remote, which is what gets deleted
+    fn delete_on_remote(&self, branch: GitBranch, deletion: &mut BranchDeletion) -> Result<(), GitError> {
+        let full_name = branch.get().name().unwrap_or_default().to_string();
+        let remote_name = self.repo.branch_remote_name(&full_name)?;
+        let remote_name = remote_name.as_str().unwrap_or("origin");
+        let branch_name = full_name
+            .strip_prefix(&format!("refs/remotes/{}/", remote_name))
+            .unwrap_or(&deletion.name)
+            .to_string();
+
+        deletion.merged_into = merged_into(self.repo, &branch)?;
+        deletion.remote_branch = Some(deletion.name.clone());
+        if !self.force && deletion.merged_into.is_none() {
+            return Err(GitError::BranchNotMerged {
+                name: deletion.name.clone(),
+                message: format!("The branch '{}' is not fully merged.", deletion.name),
+            });
+        }
+
+        deletion.messages.push(self.remote.delete_remote_branch(remote_name, &branch_name)?);
+        deletion.remote_deleted = true;
+        Ok(())
+    }
+}
+
+// a function to delete branches one by one, reporting for each whether it was merged and how far
+// the deletion got; unmerged branches are kept unless `force` is set, and `delete_remote` also
+// deletes the upstream of a local branch on its remote. Remote branches are deleted on the remote.
+#[tauri::command]
+pub async fn delete_branches(
+    directory: String,
+    branches: Vec<String>,
+    force: Option<bool>,
+    delete_remote: Option<bool>,
+) -> Result<Vec<BranchDeletion>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let local = backend::open(&directory, Operation::Branch)?;
+        let remote = backend::open(&directory, Operation::Push)?;
+        let deleter = Deleter {
+            repo: &repo,
+            local: &*local,
+            remote: &*remote,
+            force: force.unwrap_or(false),
+            delete_remote: delete_remote.unwrap_or(false),
+        };
+
+        let deletions = branches.into_iter().map(|name| deleter.delete(name)).collect();
+        Ok(deletions)
+    })
+    .await?
+}
+
+// a function to create a local branch tracking a remote branch from the branch list, named after
+// it without the remote unless `branch_name` is given, and checked out unless `checkout` is false
+#[tauri::command]
+pub async fn create_tracking_branch(
+    directory: String,
+    remote_branch: String,
+    branch_name: Option<String>,
+    checkout: Option<bool>,
+) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let branch = repo
+            .find_branch(&remote_branch, BranchType::Remote)
+            .map_err(|err| GitError::ref_not_found(&remote_branch, err))?;
+        let branch_name = match branch_name {
+            Some(branch_name) => branch_name,
+            None => {
+                let full_name = branch.get().name().unwrap_or_default();
+                let remote = repo.branch_remote_name(full_name)?;
+                let prefix = format!("refs/remotes/{}/", remote.as_str().unwrap_or_default());
+                full_name.strip_prefix(&prefix).unwrap_or(&remote_branch).to_string()
+            }
+        };
+
+        let options = NewBranch {
+            start_point: Some(remote_branch.clone()),
+            checkout: checkout.unwrap_or(true),
+            upstream: Some(remote_branch),
+        };
+        backend::open(&directory, Operation::Branch)?.create_branch(&branch_name, &options)
+    })
+    .await?
+}
+
+// a function to rename a local branch; its config section and HEAD follow it. With `rename_remote`
+// its upstream is renamed on the remote as well and tracked under the new name; when that push
+// fails, the local rename is undone
+#[tauri::command]
+pub async fn rename_branch(
+    directory: String,
+    branch_name: String,
+    new_name: String,
+    rename_remote: Option<bool>,
+) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let mut branch = repo
+            .find_branch(&branch_name, BranchType::Local)
+            .map_err(|err| GitError::ref_not_found(&branch_name, err))?;
+        let full_name = branch.get().name().unwrap_or_default().to_string();
+
+        if !GitBranch::name_is_valid(&new_name)? {
+            return Err(GitError::InvalidSelection {
+                message: format!("'{}' is not a valid branch name.", new_name),
+            });
+        }
+        if repo.find_branch(&new_name, BranchType::Local).is_ok() {
+            return Err(GitError::InvalidSelection {
+                message: format!("a branch named '{}' already exists", new_name),
+            });
+        }
+
+        let counterpart = if rename_remote.unwrap_or(false) {
+            let counterpart = remote_counterpart(&repo, &full_name, &branch_name).ok_or_else(|| GitError::RefNotFound {
+                name: branch_name.clone(),
+                message: format!("The branch {} has no upstream branch on a remote.", branch_name),
+            })?;
+            Some(counterpart)
+        } else {
+            None
+        };
+
+        let mut branch = branch.rename(&new_name, false)?;
+        let mut messages = vec![format!("Renamed branch '{}' to '{}'", branch_name, new_name)];
+        if let Some((remote, remote_branch)) = &counterpart {
+            let pushed = backend::open(&directory, Operation::Push)
+                .and_then(|pusher| pusher.rename_remote_branch(remote, remote_branch, &new_name));
+            match pushed {
+                Ok(message) => messages.push(message),
+                Err(err) => {
+                    // put the local branch back, its upstream config moves back with it
+                    branch.rename(&branch_name, false)?;
+                    return Err(err);
+                }
+            }
+            branch.set_upstream(Some(&format!("{}/{}", remote, new_name)))?;
+        }
+        Ok(messages.join("\n"))
+    })
+    .await?
+}
+
+// a function to set the branch a local branch tracks, e.g. `origin/main`, or unset it with None
+#[tauri::command]
+pub async fn set_branch_upstream(
+    directory: String,
+    branch_name: String,
+    upstream: Option<String>,
+) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let mut branch = repo
+            .find_branch(&branch_name, BranchType::Local)
+            .map_err(|err| GitError::ref_not_found(&branch_name, err))?;
+
+        match upstream {
+            Some(upstream) => {
+                branch
+                    .set_upstream(Some(&upstream))
+                    .map_err(|err| GitError::ref_not_found(&upstream, err))?;
+                Ok(format!("branch '{}' set up to track '{}'.", branch_name, upstream))
+            }
+            None => {
+                // unsetting an upstream that is not there is not worth an error
+                let full_name = branch.get().name().unwrap_or_default().to_string();
+                if repo.branch_upstream_name(&full_name).is_ok() {
+                    branch.set_upstream(None)?;
+                }
+                Ok(format!("Branch '{}' no longer tracks an upstream.", branch_name))
+            }
+        }
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    fn find<'a>(branches: &'a [Branch], name: &str) -> &'a Branch {
+        branches.iter().find(|branch| branch.name == name).unwrap()
+    }
+
+    #[tokio::test]
+    async fn lists_upstreams_drift_tips_and_merged_state() {
+        let test = TestRepo::new();
+        let _origin = test.remote("origin");
+        let first = test.commit_file("a.txt", "1\n", "first");
+        test.git(&["push", "-u", "origin", "main"]);
+        test.commit_file("a.txt", "2\n", "second");
+        test.git(&["push"]);
+        test.commit_file("a.txt", "3\n", "third");
+        // one commit behind origin/main and already merged
+        test.git(&["branch", "stale", &first.to_string()]);
+        test.git(&["branch", "--set-upstream-to=origin/main", "stale"]);
+        // tracking a branch that was deleted on the remote
+        test.git(&["push", "origin", "main:gone"]);
+        test.git(&["branch", "gone", "main"]);
+        test.git(&["branch", "--set-upstream-to=origin/gone", "gone"]);
+        test.git(&["push", "origin", "--delete", "gone"]);
+        test.git(&["switch", "-c", "feature"]);
+        test.commit_file("b.txt", "b\n", "on feature");
+
+        let branches = get_branch_list(test.path()).await.unwrap();
+        let names: Vec<&str> = branches.iter().map(|branch| branch.name.as_str()).collect();
+        assert_eq!(names, ["feature", "gone", "main", "stale", "origin/main"]);
+
+        let main = find(&branches, "main");
+        assert_eq!(main.full_name, "refs/heads/main");
+        assert_eq!(main.upstream.as_deref(), Some("origin/main"));
+        assert_eq!((main.ahead, main.behind), (Some(1), Some(0)));
+        assert!(main.is_default && !main.is_head && !main.upstream_gone);
+        assert_eq!(main.merged, Some(true));
+        let tip = main.tip.as_ref().unwrap();
+        assert_eq!((tip.summary.as_str(), tip.author.as_str()), ("third", "Test"));
+        assert_eq!(tip.email, "test@example.com");
+        assert_eq!(tip.id, test.git(&["rev-parse", "main"]).trim());
+
+        let stale = find(&branches, "stale");
+        assert_eq!((stale.ahead, stale.behind), (Some(0), Some(1)));
+        assert_eq!(stale.merged, Some(true));
+        assert!(!stale.is_default);
+
+        let gone = find(&branches, "gone");
+        assert_eq!(gone.upstream.as_deref(), Some("origin/gone"));
+        assert!(gone.upstream_gone);
+        assert_eq!((gone.ahead, gone.behind), (None, None));
+
+        let feature = find(&branches, "feature");
+        assert!(feature.is_head);
+        assert_eq!(feature.upstream, None);
+        assert_eq!(feature.merged, Some(false));
+
+        let remote = find(&branches, "origin/main");
+        assert!(remote.is_remote && remote.is_default);
+        assert_eq!((remote.upstream.as_deref(), remote.ahead), (None, None));
+        assert_eq!(remote.tip.as_ref().unwrap().summary, "second");
+    }
+
+    #[tokio::test]
+    async fn prefers_the_remote_head_as_default_branch() {
+        let test = TestRepo::new();
+        let _origin = test.remote("origin");
+        test.commit_file("a.txt", "1\n", "first");
+        test.git(&["push", "origin", "main", "main:trunk"]);
+        test.git(&["symbolic-ref", "refs/remotes/origin/HEAD", "refs/remotes/origin/trunk"]);
+
+        let branches = get_branch_list(test.path()).await.unwrap();
+        assert!(!find(&branches, "main").is_default);
+        assert!(!find(&branches, "origin/main").is_default);
+        assert!(find(&branches, "origin/trunk").is_default);
+        assert_eq!(find(&branches, "main").merged, Some(true));
+    }
+
+    #[tokio::test]
+    async fn has_no_default_branch_without_main_or_master() {
+        let test = TestRepo::new();
+        test.commit_file("a.txt", "1\n", "first");
+        test.git(&["branch", "-m", "work"]);
+
+        let branches = get_branch_list(test.path()).await.unwrap();
+        let work = find(&branches, "work");
+        assert!(!work.is_default);
+        assert_eq!(work.merged, None);
+    }
+
+    // a repository pushed to `origin` with `merged` on main and `unmerged` ahead of it, both tracking
+    // their pushed counterparts and `unmerged` a commit ahead of its own, run through `backend`
+    fn branches_on(backend: &str) -> (TestRepo, tempfile::TempDir) {
+        let test = TestRepo::new();
+        let origin = test.remote("origin");
+        test.commit_file("a.txt", "1\n", "first");
+        test.git(&["branch", "merged"]);
+        test.git(&["switch", "-c", "unmerged"]);
+        test.commit_file("b.txt", "b\n", "unmerged");
+        test.git(&["push", "-u", "origin", "main", "merged", "unmerged"]);
+        test.commit_file("b.txt", "c\n", "not pushed");
+        test.git(&["switch", "main"]);
+        test.git(&["config", "gitaurora.backend", backend]);
+        (test, origin)
+    }
+
+    fn on_remote(test: &TestRepo) -> String {
+        test.git(&["ls-remote", "--heads", "origin"])
+            .lines()
+            .filter_map(|line| line.split_once("refs/heads/").map(|(_, name)| name.to_string()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[tokio::test]
+    async fn deletes_a_batch_keeping_unmerged_and_missing_branches() {
+        for backend in ["git2", "cli"] {
+            let (test, _origin) = branches_on(backend);
+            let names = vec!["merged".into(), "unmerged".into(), "missing".into()];
+            let deletions = delete_branches(test.path(), names, None, None).await.unwrap();
+
+            let merged = &deletions[0];
+            assert!(merged.deleted && merged.error.is_none(), "{}", backend);
+            assert_eq!(merged.merged_into.as_deref(), Some("origin/merged"));
+            assert_eq!(merged.messages.len(), 1);
+            assert!(!merged.remote_deleted && merged.remote_branch.is_none());
+
+            let unmerged = &deletions[1];
+            assert!(!unmerged.deleted, "{}", backend);
+            assert_eq!(unmerged.merged_into, None);
+            assert!(
+                matches!(unmerged.error, Some(GitError::BranchNotMerged { .. })),
+                "{}: {:?}",
+                backend,
+                unmerged.error
+            );
+
+            assert!(matches!(deletions[2].error, Some(GitError::RefNotFound { .. })));
+            assert_eq!(test.git(&["branch", "--format=%(refname:short)"]), "main\nunmerged\n");
+            // nothing was deleted on the remote
+            assert_eq!(on_remote(&test), "main merged unmerged");
+        }
+    }
+
+    #[tokio::test]
+    async fn force_deletes_unmerged_branches() {
+        for backend in ["git2", "cli"] {
+            let (test, _origin) = branches_on(backend);
+            let deletions = delete_branches(test.path(), vec!["unmerged".into()], Some(true), None)
+                .await
+                .unwrap();
+            assert!(deletions[0].deleted, "{}: {:?}", backend, deletions[0].error);
+            assert_eq!(deletions[0].merged_into, None);
+            assert_eq!(test.git(&["branch", "--format=%(refname:short)"]), "main\nmerged\n");
+            assert_eq!(on_remote(&test), "main merged unmerged");
+        }
+    }
+
+    #[tokio::test]
+    async fn deletes_the_upstream_on_its_remote_too() {
+        for backend in ["git2", "cli"] {
+            let (test, _origin) = branches_on(backend);
+            let names = vec!["merged".into(), "unmerged".into()];
+            let deletions = delete_branches(test.path(), names, Some(true), Some(true)).await.unwrap();
+            for deletion in &deletions {
+                assert!(deletion.deleted && deletion.remote_deleted, "{}: {:?}", backend, deletion.error);
+                assert_eq!(deletion.messages.len(), 2);
+            }
+            assert_eq!(deletions[0].remote_branch.as_deref(), Some("origin/merged"));
+            assert_eq!(on_remote(&test), "main", "{}", backend);
+            assert_eq!(test.git(&["branch", "-r", "--format=%(refname:short)"]), "origin/main\n");
+        }
+    }
+
+    #[tokio::test]
+    async fn deletes_remote_branches_on_the_remote_when_merged_or_forced() {
+        for backend in ["git2", "cli"] {
+            let (test, _origin) = branches_on(backend);
+            let names = vec!["origin/merged".into(), "origin/unmerged".into()];
+            let deletions = delete_branches(test.path(), names, None, None).await.unwrap();
+            assert!(deletions[0].remote_deleted, "{}: {:?}", backend, deletions[0].error);
+            assert!(!deletions[0].deleted);
+            assert_eq!(deletions[0].merged_into.as_deref(), Some("HEAD"));
+            assert!(!deletions[1].remote_deleted);
+            assert!(matches!(deletions[1].error, Some(GitError::BranchNotMerged { .. })));
+            assert_eq!(on_remote(&test), "main unmerged", "{}", backend);
+
+            let deletions = delete_branches(test.path(), vec!["origin/unmerged".into()], Some(true), None)
+                .await
+                .unwrap();
+            assert!(deletions[0].remote_deleted, "{}: {:?}", backend, deletions[0].error);
+            assert_eq!(on_remote(&test), "main", "{}", backend);
+            // the local branches are left alone
+            assert_eq!(test.git(&["branch", "--format=%(refname:short)"]), "main\nmerged\nunmerged\n");
+        }
+    }
+
+    #[tokio::test]
+    async fn creates_tracking_branches_named_after_the_remote_branch() {
+        for backend in ["git2", "cli"] {
+            let (test, _origin) = branches_on(backend);
+            test.git(&["push", "origin", "main:feature/x"]);
+
+            let message = create_tracking_branch(test.path(), "origin/feature/x".into(), None, None)
+                .await
+                .unwrap();
+            assert!(!message.is_empty());
+            assert_eq!(test.git(&["branch", "--show-current"]), "feature/x\n", "{}", backend);
+            assert_eq!(
+                test.git(&["rev-parse", "--abbrev-ref", "feature/x@{upstream}"]),
+                "origin/feature/x\n",
+                "{}",
+                backend
+            );
+
+            create_tracking_branch(test.path(), "origin/unmerged".into(), Some("mine".into()), Some(false))
+                .await
+                .unwrap();
+            assert_eq!(test.git(&["branch", "--show-current"]), "feature/x\n", "{}", backend);
+            assert_eq!(test.git(&["rev-parse", "mine"]), test.git(&["rev-parse", "origin/unmerged"]));
+            assert_eq!(test.git(&["rev-parse", "--abbrev-ref", "mine@{upstream}"]), "origin/unmerged\n");
+
+            let err = create_tracking_branch(test.path(), "origin/missing".into(), None, None)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, GitError::RefNotFound { .. }), "{}: {:?}", backend, err);
+        }
+    }
+
+    #[tokio::test]
+    async fn renames_locally_with_head_and_upstream_following() {
+        let (test, _origin) = branches_on("git2");
+        test.git(&["switch", "unmerged"]);
+
+        rename_branch(test.path(), "unmerged".into(), "renamed".into(), None).await.unwrap();
+        assert_eq!(test.git(&["branch", "--show-current"]), "renamed\n");
+        assert_eq!(test.git(&["rev-parse", "--abbrev-ref", "renamed@{upstream}"]), "origin/unmerged\n");
+        assert_eq!(on_remote(&test), "main merged unmerged");
+
+        for (new_name, existing) in [("bad..name", "renamed"), ("main", "renamed"), ("x", "missing")] {
+            let err = rename_branch(test.path(), existing.into(), new_name.into(), None).await.unwrap_err();
+            let expected = matches!(err, GitError::InvalidSelection { .. } | GitError::RefNotFound { .. });
+            assert!(expected, "{}: {:?}", new_name, err);
+        }
+        test.git(&["branch", "--unset-upstream", "merged"]);
+        let err = rename_branch(test.path(), "merged".into(), "x".into(), Some(true)).await.unwrap_err();
+        assert!(matches!(err, GitError::RefNotFound { .. }), "{:?}", err);
+        assert_eq!(test.git(&["branch", "--format=%(refname:short)"]), "main\nmerged\nrenamed\n");
+    }
+
+    #[tokio::test]
+    async fn renames_on_the_remote_and_tracks_the_new_name_on_both_backends() {
+        for backend in ["git2", "cli"] {
+            let (test, _origin) = branches_on(backend);
+            let message = rename_branch(test.path(), "merged".into(), "done".into(), Some(true))
+                .await
+                .unwrap();
+            assert!(message.lines().count() > 1, "{}", message);
+            assert_eq!(on_remote(&test), "done main unmerged", "{}", backend);
+            assert_eq!(test.git(&["rev-parse", "--abbrev-ref", "done@{upstream}"]), "origin/done\n");
+            assert_eq!(
+                test.git(&["branch", "-r", "--format=%(refname:short)"]),
+                "origin/done\norigin/main\norigin/unmerged\n",
+                "{}",
+                backend
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn undoes_the_local_rename_when_the_push_fails() {
+        for backend in ["git2", "cli"] {
+            let (test, origin) = branches_on(backend);
+            test.git(&["switch", "merged"]);
+            drop(origin);
+
+            let err = rename_branch(test.path(), "merged".into(), "done".into(), Some(true)).await;
+            assert!(err.is_err(), "{}", backend);
+            assert_eq!(test.git(&["branch", "--show-current"]), "merged\n", "{}", backend);
+            assert_eq!(test.git(&["branch", "--format=%(refname:short)"]), "main\nmerged\nunmerged\n");
+            assert_eq!(test.git(&["rev-parse", "--abbrev-ref", "merged@{upstream}"]), "origin/merged\n");
+        }
+    }
+
+    #[tokio::test]
+    async fn sets_and_unsets_upstreams() {
+        let (test, _origin) = branches_on("git2");
+        set_branch_upstream(test.path(), "merged".into(), Some("origin/main".into())).await.unwrap();
+        assert_eq!(test.git(&["rev-parse", "--abbrev-ref", "merged@{upstream}"]), "origin/main\n");
+
+        let err = set_branch_upstream(test.path(), "merged".into(), Some("origin/missing".into()))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GitError::RefNotFound { .. }), "{:?}", err);
+        assert_eq!(test.git(&["rev-parse", "--abbrev-ref", "merged@{upstream}"]), "origin/main\n");
+
+        // unsetting twice is not an error
+        for _ in 0..2 {
+            set_branch_upstream(test.path(), "merged".into(), None).await.unwrap();
+            assert!(!test.git_unchecked(&["rev-parse", "merged@{upstream}"]).status.success());
+        }
+    }
+}
@@ -0,0 +1,353 @@
+// switching with local changes in the way: the files that block the switch are found before
+// anything is touched, then the changes are kept out, carried over, stashed and reapplied, or
+// discarded, as the caller chooses
+use crate::backend::libgit2::{checkout_target, checkout_tree, conflicted_paths, dirty_files, head_tree, switch_head};
+use crate::error::GitError;
+use crate::gitfunction::open_repository;
+use crate::snapshot;
+use git2::{
+    build::CheckoutBuilder, Commit, ErrorCode, Oid, Repository, StashApplyOptions, StashFlags, Status, StatusOptions,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+// what to do with local changes when switching
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DirtyStrategy {
+    // switch only from a clean working tree
+    Abort,
+    // keep the changes across the switch like `git checkout`, refusing when they are in the way
+    #[default]
+    Carry,
+    // stash the changes, switch and reapply them; the stash is kept when reapplying conflicts
+    Stash,
+    // overwrite the changes in the way, after a safety snapshot
+    Force,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckoutResult {
+    // whether HEAD moved to the target
+    switched: bool,
+    strategy: DirtyStrategy,
+    // the branch checked out afterwards, None when HEAD is detached
+    branch: Option<String>,
+    head: Option<String>,
+    // tracked files with local changes before the switch
+    dirty_files: Vec<String>,
+    // changed or untracked files the switch would have overwritten
+    blocking_files: Vec<String>,
+    // the stash left behind when reapplying it conflicted, and the files it conflicted in
+    stash: Option<String>,
+    conflicts: Vec<String>,
+    message: String,
+}
+
+// the files a checkout of `commit` would have to overwrite, by git's rule: local changes to files
+// that differ between HEAD and the target, and untracked files where the target has one
+fn blocking_files(repo: &Repository, commit: &Commit) -> Result<Vec<String>, GitError> {
+    let head = head_tree(repo)?;
+    let diff = repo.diff_tree_to_tree(head.as_ref(), Some(&commit.tree()?), None)?;
+    let mut changed = HashSet::new();
+    for delta in diff.deltas() {
+        changed.extend(delta.old_file().path().map(Path::to_path_buf));
+        changed.extend(delta.new_file().path().map(Path::to_path_buf));
+    }
+
+    let mut opts = StatusOptions::new();
+    opts.include_untracked(true).recurse_untracked_dirs(true).include_ignored(false);
+    let statuses = repo.statuses(Some(&mut opts))?;
+    Ok(statuses
+        .iter()
+        .filter(|entry| entry.status() != Status::CURRENT)
+        .filter_map(|entry| entry.path().map(str::to_string))
+        .filter(|path| changed.contains(Path::new(path)))
+        .collect())
+}
+
+fn head_state(repo: &Repository) -> (Option<String>, Option<String>) {
+    let Ok(head) = repo.head() else {
+        return (None, None);
+    };
+    let branch = head.is_branch().then(|| head.shorthand().map(str::to_string)).flatten();
+    (branch, head.target().map(|id| id.to_string()))
+}
+
+// reapply the stash on top of the new HEAD, dropping it only when it applied without conflicts
+fn reapply(repo: &mut Repository, stash: Oid, result: &mut CheckoutResult) -> Result<(), GitError> {
+    let mut options = StashApplyOptions::new();
+    options.reinstantiate_index();
+    let applied = repo.stash_apply(0, Some(&mut options));
+
+    let conflicts = conflicted_paths(&repo.index()?)?;
+    match applied {
+        Ok(()) if conflicts.is_empty() => {
+            repo.stash_drop(0)?;
+            result.message.push_str("\nYour local changes were stashed and reapplied.");
+        }
+        Err(err) if conflicts.is_empty() && err.code() != ErrorCode::Conflict => return Err(err.into()),
+        _ => {
+            result.stash = Some(stash.to_string());
+            result.message.push_str(&format!(
+                "\nReapplying your local changes conflicted; they are kept in the stash {}.",
+                &stash.to_string()[..7]
+            ));
+            result.conflicts = conflicts;
+        }
+    }
+    Ok(())
+}
+
+fn switch(
+    directory: &str,
+    target: &str,
+    strategy: DirtyStrategy,
+    result: &mut CheckoutResult,
+) -> Result<(), GitError> {
+    let mut repo = open_repository(directory)?;
+    result.dirty_files = dirty_files(&repo)?;
+    result.blocking_files = blocking_files(&repo, &checkout_target(&repo, target)?.commit()?)?;
+
+    let refuse = match strategy {
+        DirtyStrategy::Abort => !result.dirty_files.is_empty() || !result.blocking_files.is_empty(),
+        DirtyStrategy::Carry => !result.blocking_files.is_empty(),
+        DirtyStrategy::Stash | DirtyStrategy::Force => false,
+    };
+    if refuse {
+        result.message = if result.blocking_files.is_empty() {
+            format!(
+                "You have local changes to the following files:\n\t{}\n\
+                 Please commit, stash or discard them before you switch branches.",
+                result.dirty_files.join("\n\t")
+            )
+        } else {
+            format!(
+                "Your local changes to the following files would be overwritten by checkout:\n\t{}\n\
+                 Please commit your changes or stash them before you switch branches.",
+                result.blocking_files.join("\n\t")
+            )
+        };
+        return Ok(());
+    }
+
+    let mut stash = None;
+    if strategy == DirtyStrategy::Stash && !(result.dirty_files.is_empty() && result.blocking_files.is_empty()) {
+        // untracked files only need stashing when they are in the way
+        let untracked_in_way = result.blocking_files.iter().any(|file| !result.dirty_files.contains(file));
+        let flags = if untracked_in_way {
+            StashFlags::INCLUDE_UNTRACKED
+        } else {
+            StashFlags::DEFAULT
+        };
+        let signature = snapshot::signature(&repo)?;
+        let message = format!("autostash before checking out {}", target);
+        stash = Some(repo.stash_save(&signature, &message, Some(flags))?);
+    }
+    if strategy == DirtyStrategy::Force {
+        snapshot::take(directory, "force checkout")?;
+    }
+
+    let switched = {
+        let checkout = checkout_target(&repo, target)?;
+        let commit = checkout.commit()?;
+        let written = if strategy == DirtyStrategy::Force {
+            let mut builder = CheckoutBuilder::new();
+            builder.force();
+            repo.checkout_tree(commit.as_object(), Some(&mut builder)).map_err(GitError::from)
+        } else {
+            checkout_tree(&repo, &commit)
+        };
+        written.and_then(|()| switch_head(&repo, target, checkout))
+    };
+    let message = match switched {
+        Ok(message) => message,
+        Err(err) => {
+            // put the changes back where they were before giving up
+            if stash.is_some() {
+                let mut options = StashApplyOptions::new();
+                options.reinstantiate_index();
+                repo.stash_pop(0, Some(&mut options))?;
+            }
+            return Err(err);
+        }
+    };
+    result.switched = true;
+    result.message = message;
+
+    if let Some(stash) = stash {
+        reapply(&mut repo, stash, result)?;
+    }
+    Ok(())
+}
+
+// a function to switch to a branch, remote branch or commit, handling local changes with the
+// given strategy, `carry` by default; the result lists the files that were in the way
+#[tauri::command]
+pub async fn checkout(
+    directory: String,
+    target: String,
+    strategy: Option<DirtyStrategy>,
+) -> Result<CheckoutResult, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let strategy = strategy.unwrap_or_default();
+        let mut result = CheckoutResult {
+            switched: false,
+            strategy,
+            branch: None,
+            head: None,
+            dirty_files: Vec::new(),
+            blocking_files: Vec::new(),
+            stash: None,
+            conflicts: Vec::new(),
+            message: String::new(),
+        };
+        switch(&directory, &target, strategy, &mut result)?;
+
+        let repo = open_repository(&directory)?;
+        (result.branch, result.head) = head_state(&repo);
+        Ok(result)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    // `main` and `other` differ in `shared`; `same` is the same on both
+    fn two_branches() -> TestRepo {
+        let test = TestRepo::new();
+        test.write("same", "same\n");
+        test.commit_file("shared", "1\n2\n3\n4\n5\n", "first");
+        test.git(&["branch", "other"]);
+        test.commit_file("shared", "one\n2\n3\n4\n5\n", "on main");
+        test
+    }
+
+    async fn run(test: &TestRepo, target: &str, strategy: DirtyStrategy) -> CheckoutResult {
+        checkout(test.path(), target.into(), Some(strategy)).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn resolves_local_branches_then_revisions_then_remote_branches() {
+        let test = two_branches();
+        let _origin = test.remote("origin");
+        let first = test.git(&["rev-parse", "other"]).trim().to_string();
+        test.git(&["push", "origin", "main", "main:feature", "main:v1"]);
+        test.git(&["tag", "v1", &first]);
+        test.git(&["branch", "main-too", "main"]);
+        test.git(&["tag", "main-too", &first]);
+
+        let result = run(&test, "main-too", DirtyStrategy::Carry).await;
+        assert_eq!(result.branch.as_deref(), Some("main-too"));
+
+        // a tag wins over a remote branch of the same name
+        let result = run(&test, "v1", DirtyStrategy::Carry).await;
+        assert_eq!((result.branch, result.head), (None, Some(first.clone())));
+        assert!(test.git_unchecked(&["rev-parse", "--verify", "refs/heads/v1"]).stdout.is_empty());
+
+        let result = run(&test, "feature", DirtyStrategy::Carry).await;
+        assert_eq!(result.branch.as_deref(), Some("feature"));
+        assert_eq!(test.git(&["rev-parse", "--abbrev-ref", "feature@{upstream}"]), "origin/feature\n");
+
+        let result = run(&test, &first[..7], DirtyStrategy::Carry).await;
+        assert_eq!((result.branch, result.head), (None, Some(first)));
+
+        let err = checkout(test.path(), "missing".into(), None).await.unwrap_err();
+        assert!(matches!(err, GitError::RefNotFound { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn aborts_on_any_local_change() {
+        let test = two_branches();
+        test.write("same", "changed\n");
+
+        let result = run(&test, "other", DirtyStrategy::Abort).await;
+        assert!(!result.switched);
+        assert_eq!(result.dirty_files, ["same"]);
+        assert!(result.blocking_files.is_empty());
+        assert!(result.message.contains("same"), "{}", result.message);
+        assert_eq!(result.branch.as_deref(), Some("main"));
+        assert_eq!(test.read("same"), "changed\n");
+    }
+
+    #[tokio::test]
+    async fn carries_changes_unless_they_are_in_the_way() {
+        let test = two_branches();
+        test.write("same", "changed\n");
+        let result = run(&test, "other", DirtyStrategy::Carry).await;
+        assert!(result.switched, "{}", result.message);
+        assert_eq!(result.branch.as_deref(), Some("other"));
+        assert_eq!(test.read("same"), "changed\n");
+
+        // a branch where `untracked` is tracked
+        test.git(&["stash"]);
+        test.git(&["switch", "-c", "with-untracked", "main"]);
+        test.commit_file("untracked", "committed\n", "untracked is tracked here");
+        test.git(&["switch", "other"]);
+        test.git(&["stash", "pop"]);
+        test.write("shared", "mine\n");
+        test.write("untracked", "new\n");
+
+        let result = run(&test, "with-untracked", DirtyStrategy::Carry).await;
+        assert!(!result.switched);
+        assert_eq!(result.blocking_files, ["shared", "untracked"]);
+        assert!(result.message.contains("would be overwritten"), "{}", result.message);
+        assert_eq!(result.branch.as_deref(), Some("other"));
+        assert_eq!(test.read("shared"), "mine\n");
+        assert_eq!(test.read("untracked"), "new\n");
+    }
+
+    #[tokio::test]
+    async fn stashes_and_reapplies_changes_keeping_what_was_staged() {
+        let test = two_branches();
+        test.write("same", "staged\n");
+        test.stage_all();
+        test.write("shared", "1\n2\n3\n4\nfive\n");
+
+        let result = run(&test, "other", DirtyStrategy::Stash).await;
+        assert!(result.switched, "{}", result.message);
+        assert_eq!(result.blocking_files, ["shared"]);
+        assert_eq!((result.stash, result.conflicts.len()), (None, 0));
+        assert_eq!(result.branch.as_deref(), Some("other"));
+        assert_eq!(test.read("shared"), "1\n2\n3\n4\nfive\n");
+        assert_eq!(test.staged("same").as_deref(), Some("staged\n"));
+        assert_eq!(test.staged("shared").as_deref(), Some("1\n2\n3\n4\n5\n"));
+        assert_eq!(test.git(&["stash", "list"]), "");
+    }
+
+    #[tokio::test]
+    async fn keeps_the_stash_when_reapplying_conflicts() {
+        let test = two_branches();
+        test.write("shared", "mine\n2\n3\n4\n5\n");
+
+        let result = run(&test, "other", DirtyStrategy::Stash).await;
+        assert!(result.switched);
+        assert_eq!(result.conflicts, ["shared"]);
+        let stash = result.stash.unwrap();
+        assert_eq!(test.git(&["rev-parse", "stash@{0}"]).trim(), stash);
+        assert!(test.read("shared").contains("<<<<<<<"));
+    }
+
+    #[tokio::test]
+    async fn forces_the_switch_after_a_snapshot() {
+        let test = two_branches();
+        test.write("shared", "mine\n");
+
+        let result = run(&test, "other", DirtyStrategy::Force).await;
+        assert!(result.switched);
+        assert_eq!(result.blocking_files, ["shared"]);
+        assert_eq!(test.read("shared"), "1\n2\n3\n4\n5\n");
+
+        let snapshots = snapshot::list_snapshots(test.path()).await.unwrap();
+        let snapshot = serde_json::to_value(&snapshots[0]).unwrap();
+        assert_eq!(snapshot["operation"], "force checkout");
+        snapshot::restore_snapshot(test.path(), snapshot["name"].as_str().unwrap().into())
+            .await
+            .unwrap();
+        assert_eq!(test.read("shared"), "mine\n");
+    }
+}
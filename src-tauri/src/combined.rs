@@ -0,0 +1,784 @@
+// the combined diff of a merge, what `git show --cc` prints: every file the merge changed relative
+// to all of its parents, with one column per parent. Hunks where the result simply takes one
+// side are left out, so what remains is where the merge resolved a conflict or changed things itself.
+use crate::backend::libgit2::diff_options;
+use crate::backend::DiffSettings;
+use crate::diff::LineOrigin;
+use crate::error::GitError;
+use crate::gitfunction::{find_commit, open_repository};
+use git2::{DiffLineType, ObjectType, Oid, Patch, Repository, Tree};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+// beyond this many line pairs the lines lost before one result line are listed as they come
+// rather than matched up
+const COALESCE_BUDGET: usize = 250_000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CombinedLine {
+    // one column per parent: added since that parent, deleted since it, or neither
+    pub origins: Vec<LineOrigin>,
+    // None for a line only parents have
+    pub new_lineno: Option<u32>,
+    pub content: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LineRange {
+    pub start: u32,
+    pub lines: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CombinedHunk {
+    // the full `@@@ -a,b -c,d +e,f @@@` line
+    pub header: String,
+    // the range the hunk covers in each parent
+    pub parents: Vec<LineRange>,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<CombinedLine>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CombinedFileDiff {
+    pub path: String,
+    // "added", "deleted" or "modified"
+    pub status: &'static str,
+    // the blob in each parent, all zeroes where the parent has no such file
+    pub parent_ids: Vec<String>,
+    pub new_id: String,
+    pub binary: bool,
+    // binary only because a side is over the size limit, so no content was compared
+    pub too_large: bool,
+    pub hunks: Vec<CombinedHunk>,
+}
+
+// a line some parents had that the result dropped
+#[derive(Clone)]
+struct LostLine {
+    content: String,
+    parents: Vec<bool>,
+}
+
+// a line of the result and the lines lost just before it; the slot after the last line only
+// holds lines lost at the end of the file
+struct Slot {
+    content: Option<String>,
+    // which parents the line was added since
+    added: Vec<bool>,
+    lost: Vec<LostLine>,
+    interesting: bool,
+    shown: bool,
+    // a leading context line, whose lost lines belong to a hunk that was left out
+    hide_lost: bool,
+}
+
+// the paths that differ between a parent and the merge
+fn changed_paths(
+    repo: &Repository,
+    parent: &Tree,
+    tree: &Tree,
+    path: Option<&str>,
+    settings: &DiffSettings,
+) -> Result<BTreeSet<String>, GitError> {
+    let mut opts = diff_options(path, settings)?;
+    let diff = repo.diff_tree_to_tree(Some(parent), Some(tree), Some(&mut opts))?;
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| {
+            delta
+                .new_file()
+                .path()
+                .or(delta.old_file().path())
+                .map(Path::to_path_buf)
+        })
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+// a file's blob id and contents, None for a blob over the size limit
+type Side = (Oid, Option<Vec<u8>>);
+
+// a file in a tree; a submodule has no contents here, and a blob over `max_size` bytes is read no
+// further than its header
+fn file_at(repo: &Repository, tree: &Tree, path: &str, max_size: u64) -> Result<Option<Side>, GitError> {
+    let Ok(entry) = tree.get_path(Path::new(path)) else {
+        return Ok(None);
+    };
+    match entry.kind() {
+        Some(ObjectType::Blob) => {
+            let (size, _) = repo.odb()?.read_header(entry.id())?;
+            if size as u64 > max_size {
+                return Ok(Some((entry.id(), None)));
+            }
+            Ok(Some((entry.id(), Some(repo.find_blob(entry.id())?.content().to_vec()))))
+        }
+        Some(ObjectType::Commit) => Ok(Some((entry.id(), Some(Vec::new())))),
+        _ => Ok(None),
+    }
+}
+
+// drop the trailing newline, keeping any carriage return, as the structured diffs do
+fn line_text(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    text.strip_suffix('\n').unwrap_or(&text).to_string()
+}
+
+// merge the lines a parent lost before one result line into those other parents lost there. A line
+// lost from several parents is listed once; the longest common run is shared, the rest keep their
+// order with the lines already listed first.
+fn coalesce(lost: &[LostLine], lines: &[String], parent: usize, parent_count: usize) -> Vec<LostLine> {
+    let matches = |line: &LostLine, content: &String| !line.parents[parent] && line.content == *content;
+    let lost_from_parent = |content: &String| {
+        let mut parents = vec![false; parent_count];
+        parents[parent] = true;
+        LostLine {
+            content: content.clone(),
+            parents,
+        }
+    };
+    if lost.len() * lines.len() > COALESCE_BUDGET {
+        return lost.iter().cloned().chain(lines.iter().map(lost_from_parent)).collect();
+    }
+
+    // common[i][j]: the longest common subsequence of lost[i..] and lines[j..]
+    let mut common = vec![vec![0usize; lines.len() + 1]; lost.len() + 1];
+    for i in (0..lost.len()).rev() {
+        for j in (0..lines.len()).rev() {
+            common[i][j] = if matches(&lost[i], &lines[j]) {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut merged = Vec::with_capacity(lost.len() + lines.len());
+    let (mut i, mut j) = (0, 0);
+    while i < lost.len() || j < lines.len() {
+        let shared = i < lost.len()
+            && j < lines.len()
+            && matches(&lost[i], &lines[j])
+            && common[i][j] == common[i + 1][j + 1] + 1;
+        if shared {
+            let mut line = lost[i].clone();
+            line.parents[parent] = true;
+            merged.push(line);
+            i += 1;
+            j += 1;
+        } else if i < lost.len() && (j == lines.len() || common[i + 1][j] >= common[i][j + 1]) {
+            merged.push(lost[i].clone());
+            i += 1;
+        } else {
+            merged.push(lost_from_parent(&lines[j]));
+            j += 1;
+        }
+    }
+    merged
+}
+
+// mark what each parent's diff against the result added and lost, with the whitespace and
+// algorithm settings applied; false when the file is binary
+fn compare(
+    slots: &mut [Slot],
+    parent: usize,
+    parent_count: usize,
+    old: &[u8],
+    new: &[u8],
+    path: &str,
+    settings: &DiffSettings,
+) -> Result<bool, GitError> {
+    let mut opts = diff_options(None, settings)?;
+    opts.context_lines(0);
+    let patch = Patch::from_buffers(old, Some(Path::new(path)), new, Some(Path::new(path)), Some(&mut opts))?;
+    if patch.delta().flags().is_binary() {
+        return Ok(false);
+    }
+
+    for index in 0..patch.num_hunks() {
+        let (hunk, line_count) = patch.hunk(index)?;
+        // lines removed with nothing added in their place were lost after line new_start
+        let bucket = if hunk.new_lines() == 0 {
+            hunk.new_start()
+        } else {
+            hunk.new_start() - 1
+        } as usize;
+        let mut deleted = Vec::new();
+
+        for line in 0..line_count {
+            let line = patch.line_in_hunk(index, line)?;
+            match line.origin_value() {
+                DiffLineType::Addition => {
+                    if let Some(lineno) = line.new_lineno() {
+                        slots[lineno as usize - 1].added[parent] = true;
+                    }
+                }
+                DiffLineType::Deletion => deleted.push(line_text(line.content())),
+                _ => {}
+            }
+        }
+        slots[bucket].lost = coalesce(&slots[bucket].lost, &deleted, parent, parent_count);
+    }
+    Ok(true)
+}
+
+// when the last line of a run is only interesting for what was lost before it, it does not
+// count towards the trailing context, as lost lines are shown before their line
+fn adjust_tail(slots: &[Slot], begin: usize, end: usize) -> usize {
+    if begin < end && !slots[end - 1].added.contains(&true) {
+        end - 1
+    } else {
+        end
+    }
+}
+
+// drop the runs of changes where the parents have only two versions and the result takes one of
+// them unchanged: every change in the run is against the same set of parents, and not all of them.
+// `context` is the number of context lines the hunks get
+fn drop_uninteresting(slots: &mut [Slot], context: usize) {
+    let len = slots.len();
+    let mut begin = 0;
+    while begin < len {
+        if !slots[begin].interesting {
+            begin += 1;
+            continue;
+        }
+
+        // runs closer together than the context would be shown as one hunk, so they are judged as one
+        let mut end = begin + 1;
+        while end < len {
+            if slots[end].interesting {
+                end += 1;
+                continue;
+            }
+            let lookahead = (adjust_tail(slots, begin, end) + context).min(len);
+            match (end..lookahead).rev().find(|&index| slots[index].interesting) {
+                Some(index) => end = index + 1,
+                None => break,
+            }
+        }
+
+        let mut same: Option<&[bool]> = None;
+        let mut varied = false;
+        for slot in &slots[begin..end] {
+            let lost = slot.lost.iter().map(|line| line.parents.as_slice());
+            let changes = std::iter::once(slot.added.as_slice()).filter(|added| added.contains(&true));
+            for parents in changes.chain(lost) {
+                match same {
+                    None => same = Some(parents),
+                    Some(same) if same != parents => varied = true,
+                    _ => {}
+                }
+            }
+        }
+        let against_all = same.is_some_and(|same| same.iter().all(|parent| *parent));
+        if !varied && !against_all {
+            slots[begin..end].iter_mut().for_each(|slot| slot.interesting = false);
+        }
+        begin = end;
+    }
+}
+
+// show the interesting lines with `context` lines around them, joining runs whose gap is small
+fn give_context(slots: &mut [Slot], context: usize) {
+    let len = slots.len();
+    slots.iter_mut().for_each(|slot| slot.shown = slot.interesting);
+    let next = |slots: &[Slot], from: usize, shown: bool| {
+        (from..len).find(|&index| slots[index].shown == shown).unwrap_or(len)
+    };
+
+    let mut begin = next(slots, 0, true);
+    while begin < len {
+        for slot in &mut slots[begin.saturating_sub(context)..begin] {
+            slot.hide_lost |= !slot.shown;
+            slot.shown = true;
+        }
+
+        loop {
+            let end = next(slots, begin, false);
+            if end >= len {
+                return;
+            }
+            let following = next(slots, end, true);
+            let end = adjust_tail(slots, begin, end);
+
+            if following < end + context {
+                slots[end..following].iter_mut().for_each(|slot| slot.shown = true);
+                begin = following;
+                continue;
+            }
+            slots[end..(end + context).min(len)]
+                .iter_mut()
+                .for_each(|slot| slot.shown = true);
+            begin = following;
+            break;
+        }
+    }
+}
+
+// cut the shown slots into hunks; `before[parent][slot]` counts the parent's lines ahead of a slot
+fn hunks(slots: &[Slot], parent_count: usize) -> Vec<CombinedHunk> {
+    let mut before = vec![vec![0u32; slots.len() + 1]; parent_count];
+    for (parent, counts) in before.iter_mut().enumerate() {
+        for (index, slot) in slots.iter().enumerate() {
+            let lost = slot.lost.iter().filter(|line| line.parents[parent]).count() as u32;
+            let kept = u32::from(slot.content.is_some() && !slot.added[parent]);
+            counts[index + 1] = counts[index] + lost + kept;
+        }
+    }
+
+    let mut hunks = Vec::new();
+    let mut index = 0;
+    while index < slots.len() {
+        if !slots[index].shown {
+            index += 1;
+            continue;
+        }
+        let begin = index;
+        while index < slots.len() && slots[index].shown {
+            index += 1;
+        }
+
+        let mut lines = Vec::new();
+        for (lineno, slot) in slots.iter().enumerate().take(index).skip(begin) {
+            if !slot.hide_lost {
+                for line in &slot.lost {
+                    lines.push(CombinedLine {
+                        origins: line
+                            .parents
+                            .iter()
+                            .map(|&lost| {
+                                if lost {
+                                    LineOrigin::Deletion
+                                } else {
+                                    LineOrigin::Context
+                                }
+                            })
+                            .collect(),
+                        new_lineno: None,
+                        content: line.content.clone(),
+                    });
+                }
+            }
+            if let Some(content) = &slot.content {
+                lines.push(CombinedLine {
+                    origins: slot
+                        .added
+                        .iter()
+                        .map(|&added| {
+                            if added {
+                                LineOrigin::Addition
+                            } else {
+                                LineOrigin::Context
+                            }
+                        })
+                        .collect(),
+                    new_lineno: Some(lineno as u32 + 1),
+                    content: content.clone(),
+                });
+            }
+        }
+
+        let parents: Vec<LineRange> = (0..parent_count)
+            .map(|parent| {
+                let hidden = if slots[begin].hide_lost {
+                    slots[begin].lost.iter().filter(|line| line.parents[parent]).count() as u32
+                } else {
+                    0
+                };
+                let count = lines
+                    .iter()
+                    .filter(|line| line.origins[parent] != LineOrigin::Addition)
+                    .filter(|line| line.new_lineno.is_some() || line.origins[parent] == LineOrigin::Deletion)
+                    .count() as u32;
+                LineRange {
+                    start: before[parent][begin] + hidden + 1,
+                    lines: count,
+                }
+            })
+            .collect();
+        let new_lines = lines.iter().filter(|line| line.new_lineno.is_some()).count() as u32;
+        let new_start = begin as u32 + 1;
+
+        let marker = "@".repeat(parent_count + 1);
+        let ranges: String = parents
+            .iter()
+            .map(|range| format!(" -{},{}", range.start, range.lines))
+            .collect();
+        hunks.push(CombinedHunk {
+            header: format!(
+                "{} {} +{},{} {}",
+                marker,
+                ranges.trim_start(),
+                new_start,
+                new_lines,
+                marker
+            ),
+            parents,
+            new_start,
+            new_lines,
+            lines,
+        });
+    }
+    hunks
+}
+
+fn combined_file(
+    repo: &Repository,
+    parents: &[Tree],
+    tree: &Tree,
+    path: &str,
+    settings: &DiffSettings,
+) -> Result<CombinedFileDiff, GitError> {
+    let result = file_at(repo, tree, path, settings.max_file_size())?;
+    let sides = parents
+        .iter()
+        .map(|parent| file_at(repo, parent, path, settings.max_file_size()))
+        .collect::<Result<Vec<_>, GitError>>()?;
+
+    let status = if result.is_none() {
+        "deleted"
+    } else if sides.iter().all(Option::is_none) {
+        "added"
+    } else {
+        "modified"
+    };
+    let too_large = result
+        .iter()
+        .chain(sides.iter().flatten())
+        .any(|(_, content)| content.is_none());
+    let new = result
+        .as_ref()
+        .and_then(|(_, content)| content.as_deref())
+        .unwrap_or_default();
+
+    let text = String::from_utf8_lossy(new);
+    let mut slots: Vec<Slot> = text
+        .split_inclusive('\n')
+        .map(|line| Some(line_text(line.as_bytes())))
+        .chain(std::iter::once(None))
+        .map(|content| Slot {
+            content,
+            added: vec![false; parents.len()],
+            lost: Vec::new(),
+            interesting: false,
+            shown: false,
+            hide_lost: false,
+        })
+        .collect();
+
+    // a file over the size limit is binary without comparing anything
+    let mut binary = too_large;
+    for (parent, side) in sides.iter().enumerate() {
+        if binary {
+            break;
+        }
+        let old = side.as_ref().and_then(|(_, content)| content.as_deref()).unwrap_or_default();
+        if !compare(&mut slots, parent, parents.len(), old, new, path, settings)? {
+            binary = true;
+            break;
+        }
+    }
+
+    let hunks = if binary {
+        Vec::new()
+    } else {
+        for slot in &mut slots {
+            slot.interesting = slot.added.contains(&true) || !slot.lost.is_empty();
+        }
+        let context = settings.context() as usize;
+        drop_uninteresting(&mut slots, context);
+        give_context(&mut slots, context);
+        hunks(&slots, parents.len())
+    };
+
+    let id = |side: &Option<Side>| side.as_ref().map_or(Oid::zero(), |(id, _)| *id).to_string();
+    Ok(CombinedFileDiff {
+        path: path.to_string(),
+        status,
+        parent_ids: sides.iter().map(id).collect(),
+        new_id: id(&result),
+        binary,
+        too_large,
+        hunks,
+    })
+}
+
+pub fn combined_diff(
+    repo: &Repository,
+    commit: &git2::Commit,
+    path: Option<&str>,
+    settings: &DiffSettings,
+) -> Result<Vec<CombinedFileDiff>, GitError> {
+    let tree = commit.tree()?;
+    let parents = commit
+        .parents()
+        .map(|parent| parent.tree())
+        .collect::<Result<Vec<_>, _>>()?;
+    if parents.is_empty() {
+        return Err(GitError::InvalidSelection {
+            message: format!("commit {} has no parents to compare with", commit.id()),
+        });
+    }
+
+    // only files that differ from every parent; the others came unchanged from one side
+    let mut paths = changed_paths(repo, &parents[0], &tree, path, settings)?;
+    for parent in &parents[1..] {
+        let changed = changed_paths(repo, parent, &tree, path, settings)?;
+        paths.retain(|path| changed.contains(path));
+    }
+
+    let mut files = Vec::new();
+    for path in &paths {
+        let file = combined_file(repo, &parents, &tree, path, settings)?;
+        // a file whose every change came from one side has nothing left to show
+        if file.binary || !file.hunks.is_empty() {
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+// a function to get the combined diff of a merge commit, for one file or all of them
+#[tauri::command]
+pub async fn get_combined_diff(
+    directory: String,
+    commit_hash: String,
+    filename: Option<String>,
+    options: Option<DiffSettings>,
+) -> Result<Vec<CombinedFileDiff>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let commit = find_commit(&repo, &commit_hash)?;
+
+        combined_diff(&repo, &commit, filename.as_deref(), &options.unwrap_or_default())
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `lines` lines of a result merged from two parents, none of them changed yet
+    fn slots(lines: usize) -> Vec<Slot> {
+        (0..lines)
+            .map(|line| Some(format!("line {}", line + 1)))
+            .chain(std::iter::once(None))
+            .map(|content| Slot {
+                content,
+                added: vec![false; 2],
+                lost: Vec::new(),
+                interesting: false,
+                shown: false,
+                hide_lost: false,
+            })
+            .collect()
+    }
+
+    fn lost(content: &str, parents: [bool; 2]) -> LostLine {
+        LostLine {
+            content: content.to_string(),
+            parents: parents.to_vec(),
+        }
+    }
+
+    fn mark_interesting(slots: &mut [Slot]) {
+        for slot in slots {
+            slot.interesting = slot.added.contains(&true) || !slot.lost.is_empty();
+        }
+    }
+
+    fn shown(slots: &[Slot]) -> Vec<usize> {
+        (0..slots.len()).filter(|&index| slots[index].shown).collect()
+    }
+
+    #[test]
+    fn drops_changes_taken_from_one_parent() {
+        let mut slots = slots(10);
+        slots[3].added = vec![true, false];
+        slots[3].lost = vec![lost("old 4", [true, false])];
+        mark_interesting(&mut slots);
+
+        drop_uninteresting(&mut slots, 3);
+        assert!(slots.iter().all(|slot| !slot.interesting));
+    }
+
+    #[test]
+    fn keeps_changes_against_every_parent() {
+        let mut slots = slots(10);
+        slots[3].added = vec![true, true];
+        mark_interesting(&mut slots);
+
+        drop_uninteresting(&mut slots, 3);
+        assert!(slots[3].interesting);
+    }
+
+    #[test]
+    fn keeps_a_run_mixing_both_parents() {
+        let mut slots = slots(10);
+        slots[3].added = vec![true, false];
+        slots[5].added = vec![false, true];
+        mark_interesting(&mut slots);
+
+        drop_uninteresting(&mut slots, 3);
+        assert!(slots[3].interesting && slots[5].interesting);
+    }
+
+    #[test]
+    fn judges_distant_runs_apart() {
+        let mut slots = slots(20);
+        slots[3].added = vec![true, false];
+        slots[15].added = vec![true, true];
+        mark_interesting(&mut slots);
+
+        drop_uninteresting(&mut slots, 3);
+        assert!(!slots[3].interesting && slots[15].interesting);
+    }
+
+    #[test]
+    fn gives_context_around_changes() {
+        let mut slots = slots(20);
+        slots[5].added = vec![true, true];
+        slots[16].added = vec![true, true];
+        mark_interesting(&mut slots);
+
+        give_context(&mut slots, 3);
+        assert_eq!(shown(&slots), [2, 3, 4, 5, 6, 7, 8, 13, 14, 15, 16, 17, 18, 19]);
+        assert_eq!(hunks(&slots, 2).len(), 2);
+    }
+
+    #[test]
+    fn joins_changes_whose_context_meets() {
+        let mut slots = slots(20);
+        slots[5].added = vec![true, true];
+        slots[12].added = vec![true, true];
+        mark_interesting(&mut slots);
+
+        give_context(&mut slots, 3);
+        assert_eq!(shown(&slots), (2..=15).collect::<Vec<_>>());
+        assert_eq!(hunks(&slots, 2).len(), 1);
+    }
+
+    #[test]
+    fn hides_lines_lost_before_leading_context() {
+        let mut slots = slots(10);
+        // lost just before line 3, too far from line 6 to be judged with it
+        slots[2].lost = vec![lost("dropped", [true, false])];
+        slots[5].added = vec![true, true];
+        mark_interesting(&mut slots);
+
+        drop_uninteresting(&mut slots, 3);
+        give_context(&mut slots, 3);
+        assert!(!slots[2].interesting && slots[2].shown && slots[2].hide_lost);
+        let hunk = &hunks(&slots, 2)[0];
+        assert!(hunk.lines.iter().all(|line| line.content != "dropped"));
+    }
+
+    #[test]
+    fn coalesces_lines_lost_from_both_parents() {
+        let first = vec![lost("a", [true, false]), lost("b", [true, false])];
+        let merged = coalesce(&first, &["b".to_string(), "c".to_string()], 1, 2);
+
+        let merged: Vec<(&str, &[bool])> = merged
+            .iter()
+            .map(|line| (line.content.as_str(), line.parents.as_slice()))
+            .collect();
+        assert_eq!(
+            merged,
+            [
+                ("a", &[true, false][..]),
+                ("b", &[true, true][..]),
+                ("c", &[false, true][..])
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_lost_lines_in_order_past_the_budget() {
+        let first: Vec<LostLine> = (0..600).map(|line| lost(&line.to_string(), [true, false])).collect();
+        let lines: Vec<String> = (0..600).map(|line| line.to_string()).collect();
+        let merged = coalesce(&first, &lines, 1, 2);
+
+        assert_eq!(merged.len(), 1200);
+        assert!(merged[..600].iter().all(|line| line.parents == [true, false]));
+        assert!(merged[600..].iter().all(|line| line.parents == [false, true]));
+    }
+
+    // a merge of a side changing line 2 and main changing line 9 of a ten line file, where the
+    // merge itself rewrites line 5 as `line5`
+    fn evil_merge(test: &crate::testutil::TestRepo, line5: &str) -> Oid {
+        let lines = |changes: &[(usize, &str)]| {
+            let mut lines: Vec<String> = (1..=10).map(|line| format!("line {}", line)).collect();
+            for (line, content) in changes {
+                lines[line - 1] = content.to_string();
+            }
+            lines.join("\n") + "\n"
+        };
+        test.commit_file("file", &lines(&[]), "base");
+        test.git(&["checkout", "-q", "-b", "side"]);
+        test.commit_file("file", &lines(&[(2, "side")]), "side");
+        test.git(&["checkout", "-q", "main"]);
+        test.commit_file("file", &lines(&[(9, "main")]), "main");
+        test.git(&["merge", "-q", "--no-commit", "side"]);
+        test.write("file", &lines(&[(2, "side"), (5, line5), (9, "main")]));
+        test.git(&["commit", "-q", "-am", "merge"]);
+        test.repo.head().unwrap().target().unwrap()
+    }
+
+    fn headers(files: &[CombinedFileDiff]) -> Vec<&str> {
+        files.iter().flat_map(|file| &file.hunks).map(|hunk| hunk.header.as_str()).collect()
+    }
+
+    // the hunk headers `git show --cc` prints with the given options
+    fn git_headers(test: &crate::testutil::TestRepo, args: &[&str]) -> Vec<String> {
+        let mut command = vec!["show", "--cc", "--format="];
+        command.extend(args);
+        test.git(&command)
+            .lines()
+            .filter_map(|line| line.strip_prefix("@@@ "))
+            // without the section git adds after the ranges
+            .map(|line| format!("@@@ {} @@@", line.split(" @@@").next().unwrap()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn uses_the_context_lines_asked_for() {
+        let test = crate::testutil::TestRepo::new();
+        let merge = evil_merge(&test, "merged");
+
+        for context in [0, 1, 3] {
+            let settings = DiffSettings { context_lines: Some(context), ..Default::default() };
+            let files = get_combined_diff(test.path(), merge.to_string(), None, Some(settings)).await.unwrap();
+            let expected = git_headers(&test, &[&format!("-U{}", context)]);
+            assert_eq!(headers(&files), expected, "-U{}", context);
+        }
+        let files = get_combined_diff(test.path(), merge.to_string(), None, None).await.unwrap();
+        assert_eq!(headers(&files), git_headers(&test, &[]));
+    }
+
+    #[tokio::test]
+    async fn ignores_whitespace_the_settings_ignore() {
+        let test = crate::testutil::TestRepo::new();
+        let merge = evil_merge(&test, "line  5");
+
+        let files = get_combined_diff(test.path(), merge.to_string(), None, None).await.unwrap();
+        assert_eq!(files.len(), 1);
+        let settings = DiffSettings { whitespace: crate::backend::Whitespace::IgnoreChange, ..Default::default() };
+        let files = get_combined_diff(test.path(), merge.to_string(), None, Some(settings)).await.unwrap();
+        assert!(files.is_empty());
+    }
+
+    #[tokio::test]
+    async fn marks_files_over_the_size_asked_for_as_too_large() {
+        let test = crate::testutil::TestRepo::new();
+        let merge = evil_merge(&test, "merged");
+
+        let settings = DiffSettings { max_file_size: Some(16), ..Default::default() };
+        let files = get_combined_diff(test.path(), merge.to_string(), None, Some(settings)).await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].binary && files[0].too_large && files[0].hunks.is_empty());
+    }
+}
@@ -0,0 +1,784 @@
+// diffs as files, hunks and lines, so the UI does not have to parse unified diff text
+use crate::backend::libgit2::{commit_diff, delta_label, diff_options, staged_diff, unstaged_diff};
+use crate::backend::{DiffSettings, InlineDiff};
+use crate::error::GitError;
+use crate::gitfunction::{find_commit, open_repository};
+use crate::blob::utf16;
+use encoding_rs::{UTF_8, WINDOWS_1252};
+use git2::{Diff, DiffDelta, DiffFindOptions, DiffLineType, Oid, Patch, Repository, RevparseMode};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LineOrigin {
+    Context,
+    Addition,
+    Deletion,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffLine {
+    pub origin: LineOrigin,
+    pub old_lineno: Option<u32>,
+    pub new_lineno: Option<u32>,
+    // the line without its trailing newline
+    pub content: String,
+    // set when this is the last line of its side and that file does not end with a newline
+    pub no_newline_at_eof: bool,
+    // the changed parts of a modified line, when inline changes were asked for
+    pub spans: Vec<InlineSpan>,
+}
+
+// a changed stretch within a line, in characters from the start of its content
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct InlineSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffHunk {
+    // the full `@@ -a,b +c,d @@ section` line
+    pub header: String,
+    // the function or section name git prints after the ranges
+    pub section: String,
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<DiffLine>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileDiff {
+    // the new path, or the old one for a deletion
+    pub path: String,
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    pub status: &'static str,
+    pub old_mode: u32,
+    pub new_mode: u32,
+    pub old_id: String,
+    pub new_id: String,
+    pub binary: bool,
+    // blob sizes in bytes, 0 for a missing side
+    pub old_size: u64,
+    pub new_size: u64,
+    // binary only because a side is over the size limit, so no content was compared
+    pub too_large: bool,
+    // how the lines were decoded: "UTF-8", "windows-1252" when some were not UTF-8,
+    // "UTF-16LE" or "UTF-16BE"; None for a binary file
+    pub encoding: Option<&'static str>,
+    pub additions: usize,
+    pub deletions: usize,
+    pub hunks: Vec<DiffHunk>,
+}
+
+// the new side of a comparison: a revision, the index or the working tree
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffTarget {
+    Revision(String),
+    Index,
+    Worktree,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    // the commit on the old side; the merge base in three-dot mode
+    pub base: String,
+    // the commit on the new side, None for the index or the working tree
+    pub target: Option<String>,
+    pub files: Vec<FileDiff>,
+}
+
+fn path_of(file: git2::DiffFile) -> Option<String> {
+    file.path().map(|path| path.to_string_lossy().into_owned())
+}
+
+fn new_file_diff(delta: &DiffDelta) -> FileDiff {
+    let old_path = path_of(delta.old_file());
+    let new_path = path_of(delta.new_file());
+    let path = match delta.status() {
+        git2::Delta::Deleted => old_path.clone(),
+        _ => new_path.clone().or_else(|| old_path.clone()),
+    };
+
+    FileDiff {
+        path: path.unwrap_or_default(),
+        old_path,
+        new_path,
+        status: delta_label(delta.status()),
+        old_mode: u32::from(delta.old_file().mode()),
+        new_mode: u32::from(delta.new_file().mode()),
+        old_id: delta.old_file().id().to_string(),
+        new_id: delta.new_file().id().to_string(),
+        binary: delta.flags().is_binary(),
+        old_size: delta.old_file().size(),
+        new_size: delta.new_file().size(),
+        too_large: false,
+        encoding: None,
+        additions: 0,
+        deletions: 0,
+        hunks: Vec::new(),
+    }
+}
+
+// drop the trailing newline, keeping any carriage return so the line can be written back as is
+fn trim_newline(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    text.strip_suffix('\n').unwrap_or(&text).to_string()
+}
+
+fn diff_hunk(hunk: &git2::DiffHunk) -> DiffHunk {
+    let header = trim_newline(hunk.header());
+    let section = header
+        .splitn(3, "@@")
+        .nth(2)
+        .map(|section| section.trim().to_string())
+        .unwrap_or_default();
+    DiffHunk {
+        header,
+        section,
+        old_start: hunk.old_start(),
+        old_lines: hunk.old_lines(),
+        new_start: hunk.new_start(),
+        new_lines: hunk.new_lines(),
+        lines: Vec::new(),
+    }
+}
+
+// add a line to the file's last hunk. A line that is not UTF-8 is decoded as Windows-1252 rather
+// than losing its characters.
+fn push_line(file: &mut FileDiff, line: &git2::DiffLine) {
+    let origin = match line.origin_value() {
+        DiffLineType::Context => LineOrigin::Context,
+        DiffLineType::Addition => LineOrigin::Addition,
+        DiffLineType::Deletion => LineOrigin::Deletion,
+        // "\ No newline at end of file" belongs to the line before it
+        DiffLineType::ContextEOFNL | DiffLineType::AddEOFNL | DiffLineType::DeleteEOFNL => {
+            if let Some(last) = file.hunks.last_mut().and_then(|hunk| hunk.lines.last_mut()) {
+                last.no_newline_at_eof = true;
+            }
+            return;
+        }
+        _ => return,
+    };
+
+    let content = match std::str::from_utf8(line.content()) {
+        Ok(text) => text.strip_suffix('\n').unwrap_or(text).to_string(),
+        Err(_) => {
+            file.encoding = Some(WINDOWS_1252.name());
+            let (text, _) = WINDOWS_1252.decode_without_bom_handling(line.content());
+            text.strip_suffix('\n').unwrap_or(&text).to_string()
+        }
+    };
+    match origin {
+        LineOrigin::Addition => file.additions += 1,
+        LineOrigin::Deletion => file.deletions += 1,
+        LineOrigin::Context => {}
+    }
+    if let Some(hunk) = file.hunks.last_mut() {
+        hunk.lines.push(DiffLine {
+            origin,
+            old_lineno: line.old_lineno(),
+            new_lineno: line.new_lineno(),
+            content,
+            no_newline_at_eof: false,
+            spans: Vec::new(),
+        });
+    }
+}
+
+// walk a diff with line callbacks and collect it as files, hunks and lines
+pub fn file_diffs(diff: &Diff) -> Result<Vec<FileDiff>, GitError> {
+    let files: RefCell<Vec<FileDiff>> = RefCell::new(Vec::new());
+
+    diff.foreach(
+        &mut |delta, _| {
+            files.borrow_mut().push(new_file_diff(&delta));
+            true
+        },
+        Some(&mut |_, _| {
+            if let Some(file) = files.borrow_mut().last_mut() {
+                file.binary = true;
+            }
+            true
+        }),
+        Some(&mut |_, hunk| {
+            if let Some(file) = files.borrow_mut().last_mut() {
+                file.hunks.push(diff_hunk(&hunk));
+            }
+            true
+        }),
+        Some(&mut |_, _, line| {
+            if let Some(file) = files.borrow_mut().last_mut() {
+                push_line(file, &line);
+            }
+            true
+        }),
+    )?;
+
+    let mut files = files.into_inner();
+    for file in files.iter_mut().filter(|file| !file.binary) {
+        file.encoding.get_or_insert(UTF_8.name());
+    }
+    Ok(files)
+}
+
+// one side of a file in a diff: nothing for a missing side, the blob when the object database has
+// it, otherwise the working tree file the diff hashed
+fn side_bytes(repo: &Repository, id: &str, mode: u32, path: Option<&str>) -> Result<Vec<u8>, GitError> {
+    if mode == 0 {
+        return Ok(Vec::new());
+    }
+    if let Ok(blob) = Oid::from_str(id).and_then(|id| repo.find_blob(id)) {
+        return Ok(blob.content().to_vec());
+    }
+    match (repo.workdir(), path) {
+        (Some(workdir), Some(path)) => Ok(std::fs::read(workdir.join(path))?),
+        _ => Ok(Vec::new()),
+    }
+}
+
+// libgit2 takes the zero bytes of UTF-16 for binary; decode both sides and diff them as text
+fn diff_utf16(repo: &Repository, file: &mut FileDiff, settings: &DiffSettings) -> Result<(), GitError> {
+    let old = side_bytes(repo, &file.old_id, file.old_mode, file.old_path.as_deref())?;
+    let new = side_bytes(repo, &file.new_id, file.new_mode, file.new_path.as_deref())?;
+    let Some(encoding) = utf16(&old).or_else(|| utf16(&new)) else {
+        return Ok(());
+    };
+    // a side that is neither missing nor UTF-16 really is binary
+    if [&old, &new].iter().any(|side| !side.is_empty() && utf16(side).is_none()) {
+        return Ok(());
+    }
+
+    let (old_text, _) = utf16(&old).unwrap_or(encoding).decode_with_bom_removal(&old);
+    let (new_text, _) = utf16(&new).unwrap_or(encoding).decode_with_bom_removal(&new);
+    let mut opts = diff_options(None, settings)?;
+    let patch = Patch::from_buffers(
+        old_text.as_bytes(),
+        file.old_path.as_deref().map(Path::new),
+        new_text.as_bytes(),
+        file.new_path.as_deref().map(Path::new),
+        Some(&mut opts),
+    )?;
+
+    file.binary = false;
+    file.encoding = Some(encoding.name());
+    for index in 0..patch.num_hunks() {
+        let (hunk, line_count) = patch.hunk(index)?;
+        file.hunks.push(diff_hunk(&hunk));
+        for line in 0..line_count {
+            push_line(file, &patch.line_in_hunk(index, line)?);
+        }
+    }
+    Ok(())
+}
+
+// beyond this many token pairs a line pair is left unmarked rather than compared
+const INLINE_BUDGET: usize = 250_000;
+
+// split a line into what inline changes are made of: runs of word characters, runs of
+// whitespace and single other characters, or every character on its own
+fn tokens(content: &str, mode: InlineDiff) -> Vec<&str> {
+    let class = |c: char| {
+        if c.is_alphanumeric() || c == '_' {
+            0
+        } else if c.is_whitespace() {
+            1
+        } else {
+            2
+        }
+    };
+
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut chars = content.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        let joins = |next: char| mode == InlineDiff::Word && class(c) != 2 && class(next) == class(c);
+        if let Some(&(end, next)) = chars.peek() {
+            if joins(next) {
+                continue;
+            }
+            tokens.push(&content[start..end]);
+            start = end;
+        }
+    }
+    if start < content.len() {
+        tokens.push(&content[start..]);
+    }
+    tokens
+}
+
+// the tokens of `old` and `new` missing from the other side, as character spans of each line
+fn changed_spans(old: &str, new: &str, mode: InlineDiff) -> Option<(Vec<InlineSpan>, Vec<InlineSpan>)> {
+    let old_tokens = tokens(old, mode);
+    let new_tokens = tokens(new, mode);
+
+    // the unchanged start and end need no comparing
+    let prefix = old_tokens.iter().zip(&new_tokens).take_while(|(a, b)| a == b).count();
+    let suffix = old_tokens[prefix..]
+        .iter()
+        .rev()
+        .zip(new_tokens[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old_tokens[prefix..old_tokens.len() - suffix];
+    let new_middle = &new_tokens[prefix..new_tokens.len() - suffix];
+    if old_middle.len() * new_middle.len() > INLINE_BUDGET {
+        return None;
+    }
+
+    // common[i][j]: the longest common subsequence of old_middle[i..] and new_middle[j..]
+    let mut common = vec![vec![0u32; new_middle.len() + 1]; old_middle.len() + 1];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            common[i][j] = if old_middle[i] == new_middle[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut old_changed = vec![false; old_tokens.len()];
+    let mut new_changed = vec![false; new_tokens.len()];
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            i += 1;
+            j += 1;
+        } else if i < old_middle.len() && (j == new_middle.len() || common[i + 1][j] >= common[i][j + 1]) {
+            old_changed[prefix + i] = true;
+            i += 1;
+        } else {
+            new_changed[prefix + j] = true;
+            j += 1;
+        }
+    }
+
+    // lines sharing nothing but whitespace are a replacement, not an edit worth marking up
+    let kept = old_tokens
+        .iter()
+        .zip(&old_changed)
+        .any(|(token, changed)| !changed && !token.trim().is_empty());
+    if !kept {
+        return None;
+    }
+
+    Some((spans(&old_tokens, &old_changed), spans(&new_tokens, &new_changed)))
+}
+
+// join the changed tokens into spans, counting characters
+fn spans(tokens: &[&str], changed: &[bool]) -> Vec<InlineSpan> {
+    let mut spans: Vec<InlineSpan> = Vec::new();
+    let mut position = 0;
+    for (token, &changed) in tokens.iter().zip(changed) {
+        let end = position + token.chars().count();
+        if changed {
+            match spans.last_mut() {
+                Some(last) if last.end == position => last.end = end,
+                _ => spans.push(InlineSpan { start: position, end }),
+            }
+        }
+        position = end;
+    }
+    spans
+}
+
+// mark the changed spans of modified lines: in each block of deletions followed by additions,
+// the first deleted line is paired with the first added one, and so on
+fn mark_inline(files: &mut [FileDiff], mode: InlineDiff) {
+    for hunk in files.iter_mut().flat_map(|file| file.hunks.iter_mut()) {
+        let mut index = 0;
+        while index < hunk.lines.len() {
+            let deletions = hunk.lines[index..]
+                .iter()
+                .take_while(|line| line.origin == LineOrigin::Deletion)
+                .count();
+            let additions = hunk.lines[index + deletions..]
+                .iter()
+                .take_while(|line| line.origin == LineOrigin::Addition)
+                .count();
+            if deletions == 0 {
+                index += 1;
+                continue;
+            }
+
+            for pair in 0..deletions.min(additions) {
+                let (old, new) = (index + pair, index + deletions + pair);
+                if let Some((old_spans, new_spans)) =
+                    changed_spans(&hunk.lines[old].content, &hunk.lines[new].content, mode)
+                {
+                    hunk.lines[old].spans = old_spans;
+                    hunk.lines[new].spans = new_spans;
+                }
+            }
+            index += deletions + additions;
+        }
+    }
+}
+
+// the structured diff with the inline changes the settings ask for, files over the size limit
+// marked and UTF-16 text diffed after all
+pub fn structured_diff(repo: &Repository, diff: &Diff, settings: &DiffSettings) -> Result<Vec<FileDiff>, GitError> {
+    let mut files = file_diffs(diff)?;
+    for file in files.iter_mut().filter(|file| file.binary) {
+        if file.old_size.max(file.new_size) > settings.max_file_size() {
+            file.too_large = true;
+        } else {
+            diff_utf16(repo, file, settings)?;
+        }
+    }
+    if let Some(mode) = settings.inline {
+        mark_inline(&mut files, mode);
+    }
+    Ok(files)
+}
+
+// a function to get the structured diff of the working tree or the index, for one file or all of them
+#[tauri::command]
+pub async fn get_working_diff(
+    directory: String,
+    filename: Option<String>,
+    staged: Option<bool>,
+    options: Option<DiffSettings>,
+) -> Result<Vec<FileDiff>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let settings = options.unwrap_or_default();
+        let diff = if staged.unwrap_or(false) {
+            staged_diff(&repo, filename.as_deref(), &settings)?
+        } else {
+            unstaged_diff(&repo, filename.as_deref(), &settings)?
+        };
+
+        structured_diff(&repo, &diff, &settings)
+    })
+    .await?
+}
+
+// a function to get the structured diff a commit introduces, for one file or all of them,
+// against its first parent or the parent at index `parent`
+#[tauri::command]
+pub async fn get_commit_diff(
+    directory: String,
+    commit_hash: String,
+    filename: Option<String>,
+    parent: Option<usize>,
+    options: Option<DiffSettings>,
+) -> Result<Vec<FileDiff>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let commit = find_commit(&repo, &commit_hash)?;
+        let settings = options.unwrap_or_default();
+        let diff = commit_diff(&repo, &commit, parent.unwrap_or(0), filename.as_deref(), &settings)?;
+
+        structured_diff(&repo, &diff, &settings)
+    })
+    .await?
+}
+
+// the old and new side of a comparison. `base` may itself be a range: `a..b` compares a with b
+// and `a...b` compares their merge base with b, as `git diff` reads them.
+fn comparison_sides(
+    repo: &Repository,
+    base: &str,
+    target: Option<DiffTarget>,
+    three_dot: bool,
+) -> Result<(Oid, DiffTarget, bool), GitError> {
+    let spec = repo.revparse(base).map_err(|err| GitError::ref_not_found(base, err))?;
+    if !spec.mode().contains(RevparseMode::RANGE) {
+        let base = find_commit(repo, base)?.id();
+        return Ok((base, target.unwrap_or(DiffTarget::Worktree), three_dot));
+    }
+
+    if target.is_some() {
+        return Err(GitError::InvalidSelection {
+            message: format!("{} is already a range and cannot be compared with another target", base),
+        });
+    }
+    let (Some(from), Some(to)) = (spec.from(), spec.to()) else {
+        return Err(GitError::InvalidSelection {
+            message: format!("{} is missing one side of the range", base),
+        });
+    };
+    let from = from.peel_to_commit()?.id();
+    let to = to.peel_to_commit()?.id();
+    let merge_base = spec.mode().contains(RevparseMode::MERGE_BASE);
+    Ok((from, DiffTarget::Revision(to.to_string()), three_dot || merge_base))
+}
+
+pub fn revision_comparison(
+    repo: &Repository,
+    base: &str,
+    target: Option<DiffTarget>,
+    three_dot: bool,
+    path: Option<&str>,
+    settings: &DiffSettings,
+) -> Result<Comparison, GitError> {
+    let (base, target, three_dot) = comparison_sides(repo, base, target, three_dot)?;
+    let target_commit = match &target {
+        DiffTarget::Revision(revision) => Some(find_commit(repo, revision)?),
+        _ => None,
+    };
+
+    // three-dot compares against where the two sides forked, so only the target's own changes show;
+    // the index and working tree fork from HEAD
+    let base = if three_dot {
+        let tip = match &target_commit {
+            Some(commit) => commit.id(),
+            None => repo.head()?.peel_to_commit()?.id(),
+        };
+        repo.merge_base(base, tip)?
+    } else {
+        base
+    };
+    let base_tree = repo.find_commit(base)?.tree()?;
+
+    let mut opts = diff_options(path, settings)?;
+    let mut diff = match (&target_commit, target) {
+        (Some(commit), _) => repo.diff_tree_to_tree(Some(&base_tree), Some(&commit.tree()?), Some(&mut opts))?,
+        (None, DiffTarget::Index) => repo.diff_tree_to_index(Some(&base_tree), None, Some(&mut opts))?,
+        (None, _) => repo.diff_tree_to_workdir_with_index(Some(&base_tree), Some(&mut opts))?,
+    };
+    diff.find_similar(Some(DiffFindOptions::new().renames(true).copies(true)))?;
+
+    Ok(Comparison {
+        base: base.to_string(),
+        target: target_commit.map(|commit| commit.id().to_string()),
+        files: structured_diff(repo, &diff, settings)?,
+    })
+}
+
+// a function to diff two revisions, or a revision against the index or the working tree, for one
+// file or all of them. With `three_dot` the base is the merge base of the two sides, which shows
+// what the target would bring into the base.
+#[tauri::command]
+pub async fn compare_revisions(
+    directory: String,
+    base: String,
+    target: Option<DiffTarget>,
+    three_dot: Option<bool>,
+    filename: Option<String>,
+    options: Option<DiffSettings>,
+) -> Result<Comparison, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let settings = options.unwrap_or_default();
+        revision_comparison(&repo, &base, target, three_dot.unwrap_or(false), filename.as_deref(), &settings)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    const TWELVE: &str = "fn main() {\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+
+    fn lines(hunk: &DiffHunk) -> Vec<(LineOrigin, Option<u32>, Option<u32>, &str)> {
+        hunk.lines
+            .iter()
+            .map(|line| (line.origin, line.old_lineno, line.new_lineno, line.content.as_str()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn splits_a_working_tree_change_into_hunks_and_numbered_lines() {
+        let test = TestRepo::new();
+        test.commit_file("file.rs", TWELVE, "first");
+        test.write("file.rs", "fn main() {\n2\n3\n4\n5\n6\n7\n8\n9\nten\n11\n12\n");
+
+        let files = get_working_diff(test.path(), None, None, None).await.unwrap();
+        assert_eq!(files.len(), 1);
+        let file = &files[0];
+        assert_eq!((file.path.as_str(), file.status), ("file.rs", "modified"));
+        assert_eq!((file.old_mode, file.new_mode), (0o100644, 0o100644));
+        assert_eq!((file.additions, file.deletions), (1, 1));
+        assert!(!file.binary);
+        assert_eq!(file.encoding, Some("UTF-8"));
+
+        let hunk = &file.hunks[0];
+        assert_eq!(hunk.header, "@@ -7,6 +7,6 @@ fn main() {");
+        assert_eq!(hunk.section, "fn main() {");
+        assert_eq!((hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines), (7, 6, 7, 6));
+        assert_eq!(
+            lines(hunk)[2..5],
+            [
+                (LineOrigin::Context, Some(9), Some(9), "9"),
+                (LineOrigin::Deletion, Some(10), None, "10"),
+                (LineOrigin::Addition, None, Some(10), "ten"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn marks_the_last_line_without_a_newline() {
+        let test = TestRepo::new();
+        test.commit_file("file", "one\ntwo", "first");
+        test.write("file", "one\ntwo\n");
+
+        let files = get_working_diff(test.path(), Some("file".into()), None, None).await.unwrap();
+        let hunk = &files[0].hunks[0];
+        let ends: Vec<_> = hunk.lines.iter().map(|line| (line.content.as_str(), line.no_newline_at_eof)).collect();
+        assert_eq!(ends, [("one", false), ("two", true), ("two", false)]);
+    }
+
+    #[tokio::test]
+    async fn keeps_staged_and_unstaged_changes_apart() {
+        let test = TestRepo::new();
+        test.commit_file("staged", "a\n", "first");
+        test.commit_file("unstaged", "a\n", "second");
+        test.write("staged", "b\n");
+        test.git(&["add", "staged"]);
+        test.write("unstaged", "b\n");
+
+        let staged = get_working_diff(test.path(), None, Some(true), None).await.unwrap();
+        let unstaged = get_working_diff(test.path(), None, Some(false), None).await.unwrap();
+        assert_eq!(staged.iter().map(|file| file.path.as_str()).collect::<Vec<_>>(), ["staged"]);
+        assert_eq!(unstaged.iter().map(|file| file.path.as_str()).collect::<Vec<_>>(), ["unstaged"]);
+    }
+
+    #[tokio::test]
+    async fn diffs_a_commit_and_flags_binary_files() {
+        let test = TestRepo::new();
+        test.commit_file("text", "a\n", "first");
+        test.write("text", "b\n");
+        std::fs::write(test.join("image.bin"), [0u8, 1, 2, 0, 255]).unwrap();
+        let commit = test.commit("second");
+
+        let files = get_commit_diff(test.path(), commit.to_string(), None, None, None).await.unwrap();
+        let binary = files.iter().find(|file| file.path == "image.bin").unwrap();
+        assert_eq!(binary.status, "added");
+        assert!(binary.binary && binary.hunks.is_empty());
+        assert_eq!(binary.encoding, None);
+        assert_eq!(binary.new_size, 5);
+
+        let only = get_commit_diff(test.path(), commit.to_string(), Some("text".into()), None, None).await.unwrap();
+        assert_eq!(only.len(), 1);
+        assert_eq!(
+            lines(&only[0].hunks[0]),
+            [
+                (LineOrigin::Deletion, Some(1), None, "a"),
+                (LineOrigin::Addition, None, Some(1), "b"),
+            ]
+        );
+    }
+
+    fn paths(comparison: &Comparison) -> Vec<(&str, &str)> {
+        comparison.files.iter().map(|file| (file.path.as_str(), file.status)).collect()
+    }
+
+    // main and feature forked from base, each changing a file of its own
+    fn forked() -> (TestRepo, Oid) {
+        let test = TestRepo::new();
+        let base = test.commit_file("base", "base\n", "base");
+        test.git(&["checkout", "-q", "-b", "feature"]);
+        test.commit_file("feature", "feature\n", "feature");
+        test.git(&["checkout", "-q", "main"]);
+        test.commit_file("main", "main\n", "main");
+        (test, base)
+    }
+
+    async fn compare(
+        test: &TestRepo,
+        base: &str,
+        target: Option<DiffTarget>,
+        three_dot: bool,
+    ) -> Result<Comparison, GitError> {
+        compare_revisions(test.path(), base.into(), target, Some(three_dot), None, None).await
+    }
+
+    #[tokio::test]
+    async fn compares_two_branches_directly_or_from_their_merge_base() {
+        let (test, base) = forked();
+        let feature = || Some(DiffTarget::Revision("feature".into()));
+
+        let direct = compare(&test, "main", feature(), false).await.unwrap();
+        assert_eq!(paths(&direct), [("feature", "added"), ("main", "deleted")]);
+        let brought = compare(&test, "main", feature(), true).await.unwrap();
+        assert_eq!(paths(&brought), [("feature", "added")]);
+        assert_eq!(brought.base, base.to_string());
+
+        // a range reads the way `git diff` reads it
+        assert_eq!(paths(&compare(&test, "main..feature", None, false).await.unwrap()), paths(&direct));
+        assert_eq!(paths(&compare(&test, "main...feature", None, false).await.unwrap()), paths(&brought));
+        let err = compare(&test, "main..feature", feature(), false).await.unwrap_err();
+        assert!(matches!(err, GitError::InvalidSelection { .. }));
+    }
+
+    #[tokio::test]
+    async fn compares_a_revision_with_the_index_or_the_working_tree() {
+        let (test, base) = forked();
+        test.write("staged", "staged\n");
+        test.git(&["add", "staged"]);
+        test.write("base", "changed\n");
+
+        let index = compare(&test, &base.to_string(), Some(DiffTarget::Index), false).await.unwrap();
+        assert_eq!(paths(&index), [("main", "added"), ("staged", "added")]);
+        assert_eq!(index.target, None);
+        let worktree = compare(&test, &base.to_string(), None, false).await.unwrap();
+        assert_eq!(paths(&worktree), [("base", "modified"), ("main", "added"), ("staged", "added")]);
+
+        // three-dot against the working tree forks from HEAD
+        let forked = compare(&test, "feature", Some(DiffTarget::Worktree), true).await.unwrap();
+        assert_eq!(paths(&forked), [("base", "modified"), ("main", "added"), ("staged", "added")]);
+    }
+
+    #[tokio::test]
+    async fn detects_renames_and_copies_between_revisions() {
+        let test = TestRepo::new();
+        test.write("source", "1\n2\n3\n4\n5\n6\n7\n8\n");
+        test.write("moved", "a\nb\nc\nd\ne\nf\ng\nh\n");
+        let first = test.commit("first");
+        test.write("source", "1\n2\n3\n4\n5\n6\n7\n8\n9\n");
+        test.write("copy", "1\n2\n3\n4\n5\n6\n7\n8\n");
+        std::fs::remove_file(test.join("moved")).unwrap();
+        test.write("renamed", "a\nb\nc\nd\ne\nf\ng\nh\n");
+        test.commit("second");
+
+        let comparison = compare(&test, &format!("{}..HEAD", first), None, false).await.unwrap();
+        assert_eq!(paths(&comparison), [("copy", "copied"), ("renamed", "renamed"), ("source", "modified")]);
+        assert_eq!(comparison.files[0].old_path.as_deref(), Some("source"));
+    }
+
+    #[tokio::test]
+    async fn reports_an_unknown_revision() {
+        let (test, _) = forked();
+        assert!(matches!(compare(&test, "missing", None, false).await, Err(GitError::RefNotFound { .. })));
+        let missing = Some(DiffTarget::Revision("missing".into()));
+        assert!(matches!(compare(&test, "main", missing, false).await, Err(GitError::RefNotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn applies_context_whitespace_and_inline_settings() {
+        let test = TestRepo::new();
+        test.commit_file("file", TWELVE, "first");
+        test.write("file", "fn main() {\n2\n3\n4\n5\n6 \n7\n8\n9\n10 and more\n11\n12\n");
+        let diff = |settings: DiffSettings| get_working_diff(test.path(), None, None, Some(settings));
+
+        let narrow = diff(DiffSettings { context_lines: Some(0), ..Default::default() }).await.unwrap();
+        let ranges: Vec<_> = narrow[0].hunks.iter().map(|hunk| (hunk.old_start, hunk.old_lines)).collect();
+        assert_eq!(ranges, [(6, 1), (10, 1)]);
+
+        let whitespace = crate::backend::Whitespace::IgnoreAtEol;
+        let ignored = diff(DiffSettings { whitespace, context_lines: Some(0), ..Default::default() }).await.unwrap();
+        assert_eq!(ignored[0].hunks.len(), 1);
+        assert_eq!(ignored[0].hunks[0].old_start, 10);
+
+        let inline = Some(crate::backend::InlineDiff::Word);
+        let words = diff(DiffSettings { inline, ..Default::default() }).await.unwrap();
+        let added = words[0].hunks.iter().flat_map(|hunk| &hunk.lines).find(|line| line.content == "10 and more");
+        let spans: Vec<_> = added.unwrap().spans.iter().map(|span| (span.start, span.end)).collect();
+        assert_eq!(spans, [(2, 11)]);
+
+        let algorithm = crate::backend::DiffAlgorithm::Histogram;
+        let err = diff(DiffSettings { algorithm, ..Default::default() }).await.unwrap_err();
+        assert!(matches!(err, GitError::InvalidSelection { .. }));
+    }
+}
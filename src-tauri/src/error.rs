@@ -0,0 +1,350 @@
+use git2::{ErrorClass, ErrorCode};
+use serde::Serialize;
+use std::fmt;
+use std::process::Output;
+
+// the error returned by every command, serialized as `{ "kind": "...", ... }` for the UI
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GitError {
+    RepoNotFound { path: String, message: String },
+    // a repository libgit2 cannot open, for a command that has no git CLI implementation
+    Unsupported { path: String, message: String },
+    RefNotFound { name: String, message: String },
+    Conflict { files: Vec<String>, stderr: String },
+    AuthRequired { stderr: String },
+    DirtyWorktree { files: Vec<String>, stderr: String },
+    NonFastForward { stderr: String },
+    BranchNotMerged { name: String, message: String },
+    NothingToCommit { message: String },
+    InvalidSelection { message: String },
+    InvalidFilter { message: String },
+    GitBinaryMissing { message: String },
+    CommandFailed { command: String, code: Option<i32>, stderr: String },
+    Git { class: String, code: String, message: String },
+    Io { message: String },
+    Internal { message: String },
+}
+
+impl GitError {
+    // build an error from a finished `git` invocation, classifying it from stderr
+    pub fn from_output(args: &[&str], output: &Output) -> GitError {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        // some commands (merge, pull) report conflicts on stdout
+        let combined = format!("{}\n{}", stdout, stderr);
+
+        if combined.contains("not a git repository") {
+            return GitError::RepoNotFound {
+                path: String::new(),
+                message: stderr,
+            };
+        }
+
+        if combined.contains("CONFLICT") || combined.contains("Automatic merge failed") {
+            return GitError::Conflict {
+                files: conflicted_files(&combined),
+                stderr: combined.trim().to_string(),
+            };
+        }
+
+        if combined.contains("would be overwritten by")
+            || combined.contains("You have unstaged changes")
+            || combined.contains("Your index contains uncommitted changes")
+            || combined.contains("Please commit your changes or stash them")
+        {
+            return GitError::DirtyWorktree {
+                files: blocking_files(&combined),
+                stderr,
+            };
+        }
+
+        if combined.contains("Authentication failed")
+            || combined.contains("could not read Username")
+            || combined.contains("could not read Password")
+            || combined.contains("Permission denied (publickey")
+            || combined.contains("terminal prompts disabled")
+        {
+            return GitError::AuthRequired { stderr };
+        }
+
+        if combined.contains("[rejected]") || combined.contains("non-fast-forward") {
+            return GitError::NonFastForward { stderr };
+        }
+
+        if combined.contains("did not match any file(s) known to git")
+            || combined.contains("unknown revision")
+            || combined.contains("not a valid object name")
+            || combined.contains("Not a valid object name")
+            || combined.contains("invalid reference")
+            || combined.contains("couldn't find remote ref")
+            || combined.contains("is not a commit and a branch")
+        {
+            return GitError::RefNotFound {
+                name: String::new(),
+                message: stderr,
+            };
+        }
+
+        GitError::CommandFailed {
+            command: format!("git {}", args.join(" ")),
+            code: output.status.code(),
+            stderr: if stderr.is_empty() { stdout } else { stderr },
+        }
+    }
+
+    // error for a repository that cannot be opened at `path`
+    pub fn repo_not_found(path: &str, err: git2::Error) -> GitError {
+        GitError::RepoNotFound {
+            path: path.to_string(),
+            message: err.message().to_string(),
+        }
+    }
+
+    // error for a repository only the git CLI can open, given to the commands that need libgit2
+    pub fn unsupported(path: &str, err: git2::Error) -> GitError {
+        GitError::Unsupported {
+            path: path.to_string(),
+            message: format!(
+                "{}: this command needs libgit2, which cannot open the repository, and has no git CLI \
+                 implementation; the gitaurora.backend setting does not change that",
+                err.message()
+            ),
+        }
+    }
+
+    // error for a branch, commit or revision that does not exist
+    pub fn ref_not_found(name: &str, err: git2::Error) -> GitError {
+        GitError::RefNotFound {
+            name: name.to_string(),
+            message: err.message().to_string(),
+        }
+    }
+}
+
+// collect the paths from "CONFLICT (content): Merge conflict in <path>" lines
+fn conflicted_files(text: &str) -> Vec<String> {
+    text.lines()
+        .filter(|line| line.starts_with("CONFLICT"))
+        .filter_map(|line| {
+            line.split_once("Merge conflict in ")
+                .map(|(_, path)| path.trim().to_string())
+        })
+        .collect()
+}
+
+// collect the tab-indented paths git lists under "would be overwritten by ..."
+fn blocking_files(text: &str) -> Vec<String> {
+    text.lines()
+        .skip_while(|line| !line.contains("would be overwritten by"))
+        .skip(1)
+        .take_while(|line| line.starts_with('\t'))
+        .map(|line| line.trim().to_string())
+        .collect()
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitError::RepoNotFound { path, message } => {
+                write!(f, "Repository not found at {}: {}", path, message)
+            }
+            GitError::Unsupported { path, message } => {
+                write!(f, "Unsupported repository at {}: {}", path, message)
+            }
+            GitError::RefNotFound { name, message } => {
+                write!(f, "Reference {} not found: {}", name, message)
+            }
+            GitError::Conflict { files, .. } => {
+                write!(f, "Conflicts in {} file(s): {}", files.len(), files.join(", "))
+            }
+            GitError::AuthRequired { stderr } => write!(f, "Authentication required: {}", stderr),
+            GitError::DirtyWorktree { files, stderr } => {
+                if files.is_empty() {
+                    write!(f, "Local changes block this operation: {}", stderr)
+                } else {
+                    write!(f, "Local changes block this operation: {}", files.join(", "))
+                }
+            }
+            GitError::NonFastForward { stderr } => write!(f, "Rejected (non-fast-forward): {}", stderr),
+            GitError::BranchNotMerged { message, .. } => write!(f, "{}", message),
+            GitError::NothingToCommit { message } => write!(f, "{}", message),
+            GitError::InvalidSelection { message } => write!(f, "Invalid selection: {}", message),
+            GitError::InvalidFilter { message } => write!(f, "Invalid filter: {}", message),
+            GitError::GitBinaryMissing { message } => write!(f, "git executable not found: {}", message),
+            GitError::CommandFailed { command, code, stderr } => match code {
+                Some(code) => write!(f, "`{}` exited with {}: {}", command, code, stderr),
+                None => write!(f, "`{}` was terminated: {}", command, stderr),
+            },
+            GitError::Git { message, .. } => write!(f, "{}", message),
+            GitError::Io { message } => write!(f, "I/O error: {}", message),
+            GitError::Internal { message } => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for GitError {}
+
+impl From<git2::Error> for GitError {
+    fn from(err: git2::Error) -> Self {
+        let message = err.message().to_string();
+        match (err.code(), err.class()) {
+            (ErrorCode::NotFound, ErrorClass::Repository) => GitError::RepoNotFound {
+                path: String::new(),
+                message,
+            },
+            (ErrorCode::NotFound, ErrorClass::Reference)
+            | (ErrorCode::InvalidSpec, _)
+            | (ErrorCode::UnbornBranch, _) => GitError::RefNotFound {
+                name: String::new(),
+                message,
+            },
+            (ErrorCode::MergeConflict, _) => GitError::Conflict {
+                files: Vec::new(),
+                stderr: message,
+            },
+            (ErrorCode::Conflict, _) | (ErrorCode::Uncommitted, _) => GitError::DirtyWorktree {
+                files: Vec::new(),
+                stderr: message,
+            },
+            (ErrorCode::Auth, _) | (ErrorCode::Certificate, _) => GitError::AuthRequired { stderr: message },
+            (ErrorCode::NotFastForward, _) => GitError::NonFastForward { stderr: message },
+            (code, class) => GitError::Git {
+                class: format!("{:?}", class),
+                code: format!("{:?}", code),
+                message,
+            },
+        }
+    }
+}
+
+impl From<std::io::Error> for GitError {
+    fn from(err: std::io::Error) -> Self {
+        GitError::Io {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<tokio::task::JoinError> for GitError {
+    fn from(err: tokio::task::JoinError) -> Self {
+        GitError::Internal {
+            message: err.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::cli::run_git_unchecked;
+    use std::process::ExitStatus;
+
+    #[cfg(unix)]
+    fn exit_status(code: i32) -> ExitStatus {
+        std::os::unix::process::ExitStatusExt::from_raw(code << 8)
+    }
+
+    #[cfg(windows)]
+    fn exit_status(code: i32) -> ExitStatus {
+        std::os::windows::process::ExitStatusExt::from_raw(code as u32)
+    }
+
+    fn classify(stdout: &str, stderr: &str) -> GitError {
+        let output = Output {
+            status: exit_status(1),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        GitError::from_output(&["merge", "feature"], &output)
+    }
+
+    #[test]
+    fn classifies_a_missing_repository() {
+        let err = classify("", "fatal: not a git repository (or any of the parent directories): .git");
+        assert!(matches!(err, GitError::RepoNotFound { .. }));
+    }
+
+    #[test]
+    fn classifies_merge_conflicts_reported_on_stdout() {
+        let stdout = "Auto-merging a.txt\n\
+                      CONFLICT (content): Merge conflict in a.txt\n\
+                      CONFLICT (content): Merge conflict in dir/b c.txt\n\
+                      Automatic merge failed; fix conflicts and then commit the result.";
+        match classify(stdout, "") {
+            GitError::Conflict { files, stderr } => {
+                assert_eq!(files, ["a.txt", "dir/b c.txt"]);
+                assert!(stderr.contains("Automatic merge failed"));
+            }
+            err => panic!("expected a conflict, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn classifies_local_changes_in_the_way() {
+        let stderr = "error: Your local changes to the following files would be overwritten by checkout:\n\
+                      \ta.txt\n\
+                      \tb.txt\n\
+                      Please commit your changes or stash them before you switch branches.\n\
+                      Aborting";
+        match classify("", stderr) {
+            GitError::DirtyWorktree { files, .. } => assert_eq!(files, ["a.txt", "b.txt"]),
+            err => panic!("expected a dirty worktree, got {:?}", err),
+        }
+        let err = classify("", "error: cannot pull with rebase: You have unstaged changes.");
+        assert!(matches!(err, GitError::DirtyWorktree { files, .. } if files.is_empty()));
+    }
+
+    #[test]
+    fn classifies_authentication_failures() {
+        for stderr in [
+            "remote: Invalid username or password.\nfatal: Authentication failed for 'https://example.com/r.git/'",
+            "fatal: could not read Username for 'https://example.com': terminal prompts disabled",
+            "git@example.com: Permission denied (publickey).",
+        ] {
+            assert!(matches!(classify("", stderr), GitError::AuthRequired { .. }), "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn classifies_rejected_pushes() {
+        let stderr = " ! [rejected]        main -> main (non-fast-forward)\n\
+                      error: failed to push some refs to 'origin'";
+        assert!(matches!(classify("", stderr), GitError::NonFastForward { .. }));
+    }
+
+    #[test]
+    fn classifies_unknown_refs() {
+        for stderr in [
+            "error: pathspec 'nope' did not match any file(s) known to git",
+            "fatal: ambiguous argument 'nope': unknown revision or path not in the working tree.",
+            "fatal: Not a valid object name nope",
+            "fatal: invalid reference: nope",
+            "fatal: couldn't find remote ref nope",
+            "fatal: 'nope' is not a commit and a branch 'new' cannot be created from it",
+        ] {
+            assert!(matches!(classify("", stderr), GitError::RefNotFound { .. }), "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn keeps_anything_else_as_the_failed_command() {
+        match classify("only on stdout", "") {
+            GitError::CommandFailed { command, code, stderr } => {
+                assert_eq!(command, "git merge feature");
+                assert_eq!(code, Some(1));
+                assert_eq!(stderr, "only on stdout");
+            }
+            err => panic!("expected a failed command, got {:?}", err),
+        }
+    }
+
+    // the messages above are only recognised untranslated
+    #[test]
+    fn runs_git_in_the_c_locale() {
+        let directory = std::env::temp_dir();
+        let args = ["-c", "alias.locale=!echo $LC_ALL", "locale"];
+        let output = run_git_unchecked(&directory.to_string_lossy(), &args).unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "C");
+    }
+}
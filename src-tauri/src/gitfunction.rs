@@ -1,94 +1,66 @@
-use git2::{Repository, BranchType, Oid, DiffOptions, Status, StatusOptions};
+use git2::{Repository, Oid};
 use serde::Serialize;
-
-#[derive(Serialize)]
-pub struct Branch {
-    name: String,
-    is_remote: bool,
-    is_head: bool,
-}
-
-#[tauri::command]
-pub async fn get_branch_list(directory: String) -> Vec<Branch> {
-    tokio::task::spawn_blocking(move || {
-        let mut branch_list = Vec::new();
-
-        match Repository::open(directory) {
-            Ok(repo) => {
-                if let Ok(branches) = repo.branches(None) {
-                    for branch_result in branches {
-                        if let Ok((branch, branch_type)) = branch_result {
-                            if let Ok(branch_name) = branch.name() {
-                                if let Some(name) = branch_name {
-                                    branch_list.push(Branch {
-                                        name: name.to_string(),
-                                        is_remote: branch_type == BranchType::Remote,
-                                        is_head: branch.is_head(),
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            Err(err) => {
-                eprintln!("Failed to open repository: {}", err);
-            }
+use std::collections::HashMap;
+use crate::error::GitError;
+use crate::backend::{self, unsupported_extension, CommitHeader, DiffSettings, Operation};
+use crate::backend::libgit2::{commit_diff, commit_header, refs_by_commit, similarity};
+use crate::history::{History, LogFilter};
+
+// open the repository at `directory` for the commands that only run on libgit2, reporting a
+// missing one as `RepoNotFound` and one libgit2 does not support, like a partial clone, as `Unsupported`
+pub fn open_repository(directory: &str) -> Result<Repository, GitError> {
+    Repository::open(directory).map_err(|err| {
+        if unsupported_extension(&err) {
+            GitError::unsupported(directory, err)
+        } else {
+            GitError::repo_not_found(directory, err)
         }
-
-        branch_list
     })
-    .await
-    .unwrap_or_else(|_| Vec::new()) // Handle potential panics
 }
 
 #[derive(Serialize)]
 pub struct Commit {
-    id: String,
-    author: String,
-    email: String,
+    #[serde(flatten)]
+    header: CommitHeader,
+    // the whole message, summary and body together
     message: String,
-    timestamp: i64,
 }
 
-#[tauri::command]
-pub async fn get_all_commits_from_branch(directory: String, branch: String) -> Result<Vec<Commit>, String> {
-    // Open the repository
-    let repo = Repository::open(directory).map_err(|err| format!("Failed to open repository: {}", err))?;
-
-    // Find the branch reference
-    let branch_ref = repo.find_reference(&format!("refs/heads/{}", branch))
-        .map_err(|err| format!("Failed to find branch {}: {}", branch, err))?;
-
-    // Resolve the branch reference to an OID (commit hash)
-    let branch_oid = branch_ref.target()
-        .ok_or_else(|| "Branch reference does not point to a valid commit.".to_string())?;
-
-    // Find the commit corresponding to the branch
-    let mut revwalk = repo.revwalk()
-        .map_err(|err| format!("Failed to create revwalk: {}", err))?;
-
-    revwalk.push(branch_oid)
-        .map_err(|err| format!("Failed to push branch OID to revwalk: {}", err))?;
-
-    // Collect commits
-    let mut commits = Vec::new();
-    for oid_result in revwalk {
-        let oid = oid_result.map_err(|err| format!("Failed to get OID from revwalk: {}", err))?;
-        let commit = repo.find_commit(oid)
-            .map_err(|err| format!("Failed to find commit for OID {}: {}", oid, err))?;
-
-        let author = commit.author();
-        commits.push(Commit {
-            id: commit.id().to_string(),
-            author: author.name().unwrap_or("").to_string(),
-            email: author.email().unwrap_or("").to_string(),
+impl Commit {
+    // `refs` is every ref by the commit it points at, from `refs_by_commit`
+    pub fn from_git(commit: &git2::Commit, refs: &HashMap<Oid, Vec<String>>) -> Self {
+        let commit_refs = refs.get(&commit.id()).cloned().unwrap_or_default();
+        Commit {
+            header: commit_header(commit, commit_refs),
             message: commit.message().unwrap_or("").to_string(),
-            timestamp: commit.time().seconds(),
-        });
+        }
     }
+}
 
-    Ok(commits)
+#[tauri::command]
+pub async fn get_all_commits_from_branch(directory: String, branch: String) -> Result<Vec<Commit>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        // Open the repository
+        let repo = open_repository(&directory)?;
+
+        // A local branch wins over a tag of the same name; anything else is read as a revspec,
+        // so remote branches, tags and ranges like `main..feature` work too
+        let revision = if repo.find_reference(&format!("refs/heads/{}", branch)).is_ok() {
+            format!("refs/heads/{}", branch)
+        } else {
+            branch
+        };
+
+        // Collect commits
+        let refs = refs_by_commit(&repo)?;
+        let mut commits = Vec::new();
+        for commit in History::new(&repo, Some(&revision), LogFilter::default())? {
+            commits.push(Commit::from_git(&commit?, &refs));
+        }
+
+        Ok(commits)
+    })
+    .await?
 }
 
 // a function to get all the files changed in a commit
@@ -96,196 +68,469 @@ pub async fn get_all_commits_from_branch(directory: String, branch: String) -> R
 pub struct FileChange {
     path: String,
     status: String,
+    // the source of a rename or copy
+    old_path: Option<String>,
+    // rename or copy similarity, in percent
+    similarity: Option<u32>,
+    insertions: usize,
+    deletions: usize,
+    // file modes, e.g. 0o100644 for a regular file; 0 where the side is missing
+    old_mode: u32,
+    new_mode: u32,
+    // blob sizes in bytes, 0 where the side is missing or not a blob
+    old_size: u64,
+    new_size: u64,
 }
 
-#[tauri::command]
-pub fn get_changed_files_in_commit(directory: String, commit_hash: String) -> Vec<FileChange> {
-    let repo = Repository::open(directory).expect("Failed to open repository");
-    let commit_oid = Oid::from_str(&commit_hash).expect("Invalid commit hash");
-    let commit = repo.find_commit(commit_oid).expect("Failed to find commit");
-
-    let mut file_changes = Vec::new();
-
-    // Check if the commit has a parent
-    if let Ok(parent) = commit.parent(0) {
-        let parent_tree = parent.tree().expect("Failed to get parent tree");
-        let commit_tree = commit.tree().expect("Failed to get commit tree");
-
-        let mut diff_opts = DiffOptions::new();
-        let diff = repo.diff_tree_to_tree(Some(&parent_tree), Some(&commit_tree), Some(&mut diff_opts))
-            .expect("Failed to create diff");
-
-        diff.foreach(&mut |delta, _| {
-            if let Some(path) = delta.new_file().path() {
-                let status = match delta.status() {
-                    git2::Delta::Added => "Added",
-                    git2::Delta::Deleted => "Deleted",
-                    git2::Delta::Modified => "Modified",
-                    git2::Delta::Renamed => "Renamed",
-                    _ => "Other",
-                };
-                file_changes.push(FileChange {
-                    path: path.to_string_lossy().into_owned(),
-                    status: status.to_string(),
-                });
-            }
-            true
-        }, None, None, None).expect("Failed to iterate over diff");
-    } else {
-        // For the first commit, treat all files as added
-        let tree = commit.tree().expect("Failed to get commit tree");
-        tree.walk(git2::TreeWalkMode::PreOrder, |_, entry| {
-            if let Some(path) = entry.name() {
-                file_changes.push(FileChange {
-                    path: path.to_string(),
-                    status: "Added".to_string(),
-                });
-            }
-            git2::TreeWalkResult::Ok
-        }).expect("Failed to walk tree");
+// the size of a blob, read from the object header without loading it; 0 for a missing side or a submodule
+fn blob_size(odb: &git2::Odb, file: &git2::DiffFile) -> u64 {
+    if file.id().is_zero() || file.mode() == git2::FileMode::Commit {
+        return 0;
     }
+    odb.read_header(file.id()).map_or(0, |(size, _)| size as u64)
+}
 
-    file_changes
+impl FileChange {
+    fn new(path: String, status: &str) -> Self {
+        FileChange {
+            path,
+            status: status.to_string(),
+            old_path: None,
+            similarity: None,
+            insertions: 0,
+            deletions: 0,
+            old_mode: 0,
+            new_mode: 0,
+            old_size: 0,
+            new_size: 0,
+        }
+    }
 }
 
-use std::path::Path;
+// find a commit from its full or abbreviated hash
+pub fn find_commit<'repo>(repo: &'repo Repository, commit_hash: &str) -> Result<git2::Commit<'repo>, GitError> {
+    repo.revparse_single(commit_hash)
+        .and_then(|object| object.peel_to_commit())
+        .map_err(|err| GitError::ref_not_found(commit_hash, err))
+}
 
 #[tauri::command]
-pub async fn get_diff_of_file_in_commit(directory: String, commit_hash: String, filename: String) -> Result<String, String> {
-    let show_command = Command::new("git")
-        .current_dir(&directory)
-        .args(&["show", "--pretty=", &commit_hash, "--", &filename])
-        .output()
-        .map_err(|e| format!("Failed to execute git show command: {}", e))?;
-
-    if !show_command.status.success() {
-        return Err(format!("Git command failed: {}",
-            from_utf8(&show_command.stderr).unwrap_or("Unknown error")));
-    }
+pub async fn get_changed_files_in_commit(
+    directory: String,
+    commit_hash: String,
+    parent: Option<usize>,
+    options: Option<DiffSettings>,
+) -> Result<Vec<FileChange>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let commit = find_commit(&repo, &commit_hash)?;
+
+        // renames and copies are detected, and a root commit is diffed against nothing.
+        // A merge is diffed against its first parent unless another is asked for. The settings
+        // decide which lines count as changed and which files are too large to count at all.
+        let settings = options.unwrap_or_default();
+        let diff = commit_diff(&repo, &commit, parent.unwrap_or(0), None, &settings)?;
+        let odb = repo.odb()?;
+
+        let mut file_changes = Vec::new();
+        for (index, delta) in diff.deltas().enumerate() {
+            let status = match delta.status() {
+                git2::Delta::Added => "Added",
+                git2::Delta::Deleted => "Deleted",
+                git2::Delta::Modified => "Modified",
+                git2::Delta::Renamed => "Renamed",
+                git2::Delta::Copied => "Copied",
+                git2::Delta::Typechange => "TypeChanged",
+                _ => "Other",
+            };
+            let old_path = delta.old_file().path().map(|path| path.to_string_lossy().into_owned());
+            let new_path = delta.new_file().path().map(|path| path.to_string_lossy().into_owned());
+            let mut change = FileChange::new(new_path.clone().or(old_path.clone()).unwrap_or_default(), status);
+            change.old_mode = u32::from(delta.old_file().mode());
+            change.new_mode = u32::from(delta.new_file().mode());
+            change.old_size = blob_size(&odb, &delta.old_file());
+            change.new_size = blob_size(&odb, &delta.new_file());
+
+            let patch = git2::Patch::from_diff(&diff, index)?;
+            if matches!(delta.status(), git2::Delta::Renamed | git2::Delta::Copied) {
+                change.old_path = old_path;
+                change.similarity = match &patch {
+                    Some(patch) => Some(similarity(patch)?),
+                    None => Some(100),
+                };
+            }
+            // binary files have no lines to count
+            if let Some(patch) = patch {
+                let (_, insertions, deletions) = patch.line_stats()?;
+                change.insertions = insertions;
+                change.deletions = deletions;
+            }
+            file_changes.push(change);
+        }
 
-    let diff_output = from_utf8(&show_command.stdout)
-        .map_err(|e| format!("Failed to parse git output: {}", e))?;
+        Ok(file_changes)
+    })
+    .await?
+}
 
-    Ok(diff_output.to_string())
+#[tauri::command]
+pub async fn get_diff_of_file_in_commit(
+    directory: String,
+    commit_hash: String,
+    filename: String,
+    parent: Option<usize>,
+    options: Option<DiffSettings>,
+) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let patches = backend::open(&directory, Operation::Diff)?.commit_patches(
+            &commit_hash,
+            parent.unwrap_or(0),
+            Some(&filename),
+            &options.unwrap_or_default(),
+        )?;
+
+        Ok(patches.into_iter().map(|patch| patch.patch).collect())
+    })
+    .await?
 }
 
 // a function to get all the current files changed, added, deleted, or modified
 #[tauri::command]
-pub async fn get_all_changed_files(directory: String) -> Result<Vec<FileChange>, String> {
-    let repo = match Repository::open(Path::new(&directory)) {
-        Ok(repo) => repo,
-        Err(_) => return Err("Failed to open repository".to_string()),
-    };
-
-    let mut opts = StatusOptions::new();
-    opts.include_untracked(true)
-        .recurse_untracked_dirs(true)
-        .include_ignored(false);
-
-    let statuses = match repo.statuses(Some(&mut opts)) {
-        Ok(statuses) => statuses,
-        Err(_) => return Err("Failed to get repository status".to_string()),
-    };
-
-    let mut changes = Vec::new();
-
-    for entry in statuses.iter() {
-        let path = match entry.path() {
-            Some(path) => path.to_string(),
-            None => continue,
-        };
-
-        let status = match entry.status() {
-            Status::INDEX_NEW | Status::WT_NEW => "Added",
-            Status::INDEX_MODIFIED | Status::WT_MODIFIED => "Modified",
-            Status::INDEX_DELETED | Status::WT_DELETED => "Deleted",
-            Status::INDEX_RENAMED | Status::WT_RENAMED => "Renamed",
-            _ => "Other",
-        };
-
-        changes.push(FileChange {
-            path,
-            status: status.to_string(),
-        });
-    }
-
-    Ok(changes)
+pub async fn get_all_changed_files(directory: String) -> Result<Vec<FileChange>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let entries = backend::open(&directory, Operation::Status)?.status(false)?;
+
+        let changes = entries
+            .into_iter()
+            .map(|entry| {
+                // combined flags such as INDEX_MODIFIED | WT_MODIFIED resolve to the staged state first
+                let status = match entry.label() {
+                    "added" | "untracked" => "Added",
+                    "modified" => "Modified",
+                    "typechange" => "TypeChanged",
+                    "deleted" => "Deleted",
+                    "renamed" => "Renamed",
+                    "copied" => "Copied",
+                    "conflicted" => "Conflicted",
+                    _ => "Other",
+                };
+                let mut change = FileChange::new(entry.path, status);
+                change.old_path = entry.old_path;
+                change.similarity = entry.similarity;
+                change
+            })
+            .collect();
+
+        Ok(changes)
+    })
+    .await?
 }
 
-use std::process::Command;
-use std::str::from_utf8;
-
 #[tauri::command]
-pub async fn delete_branch(current_path: String, branch_name: String) -> String {
-    let output = Command::new("git")
-        .arg("branch")
-        .arg("-d")
-        .arg(&branch_name)
-        .current_dir(&current_path)
-        .output()
-        .expect("Failed to execute git command");
-
-    let message = from_utf8(&output.stdout)
-        .expect("Failed to convert output to UTF-8")
-        .to_string();
-
-    message
+pub async fn delete_branch(current_path: String, branch_name: String) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        backend::open(&current_path, Operation::Branch)?.delete_branch(&branch_name, false)
+    })
+    .await?
 }
 
 // a function to get the diff of a file added, changed, or deleted
 #[tauri::command]
-pub async fn get_diff_of_file(directory: String, filename: String) -> String {
-    let changed_files = Command::new("git")
-        .current_dir(&directory)
-        .args(&["status", "--porcelain"])
-        .output()
-        .expect("Failed to execute git command");
-
-    let changed_files_str =
-        from_utf8(&changed_files.stdout).expect("Failed to parse changed files");
-
-    // Check if the file is in the status list
-    let mut file_status = "unknown"; // Default status
-    for line in changed_files_str.lines() {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 2 {
-            continue;
+pub async fn get_diff_of_file(
+    directory: String,
+    filename: String,
+    options: Option<DiffSettings>,
+) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let backend = backend::open(&directory, Operation::Diff)?;
+
+        // Check if the file is in the status list
+        let is_changed = backend.status(false)?.iter().any(|entry| entry.path == filename);
+
+        // Only return diff if the file is modified, added, or untracked
+        if !is_changed {
+            return Ok("No changes detected for this file.".to_string());
         }
-        if parts[1] == filename {
-            file_status = match parts[0] {
-                "M" => "modified",
-                "A" => "added",
-                "D" => "deleted",
-                "R" => "renamed",
-                "??" => "untracked",
-                _ => "unknown",
-            };
-            break;
+
+        // Get the diff for the specified file, untracked files are diffed against nothing
+        backend.unstaged_patch(&filename, &options.unwrap_or_default())
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    const SIX: &str = "1\n2\n3\n4\n5\n6\n";
+
+    fn statuses(changes: &[FileChange]) -> Vec<(&str, &str)> {
+        changes.iter().map(|change| (change.path.as_str(), change.status.as_str())).collect()
+    }
+
+    // the type change is to a symlink
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn labels_each_kind_of_change() {
+        let test = TestRepo::new();
+        test.write("deleted", SIX);
+        test.write("modified", SIX);
+        test.write("moved", SIX);
+        test.write("typechange", SIX);
+        test.commit("first");
+
+        std::fs::remove_file(test.join("deleted")).unwrap();
+        test.write("modified", "changed\n");
+        test.git(&["mv", "moved", "renamed"]);
+        std::fs::remove_file(test.join("typechange")).unwrap();
+        std::os::unix::fs::symlink("modified", test.join("typechange")).unwrap();
+        test.write("untracked", "new\n");
+
+        let mut changes = get_all_changed_files(test.path()).await.unwrap();
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(
+            statuses(&changes),
+            [
+                ("deleted", "Deleted"),
+                ("modified", "Modified"),
+                ("renamed", "Renamed"),
+                ("typechange", "TypeChanged"),
+                ("untracked", "Added"),
+            ]
+        );
+        assert_eq!(changes[2].old_path.as_deref(), Some("moved"));
+    }
+
+    #[tokio::test]
+    async fn labels_a_copy_as_copied() {
+        let test = TestRepo::new();
+        test.commit_file("original", SIX, "first");
+        test.write("copy", SIX);
+        test.write("original", "1\n2\n3\n4\n5\n6\n7\n");
+        test.git(&["add", "-A"]);
+        // only git status reports copies, and only when asked to
+        test.git(&["config", "gitaurora.status.backend", "cli"]);
+        test.git(&["config", "status.renames", "copies"]);
+
+        let changes = get_all_changed_files(test.path()).await.unwrap();
+        let copy = changes.iter().find(|change| change.path == "copy").unwrap();
+        assert_eq!(copy.status, "Copied");
+        assert_eq!(copy.old_path.as_deref(), Some("original"));
+        assert_eq!(copy.similarity, Some(100));
+    }
+
+    async fn history(test: &TestRepo, branch: &str) -> Result<Vec<String>, GitError> {
+        let commits = get_all_commits_from_branch(test.path(), branch.to_string()).await?;
+        Ok(commits.iter().map(|commit| commit.header.id.clone()).collect())
+    }
+
+    // what `git rev-list` itself lists
+    fn rev_list(test: &TestRepo, spec: &str) -> Vec<String> {
+        test.git(&["rev-list", spec]).lines().map(str::to_string).collect()
+    }
+
+    // main and feature forked from base, each one commit ahead
+    fn forked() -> (TestRepo, Oid, Oid, Oid) {
+        let test = TestRepo::new();
+        let base = test.empty_commit(Some("main"), 1, &[]);
+        let main = test.empty_commit(Some("main"), 2, &[base]);
+        let feature = test.empty_commit(Some("feature"), 3, &[base]);
+        (test, base, main, feature)
+    }
+
+    #[tokio::test]
+    async fn reads_remote_branches_tags_and_hashes() {
+        let (test, base, main, feature) = forked();
+        test.repo.reference("refs/remotes/origin/feature", feature, false, "test").unwrap();
+        test.git(&["tag", "-a", "-m", "release", "v1", &main.to_string()]);
+
+        assert_eq!(history(&test, "origin/feature").await.unwrap(), [feature.to_string(), base.to_string()]);
+        assert_eq!(history(&test, "v1").await.unwrap(), [main.to_string(), base.to_string()]);
+        assert_eq!(history(&test, &feature.to_string()[..7]).await.unwrap(), rev_list(&test, "feature"));
+    }
+
+    #[tokio::test]
+    async fn prefers_a_local_branch_over_a_tag_of_the_same_name() {
+        let (test, base, main, feature) = forked();
+        test.repo.reference("refs/tags/feature", main, false, "test").unwrap();
+
+        assert_eq!(history(&test, "feature").await.unwrap(), [feature.to_string(), base.to_string()]);
+        assert_eq!(history(&test, "tags/feature").await.unwrap(), [main.to_string(), base.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn reads_two_and_three_dot_ranges_like_git() {
+        let (test, _, main, feature) = forked();
+
+        assert_eq!(history(&test, "main..feature").await.unwrap(), [feature.to_string()]);
+        assert_eq!(history(&test, "feature..main").await.unwrap(), [main.to_string()]);
+        let mut symmetric = history(&test, "main...feature").await.unwrap();
+        symmetric.sort();
+        let mut expected = rev_list(&test, "main...feature");
+        expected.sort();
+        assert_eq!(symmetric, expected);
+        assert!(history(&test, "main..main").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn reports_an_unknown_revision() {
+        let (test, ..) = forked();
+        assert!(matches!(history(&test, "missing").await, Err(GitError::RefNotFound { .. })));
+        assert!(matches!(history(&test, "main..missing").await, Err(GitError::RefNotFound { .. })));
+    }
+
+    async fn changed(test: &TestRepo, commit: Oid, parent: Option<usize>) -> Vec<FileChange> {
+        get_changed_files_in_commit(test.path(), commit.to_string(), parent, None).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn details_renames_copies_and_line_counts_in_a_commit() {
+        let test = TestRepo::new();
+        let long = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        test.write("moved", "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n");
+        test.write("source", long);
+        test.write("gone", "bye\n");
+        test.commit("first");
+
+        std::fs::remove_file(test.join("moved")).unwrap();
+        test.write("dir/renamed", "a\nb\nc\nd\ne\nf\ng\nh\ni\nten\n");
+        test.write("source", "0\n1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n");
+        test.write("copy", long);
+        std::fs::remove_file(test.join("gone")).unwrap();
+        let commit = test.commit("second");
+
+        let mut changes = changed(&test, commit, None).await;
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|change| {
+                let path = change.path.as_str();
+                (path, change.status.as_str(), change.old_path.as_deref(), change.insertions, change.deletions)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("copy", "Copied", Some("source"), 0, 0),
+                ("dir/renamed", "Renamed", Some("moved"), 1, 1),
+                ("gone", "Deleted", None, 0, 1),
+                ("source", "Modified", None, 1, 0),
+            ]
+        );
+        assert_eq!(changes[0].similarity, Some(100));
+        // what `git show -M` prints as R081
+        assert_eq!(changes[1].similarity, Some(81));
+        let gone = &changes[2];
+        assert_eq!((gone.old_mode, gone.new_mode, gone.old_size, gone.new_size), (0o100644, 0, 4, 0));
+    }
+
+    #[tokio::test]
+    async fn lists_a_merge_against_the_parent_asked_for() {
+        let test = TestRepo::new();
+        test.commit_file("base", "base\n", "base");
+        test.git(&["checkout", "-q", "-b", "side"]);
+        test.commit_file("theirs", "theirs\n", "side");
+        test.git(&["checkout", "-q", "main"]);
+        test.commit_file("ours", "ours\n", "main");
+        test.git(&["merge", "-q", "--no-edit", "side"]);
+        let merge = test.repo.head().unwrap().target().unwrap();
+
+        let paths = |changes: Vec<FileChange>| changes.into_iter().map(|change| change.path).collect::<Vec<_>>();
+        assert_eq!(paths(changed(&test, merge, None).await), ["theirs"]);
+        assert_eq!(paths(changed(&test, merge, Some(1)).await), ["ours"]);
+        let err = get_changed_files_in_commit(test.path(), merge.to_string(), Some(2), None).await.err().unwrap();
+        assert!(matches!(err, GitError::InvalidSelection { .. }));
+    }
+
+    #[tokio::test]
+    async fn counts_no_lines_for_a_binary_file() {
+        let test = TestRepo::new();
+        test.commit_file("text", "a\n", "first");
+        std::fs::write(test.join("image.bin"), [0u8, 1, 2, 0]).unwrap();
+        let commit = test.commit("second");
+
+        let changes = changed(&test, commit, None).await;
+        assert_eq!(changes.len(), 1);
+        let image = &changes[0];
+        assert_eq!((image.status.as_str(), image.insertions, image.deletions), ("Added", 0, 0));
+        assert_eq!((image.old_size, image.new_size, image.new_mode), (0, 4, 0o100644));
+    }
+
+    #[tokio::test]
+    async fn lists_the_root_commit_with_full_paths_modes_and_sizes() {
+        let test = TestRepo::new();
+        test.write("top", "top\n");
+        test.write("dir/nested/deep.txt", "deep\n");
+        test.write("dir/other", "other!\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(test.join("top"), std::fs::Permissions::from_mode(0o755)).unwrap();
         }
+        let root = test.commit("first");
+
+        let changes = changed(&test, root, None).await;
+        let listed: Vec<_> = changes
+            .iter()
+            .map(|change| (change.path.as_str(), change.status.as_str(), change.new_size, change.insertions))
+            .collect();
+        // directories are not listed as files of their own
+        assert_eq!(
+            listed,
+            [("dir/nested/deep.txt", "Added", 5, 1), ("dir/other", "Added", 7, 1), ("top", "Added", 4, 1)]
+        );
+        assert!(changes.iter().all(|change| change.old_mode == 0 && change.old_size == 0));
+        assert_eq!(changes[0].new_mode, 0o100644);
+        #[cfg(unix)]
+        assert_eq!(changes[2].new_mode, 0o100755);
     }
 
-    // Only return diff if the file is modified, added, or untracked
-    if file_status == "unknown" {
-        return "No changes detected for this file.".to_string();
+    #[tokio::test]
+    async fn shows_the_root_commit_patches_on_both_backends() {
+        let test = TestRepo::new();
+        test.write("dir/file", "one\n");
+        let root = test.commit("first");
+
+        for backend in ["git2", "cli"] {
+            test.git(&["config", "gitaurora.backend", backend]);
+            let patch = get_diff_of_file_in_commit(test.path(), root.to_string(), "dir/file".into(), None, None)
+                .await
+                .unwrap();
+            assert!(patch.contains("+++ b/dir/file\n@@ -0,0 +1 @@\n+one"), "{}: {}", backend, patch);
+        }
     }
 
-    // Get the diff for the specified file
-    let diff = if file_status == "untracked" {
-        Command::new("git")
-            .current_dir(&directory)
-            .args(&["diff", "--unified=3", "/dev/null", &filename])
-            .output()
-            .expect("Failed to execute git command")
-    } else {
-        Command::new("git")
-            .current_dir(&directory)
-            .args(&["diff", "--unified=3", "--", &filename])
-            .output()
-            .expect("Failed to execute git command")
-    };
-
-    let diff_str = from_utf8(&diff.stdout).expect("Failed to parse diff");
-
-    // Return the diff as a string
-    diff_str.to_string()
+    #[tokio::test]
+    async fn counts_lines_with_the_diff_settings() {
+        let test = TestRepo::new();
+        test.commit_file("file", "a b\nc\n", "first");
+        test.write("file", "a  b\nc\n");
+        test.write("large", "0123456789\n");
+        let commit = test.commit("second");
+
+        let settings = DiffSettings {
+            whitespace: crate::backend::Whitespace::IgnoreChange,
+            max_file_size: Some(8),
+            ..Default::default()
+        };
+        let changes = get_changed_files_in_commit(test.path(), commit.to_string(), None, Some(settings))
+            .await
+            .unwrap();
+        let counts: Vec<_> = changes
+            .iter()
+            .map(|change| (change.path.as_str(), change.insertions, change.deletions))
+            .collect();
+        // the file over the limit is not read, so it has no lines to count
+        assert_eq!(counts, [("file", 0, 0), ("large", 0, 0)]);
+        assert_eq!(changes[1].new_size, 11);
+
+        let changes = changed(&test, commit, None).await;
+        assert_eq!((changes[0].insertions, changes[0].deletions), (1, 1));
+        assert_eq!((changes[1].insertions, changes[1].deletions), (1, 0));
+
+        let histogram = DiffSettings { algorithm: crate::backend::DiffAlgorithm::Histogram, ..Default::default() };
+        let err = get_changed_files_in_commit(test.path(), commit.to_string(), None, Some(histogram)).await;
+        assert!(matches!(err, Err(GitError::InvalidSelection { .. })));
+    }
 }
@@ -0,0 +1,336 @@
+// the history graph: commits in topological order, each placed in a lane with the edges to its parents
+use crate::backend::HIDDEN_REFS;
+use crate::error::GitError;
+use crate::gitfunction::open_repository;
+use git2::{Oid, Reference, Repository, Sort};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphRef {
+    // the short name, e.g. `main`, `origin/main` or `v1.0`
+    name: String,
+    full_name: String,
+    // "head", "branch", "remote", "tag" or "other"
+    kind: &'static str,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    parent: String,
+    // the lane the line runs down in after leaving the commit
+    lane: usize,
+    // where the parent is drawn; None when it is not part of the walk
+    parent_row: Option<usize>,
+    parent_lane: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphCommit {
+    id: String,
+    parents: Vec<String>,
+    author: String,
+    email: String,
+    message: String,
+    timestamp: i64,
+    row: usize,
+    lane: usize,
+    edges: Vec<GraphEdge>,
+    refs: Vec<GraphRef>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CommitGraph {
+    commits: Vec<GraphCommit>,
+    // the widest the graph gets, for sizing the column
+    lane_count: usize,
+}
+
+fn graph_ref(reference: &Reference) -> Option<GraphRef> {
+    let full_name = reference.name()?.to_string();
+    let kind = if reference.is_branch() {
+        "branch"
+    } else if reference.is_remote() {
+        "remote"
+    } else if reference.is_tag() {
+        "tag"
+    } else {
+        "other"
+    };
+
+    Some(GraphRef {
+        name: reference.shorthand().unwrap_or(&full_name).to_string(),
+        full_name,
+        kind,
+    })
+}
+
+// every ref that ends up at a commit, keyed by that commit; HEAD is listed first where it points
+fn decorations(repo: &Repository) -> Result<HashMap<Oid, Vec<GraphRef>>, GitError> {
+    let mut decorations: HashMap<Oid, Vec<GraphRef>> = HashMap::new();
+
+    if let Ok(head) = repo.head() {
+        if let Ok(commit) = head.peel_to_commit() {
+            decorations.entry(commit.id()).or_default().push(GraphRef {
+                name: "HEAD".to_string(),
+                full_name: "HEAD".to_string(),
+                kind: "head",
+            });
+        }
+    }
+
+    for reference in repo.references()? {
+        let reference = reference?;
+        if reference.name().is_none_or(|name| name.starts_with(HIDDEN_REFS)) {
+            continue;
+        }
+        // tags can point at trees or blobs, which have no place in the graph
+        let Ok(commit) = reference.peel_to_commit() else {
+            continue;
+        };
+        if let Some(graph_ref) = graph_ref(&reference) {
+            decorations.entry(commit.id()).or_default().push(graph_ref);
+        }
+    }
+
+    Ok(decorations)
+}
+
+// the commits to start walking from: the given revisions, or every branch, remote branch, tag and HEAD
+fn starting_points(repo: &Repository, refs: Option<&[String]>) -> Result<Vec<Oid>, GitError> {
+    let mut tips = Vec::new();
+    match refs {
+        Some(refs) => {
+            for name in refs {
+                let commit = repo
+                    .revparse_single(name)
+                    .and_then(|object| object.peel_to_commit())
+                    .map_err(|err| GitError::ref_not_found(name, err))?;
+                tips.push(commit.id());
+            }
+        }
+        None => {
+            for reference in repo.references()? {
+                let reference = reference?;
+                if reference.name().is_none_or(|name| name.starts_with(HIDDEN_REFS)) {
+                    continue;
+                }
+                if let Ok(commit) = reference.peel_to_commit() {
+                    tips.push(commit.id());
+                }
+            }
+            if let Ok(commit) = repo.head().and_then(|head| head.peel_to_commit()) {
+                tips.push(commit.id());
+            }
+        }
+    }
+    Ok(tips)
+}
+
+// take the first free lane, or open a new one
+fn free_lane(lanes: &mut Vec<Option<Oid>>, oid: Oid) -> usize {
+    match lanes.iter().position(Option::is_none) {
+        Some(lane) => {
+            lanes[lane] = Some(oid);
+            lane
+        }
+        None => {
+            lanes.push(Some(oid));
+            lanes.len() - 1
+        }
+    }
+}
+
+// lay the history out row by row. Each lane waits for the commit it will be continued by; a commit
+// takes the leftmost lane waiting for it, its first parent carries that lane on, and further
+// parents join the lane already waiting for them or open a new one.
+pub fn commit_graph(repo: &Repository, refs: Option<&[String]>, limit: Option<usize>) -> Result<CommitGraph, GitError> {
+    let mut revwalk = repo.revwalk()?;
+    revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    for tip in starting_points(repo, refs)? {
+        revwalk.push(tip)?;
+    }
+    let mut decorations = decorations(repo)?;
+
+    let mut commits = Vec::new();
+    let mut rows: HashMap<Oid, usize> = HashMap::new();
+    let mut lanes: Vec<Option<Oid>> = Vec::new();
+    let mut lane_count = 0;
+
+    for oid in revwalk.take(limit.unwrap_or(usize::MAX)) {
+        let oid = oid?;
+        let commit = repo.find_commit(oid)?;
+        let row = commits.len();
+
+        let lane = match lanes.iter().position(|waiting| *waiting == Some(oid)) {
+            Some(lane) => lane,
+            None => free_lane(&mut lanes, oid),
+        };
+        // every other lane that was waiting for this commit merges into it here
+        for waiting in lanes.iter_mut().filter(|waiting| **waiting == Some(oid)) {
+            *waiting = None;
+        }
+
+        let mut edges = Vec::new();
+        for (index, parent) in commit.parent_ids().enumerate() {
+            let edge_lane = match lanes.iter().position(|waiting| *waiting == Some(parent)) {
+                Some(existing) => existing,
+                None if index == 0 => {
+                    lanes[lane] = Some(parent);
+                    lane
+                }
+                None => free_lane(&mut lanes, parent),
+            };
+            edges.push(GraphEdge {
+                parent: parent.to_string(),
+                lane: edge_lane,
+                parent_row: None,
+                parent_lane: None,
+            });
+        }
+
+        lane_count = lane_count.max(lanes.len());
+        while lanes.last().is_some_and(Option::is_none) {
+            lanes.pop();
+        }
+
+        let author = commit.author();
+        rows.insert(oid, row);
+        commits.push(GraphCommit {
+            id: oid.to_string(),
+            parents: commit.parent_ids().map(|parent| parent.to_string()).collect(),
+            author: author.name().unwrap_or("").to_string(),
+            email: author.email().unwrap_or("").to_string(),
+            message: commit.message().unwrap_or("").to_string(),
+            timestamp: commit.time().seconds(),
+            row,
+            lane,
+            edges,
+            refs: decorations.remove(&oid).unwrap_or_default(),
+        });
+    }
+
+    // parents come after their children, so their positions are only known now
+    let positions: Vec<(usize, usize)> = commits.iter().map(|commit| (commit.row, commit.lane)).collect();
+    for commit in &mut commits {
+        for edge in &mut commit.edges {
+            let parent_row = Oid::from_str(&edge.parent).ok().and_then(|parent| rows.get(&parent));
+            if let Some(&parent_row) = parent_row {
+                edge.parent_row = Some(parent_row);
+                edge.parent_lane = Some(positions[parent_row].1);
+            }
+        }
+    }
+
+    Ok(CommitGraph { commits, lane_count })
+}
+
+// a function to get the history graph of every ref, or of the given revisions, newest first
+#[tauri::command]
+pub async fn get_commit_graph(
+    directory: String,
+    refs: Option<Vec<String>>,
+    limit: Option<usize>,
+) -> Result<CommitGraph, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        commit_graph(&repo, refs.as_deref(), limit)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    // an edge's lane, and the row and lane of the parent it runs to
+    type Edge = (usize, Option<usize>, Option<usize>);
+
+    // each commit's lane and edges, row by row
+    fn layout(graph: &CommitGraph) -> Vec<(usize, Vec<Edge>)> {
+        graph
+            .commits
+            .iter()
+            .map(|commit| {
+                let edges = commit
+                    .edges
+                    .iter()
+                    .map(|edge| (edge.lane, edge.parent_row, edge.parent_lane))
+                    .collect();
+                (commit.lane, edges)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_a_straight_history_in_one_lane() {
+        let test = TestRepo::new();
+        let a = test.empty_commit(None, 1, &[]);
+        let b = test.empty_commit(None, 2, &[a]);
+        test.empty_commit(Some("main"), 3, &[b]);
+
+        let graph = commit_graph(&test.repo, None, None).unwrap();
+        assert_eq!(graph.lane_count, 1);
+        assert_eq!(
+            layout(&graph),
+            [
+                (0, vec![(0, Some(1), Some(0))]),
+                (0, vec![(0, Some(2), Some(0))]),
+                (0, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn opens_a_lane_for_a_merged_branch_and_joins_it_at_the_fork() {
+        let test = TestRepo::new();
+        let base = test.empty_commit(None, 1, &[]);
+        let main = test.empty_commit(None, 2, &[base]);
+        let side = test.empty_commit(None, 3, &[base]);
+        let merge = test.empty_commit(Some("main"), 4, &[main, side]);
+
+        let graph = commit_graph(&test.repo, None, None).unwrap();
+        let order: Vec<String> = graph.commits.iter().map(|commit| commit.id.clone()).collect();
+        assert_eq!(order, [merge, side, main, base].map(|id| id.to_string()));
+        assert_eq!(graph.lane_count, 2);
+        assert_eq!(
+            layout(&graph),
+            [
+                // the first parent goes on down the merge's lane, the second opens lane 1
+                (0, vec![(0, Some(2), Some(0)), (1, Some(1), Some(1))]),
+                (1, vec![(1, Some(3), Some(1))]),
+                // main's line joins the lane already waiting for the fork point
+                (0, vec![(1, Some(3), Some(1))]),
+                (1, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn reuses_a_lane_once_its_branch_has_ended() {
+        let test = TestRepo::new();
+        let root = test.empty_commit(None, 1, &[]);
+        let first = test.empty_commit(Some("first"), 2, &[root]);
+        let second = test.empty_commit(Some("second"), 3, &[]);
+        test.empty_commit(Some("main"), 4, &[first, second]);
+        test.empty_commit(Some("other"), 5, &[]);
+
+        let graph = commit_graph(&test.repo, None, None).unwrap();
+        let lanes: Vec<usize> = graph.commits.iter().map(|commit| commit.lane).collect();
+        // `other` has no parents, so the merge takes its lane; lane 1 opens for `second` and
+        // closes after it, leaving `first` and the root in lane 0
+        assert_eq!(lanes, [0, 0, 1, 0, 0]);
+        assert_eq!(graph.lane_count, 2);
+    }
+
+    #[test]
+    fn leaves_parents_past_the_limit_unplaced() {
+        let test = TestRepo::new();
+        let a = test.empty_commit(None, 1, &[]);
+        test.empty_commit(Some("main"), 2, &[a]);
+
+        let graph = commit_graph(&test.repo, None, Some(1)).unwrap();
+        assert_eq!(layout(&graph), [(0, vec![(0, None, None)])]);
+    }
+}
@@ -0,0 +1,587 @@
+// commit history a page at a time, so long histories never have to be walked or sent in one go
+use crate::backend::libgit2::refs_by_commit;
+use crate::error::GitError;
+use crate::gitfunction::{open_repository, Commit};
+use git2::{Oid, Repository, RevparseMode, Revwalk, Signature, Sort};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tauri::ipc::Channel;
+
+const DEFAULT_PAGE_SIZE: usize = 200;
+// how long counting the history may take before settling for a lower bound
+const COUNT_BUDGET: Duration = Duration::from_millis(200);
+// like git, keep going for a few commits past `since` in case of clock skew before stopping
+const SINCE_SLOP: usize = 5;
+
+#[derive(Serialize)]
+pub struct CommitPage {
+    commits: Vec<Commit>,
+    // pass back as `cursor` to get the following page; None once the history is exhausted
+    next_cursor: Option<String>,
+    // the number of commits in the history, a lower bound unless `total_exact`;
+    // only the first page of `get_commit_page` counts, later ones leave it out
+    total_estimate: Option<usize>,
+    total_exact: bool,
+}
+
+// what to narrow the history down to; every field left out matches everything
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LogFilter {
+    // regexes matched against "Name <email>", like --author and --committer
+    author: Option<String>,
+    committer: Option<String>,
+    // unix timestamps compared with the commit date
+    since: Option<i64>,
+    until: Option<i64>,
+    // a file or directory, like `git log -- <path>`
+    path: Option<String>,
+    // a regex matched against the whole message, like --grep
+    message: Option<String>,
+    // true for merges only, false for no merges
+    merges: Option<bool>,
+}
+
+// a LogFilter with its regexes compiled
+struct Filter {
+    author: Option<Regex>,
+    committer: Option<Regex>,
+    since: Option<i64>,
+    until: Option<i64>,
+    path: Option<String>,
+    message: Option<Regex>,
+    merges: Option<bool>,
+}
+
+fn compile(field: &str, pattern: Option<String>) -> Result<Option<Regex>, GitError> {
+    pattern
+        .filter(|pattern| !pattern.is_empty())
+        .map(|pattern| {
+            Regex::new(&pattern).map_err(|err| GitError::InvalidFilter {
+                message: format!("{}: {}", field, err),
+            })
+        })
+        .transpose()
+}
+
+impl Filter {
+    fn new(filter: LogFilter) -> Result<Self, GitError> {
+        Ok(Filter {
+            author: compile("author", filter.author)?,
+            committer: compile("committer", filter.committer)?,
+            since: filter.since,
+            until: filter.until,
+            path: filter
+                .path
+                .map(|path| path.trim_end_matches('/').to_string())
+                .filter(|path| !path.is_empty()),
+            message: compile("message", filter.message)?,
+            merges: filter.merges,
+        })
+    }
+
+    // everything but the path, which decides how the history is walked rather than what is shown
+    fn matches(&self, commit: &git2::Commit) -> bool {
+        let time = commit.time().seconds();
+        self.since.is_none_or(|since| time >= since)
+            && self.until.is_none_or(|until| time <= until)
+            && self.merges.is_none_or(|merges| merges == (commit.parent_count() > 1))
+            && matches_person(&self.author, &commit.author())
+            && matches_person(&self.committer, &commit.committer())
+            && self
+                .message
+                .as_ref()
+                .is_none_or(|message| message.is_match(commit.message().unwrap_or("")))
+    }
+}
+
+fn matches_person(pattern: &Option<Regex>, person: &Signature) -> bool {
+    pattern.as_ref().is_none_or(|pattern| {
+        let name = format!("{} <{}>", person.name().unwrap_or(""), person.email().unwrap_or(""));
+        pattern.is_match(&name)
+    })
+}
+
+// the history of `revision` with a filter applied, newest first
+pub struct History<'repo> {
+    repo: &'repo Repository,
+    revwalk: Revwalk<'repo>,
+    filter: Filter,
+    // with a path: the commits still reachable through the parents history simplification keeps
+    wanted: HashSet<Oid>,
+    // how many commits in a row were older than `since`
+    past_since: usize,
+    // when set, the walk gives up at this point and records that it did
+    deadline: Option<Instant>,
+    timed_out: bool,
+}
+
+// push the commits a revspec selects onto the walk and return the tips: `A` is A and its
+// ancestors, `A..B` what B has that A does not, `A...B` what either has that their merge bases do not
+fn push_revspec(repo: &Repository, revwalk: &mut Revwalk, spec: &str) -> Result<Vec<Oid>, GitError> {
+    let revspec = repo.revparse(spec).map_err(|err| GitError::ref_not_found(spec, err))?;
+    let peel = |object: Option<&git2::Object>| -> Result<Oid, GitError> {
+        let object = object.ok_or_else(|| GitError::RefNotFound {
+            name: spec.to_string(),
+            message: format!("'{}' does not name a commit", spec),
+        })?;
+        Ok(object.peel_to_commit()?.id())
+    };
+
+    let mode = revspec.mode();
+    if mode.contains(RevparseMode::SINGLE) {
+        let tip = peel(revspec.from())?;
+        revwalk.push(tip)?;
+        return Ok(vec![tip]);
+    }
+
+    let from = peel(revspec.from())?;
+    let to = peel(revspec.to())?;
+    if mode.contains(RevparseMode::MERGE_BASE) {
+        revwalk.push(from)?;
+        revwalk.push(to)?;
+        for base in repo.merge_bases(from, to)?.iter() {
+            revwalk.hide(*base)?;
+        }
+        Ok(vec![from, to])
+    } else {
+        revwalk.push(to)?;
+        revwalk.hide(from)?;
+        Ok(vec![to])
+    }
+}
+
+impl<'repo> History<'repo> {
+    // walk the commits `revision` selects, any revspec git understands, HEAD by default. Time order
+    // lets libgit2 hand out commits as it goes; a path needs every child seen before its parents,
+    // so that walk is topological
+    pub fn new(repo: &'repo Repository, revision: Option<&str>, filter: LogFilter) -> Result<Self, GitError> {
+        let filter = Filter::new(filter)?;
+        let mut revwalk = repo.revwalk()?;
+        if filter.path.is_some() {
+            revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+        } else {
+            revwalk.set_sorting(Sort::TIME)?;
+        }
+        let tips = push_revspec(repo, &mut revwalk, revision.unwrap_or("HEAD"))?;
+
+        Ok(History {
+            repo,
+            revwalk,
+            filter,
+            wanted: tips.into_iter().collect(),
+            past_since: 0,
+            deadline: None,
+            timed_out: false,
+        })
+    }
+
+    // the id of `path` in a commit's tree, None where it does not exist
+    fn path_id(commit: &git2::Commit, path: &str) -> Result<Option<Oid>, GitError> {
+        match commit.tree()?.get_path(Path::new(path)) {
+            Ok(entry) => Ok(Some(entry.id())),
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    // `git log -- <path>` simplification: a commit the path is unchanged in against one of its
+    // parents is left out, and only that parent's line is followed; any other commit is shown
+    // and all its parents followed
+    fn simplify(&mut self, commit: &git2::Commit, path: &str) -> Result<bool, GitError> {
+        if !self.wanted.remove(&commit.id()) {
+            return Ok(false);
+        }
+
+        let id = Self::path_id(commit, path)?;
+        let parents: Vec<git2::Commit> = commit.parents().collect();
+        for parent in &parents {
+            if Self::path_id(parent, path)? == id {
+                self.wanted.insert(parent.id());
+                return Ok(false);
+            }
+        }
+
+        self.wanted.extend(parents.iter().map(|parent| parent.id()));
+        // a root commit counts as a change only if it has the path at all
+        Ok(!parents.is_empty() || id.is_some())
+    }
+
+    fn next_commit(&mut self) -> Result<Option<git2::Commit<'repo>>, GitError> {
+        while let Some(oid) = self.revwalk.next() {
+            if self.deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                self.timed_out = true;
+                return Ok(None);
+            }
+            let commit = self.repo.find_commit(oid?)?;
+
+            if let Some(since) = self.filter.since {
+                if commit.time().seconds() < since {
+                    self.past_since += 1;
+                    if self.past_since > SINCE_SLOP && self.filter.path.is_none() {
+                        return Ok(None);
+                    }
+                } else {
+                    self.past_since = 0;
+                }
+            }
+
+            if let Some(path) = self.filter.path.clone() {
+                if !self.simplify(&commit, &path)? {
+                    continue;
+                }
+            }
+            if self.filter.matches(&commit) {
+                return Ok(Some(commit));
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<'repo> Iterator for History<'repo> {
+    type Item = Result<git2::Commit<'repo>, GitError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_commit().transpose()
+    }
+}
+
+// skip the history past `cursor`, the last commit of the previous page. This walks again from the
+// tip, so the cost of a page grows with how deep it is
+fn resume(history: &mut History, cursor: &str) -> Result<(), GitError> {
+    let cursor_id = Oid::from_str(cursor).map_err(|err| GitError::ref_not_found(cursor, err))?;
+    for commit in history.by_ref() {
+        if commit?.id() == cursor_id {
+            return Ok(());
+        }
+    }
+    Err(GitError::RefNotFound {
+        name: cursor.to_string(),
+        message: "the cursor is not part of this history".to_string(),
+    })
+}
+
+// count the commits of the history, stopping at the time budget; true when the count is complete
+fn count_history(
+    repo: &Repository,
+    revision: Option<&str>,
+    filter: &LogFilter,
+) -> Result<(usize, bool), GitError> {
+    let mut history = History::new(repo, revision, filter.clone())?;
+    history.deadline = Some(Instant::now() + COUNT_BUDGET);
+
+    let mut count = 0;
+    for commit in history.by_ref() {
+        commit?;
+        count += 1;
+    }
+    Ok((count, !history.timed_out))
+}
+
+// take the next `page_size` commits off the history, with the cursor of the page after them
+fn next_page(
+    history: &mut std::iter::Peekable<History>,
+    page_size: usize,
+    refs: &HashMap<Oid, Vec<String>>,
+) -> Result<(Vec<Commit>, Option<String>), GitError> {
+    let mut commits = Vec::with_capacity(page_size);
+    let mut last = None;
+    for commit in history.by_ref().take(page_size) {
+        let commit = commit?;
+        last = Some(commit.id());
+        commits.push(Commit::from_git(&commit, refs));
+    }
+
+    let next_cursor = match history.peek() {
+        Some(_) => last.map(|oid| oid.to_string()),
+        None => None,
+    };
+    Ok((commits, next_cursor))
+}
+
+// a function to get one page of the history of a branch, tag or revspec, optionally filtered,
+// resuming after `cursor` when given. Every page walks the history again up to its cursor, so
+// paging through all of a long history is quadratic: this is for the first few pages, and
+// `stream_commits`, which keeps one walk going, is the way to load the rest
+#[tauri::command]
+pub async fn get_commit_page(
+    directory: String,
+    revision: Option<String>,
+    cursor: Option<String>,
+    page_size: Option<usize>,
+    filter: Option<LogFilter>,
+) -> Result<CommitPage, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let filter = filter.unwrap_or_default();
+        let mut history = History::new(&repo, revision.as_deref(), filter.clone())?;
+        if let Some(cursor) = &cursor {
+            resume(&mut history, cursor)?;
+        }
+
+        let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+        let refs = refs_by_commit(&repo)?;
+        let (commits, next_cursor) = next_page(&mut history.peekable(), page_size, &refs)?;
+        let (total_estimate, total_exact) = match cursor {
+            Some(_) => (None, false),
+            None => {
+                let (count, exact) = count_history(&repo, revision.as_deref(), &filter)?;
+                (Some(count), exact)
+            }
+        };
+
+        Ok(CommitPage {
+            commits,
+            next_cursor,
+            total_estimate,
+            total_exact,
+        })
+    })
+    .await?
+}
+
+// a function to send the history of a branch, tag or revspec to the UI page by page as it is
+// walked, starting after `cursor` when given; it stops early if the UI goes away
+#[tauri::command]
+pub async fn stream_commits(
+    directory: String,
+    revision: Option<String>,
+    cursor: Option<String>,
+    page_size: Option<usize>,
+    filter: Option<LogFilter>,
+    on_page: Channel<CommitPage>,
+) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let filter = filter.unwrap_or_default();
+        let (mut total_estimate, mut total_exact) = count_history(&repo, revision.as_deref(), &filter)?;
+
+        let mut history = History::new(&repo, revision.as_deref(), filter)?;
+        if let Some(cursor) = &cursor {
+            resume(&mut history, cursor)?;
+        }
+
+        let page_size = page_size.unwrap_or(DEFAULT_PAGE_SIZE).max(1);
+        let refs = refs_by_commit(&repo)?;
+        let mut history = history.peekable();
+        let mut sent = 0;
+        loop {
+            let (commits, next_cursor) = next_page(&mut history, page_size, &refs)?;
+            sent += commits.len();
+            // a walk from the tip that has ended has seen everything; until then the count can only grow
+            if next_cursor.is_none() && cursor.is_none() {
+                total_estimate = sent;
+                total_exact = true;
+            } else {
+                total_estimate = total_estimate.max(sent);
+            }
+
+            let done = next_cursor.is_none();
+            let page = CommitPage {
+                commits,
+                next_cursor,
+                total_estimate: Some(total_estimate),
+                total_exact,
+            };
+            if on_page.send(page).is_err() || done {
+                return Ok(());
+            }
+        }
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    // the ids of a page's commits and its cursor, as the UI receives them
+    fn ids(page: &CommitPage) -> (Vec<String>, Option<String>) {
+        let page = serde_json::to_value(page).unwrap();
+        let ids = page["commits"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|commit| commit["id"].as_str().unwrap().to_string())
+            .collect();
+        (ids, page["next_cursor"].as_str().map(str::to_string))
+    }
+
+    // commits at times 1 to `count` in a line on main, oldest first
+    fn linear(test: &TestRepo, count: i64) -> Vec<String> {
+        let mut parents = Vec::new();
+        let mut ids = Vec::new();
+        for time in 1..=count {
+            let id = test.empty_commit(Some("main"), time, &parents);
+            parents = vec![id];
+            ids.push(id.to_string());
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn pages_through_the_history_with_cursors() {
+        let test = TestRepo::new();
+        let commits = linear(&test, 5);
+        let page = |cursor: Option<String>| get_commit_page(test.path(), None, cursor, Some(2), None);
+
+        let first = page(None).await.unwrap();
+        assert_eq!(ids(&first), (vec![commits[4].clone(), commits[3].clone()], Some(commits[3].clone())));
+        assert_eq!((first.total_estimate, first.total_exact), (Some(5), true));
+
+        let second = page(Some(commits[3].clone())).await.unwrap();
+        assert_eq!(ids(&second), (vec![commits[2].clone(), commits[1].clone()], Some(commits[1].clone())));
+        // only the first page counts
+        assert_eq!((second.total_estimate, second.total_exact), (None, false));
+
+        let last = page(Some(commits[1].clone())).await.unwrap();
+        assert_eq!(ids(&last), (vec![commits[0].clone()], None));
+    }
+
+    #[tokio::test]
+    async fn ends_without_a_cursor_when_the_last_page_is_full() {
+        let test = TestRepo::new();
+        let commits = linear(&test, 2);
+        let page = get_commit_page(test.path(), Some("main".into()), None, Some(2), None).await.unwrap();
+        assert_eq!(ids(&page), (vec![commits[1].clone(), commits[0].clone()], None));
+
+        // a page size of 0 still makes progress
+        let page = get_commit_page(test.path(), None, None, Some(0), None).await.unwrap();
+        assert_eq!(ids(&page), (vec![commits[1].clone()], Some(commits[1].clone())));
+    }
+
+    #[tokio::test]
+    async fn rejects_a_cursor_from_another_history() {
+        let test = TestRepo::new();
+        linear(&test, 2);
+        let elsewhere = test.empty_commit(Some("other"), 10, &[]);
+
+        let err = get_commit_page(test.path(), None, Some(elsewhere.to_string()), None, None)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, GitError::RefNotFound { .. }));
+        let err = get_commit_page(test.path(), None, Some("not a commit".into()), None, None)
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, GitError::RefNotFound { .. }));
+    }
+
+    // commit `file` changed by `name` at `time` seconds on top of HEAD
+    fn commit_by(test: &TestRepo, name: &str, time: i64, file: &str, message: &str) -> String {
+        test.write(file, message);
+        test.stage_all();
+        let mut index = test.repo.index().unwrap();
+        let tree = test.repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let email = format!("{}@example.com", name.to_lowercase());
+        let signature = Signature::new(name, &email, &git2::Time::new(time, 0)).unwrap();
+        let parent = test.repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        test.repo
+            .commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .unwrap()
+            .to_string()
+    }
+
+    async fn filtered(test: &TestRepo, revision: Option<&str>, filter: LogFilter) -> Vec<String> {
+        let page = get_commit_page(test.path(), revision.map(str::to_string), None, None, Some(filter));
+        ids(&page.await.unwrap()).0
+    }
+
+    // what `git log` itself lists
+    fn git_log(test: &TestRepo, args: &[&str]) -> Vec<String> {
+        let mut command = vec!["log", "--format=%H"];
+        command.extend(args);
+        test.git(&command).lines().map(str::to_string).collect()
+    }
+
+    #[tokio::test]
+    async fn filters_by_author_message_and_date() {
+        let test = TestRepo::new();
+        let first = commit_by(&test, "Alice", 100, "a", "fix: one");
+        let second = commit_by(&test, "Bob", 200, "b", "feat: two");
+        let third = commit_by(&test, "Alice", 300, "a", "fix: three\n\nCloses #4");
+
+        let by_alice = LogFilter { author: Some("^Alice <".into()), ..Default::default() };
+        assert_eq!(filtered(&test, None, by_alice).await, [third.clone(), first.clone()]);
+        assert_eq!(git_log(&test, &["--author=^Alice <"]), [third.clone(), first.clone()]);
+
+        let by_email = LogFilter { committer: Some("bob@example".into()), ..Default::default() };
+        assert_eq!(filtered(&test, None, by_email).await, [second.as_str()]);
+
+        // the whole message is searched, body included
+        let closes = LogFilter { message: Some("Closes #\\d".into()), ..Default::default() };
+        assert_eq!(filtered(&test, None, closes).await, [third.as_str()]);
+
+        let window = LogFilter { since: Some(150), until: Some(300), ..Default::default() };
+        assert_eq!(filtered(&test, None, window).await, [third, second]);
+        let until = LogFilter { until: Some(100), ..Default::default() };
+        assert_eq!(filtered(&test, None, until).await, [first]);
+    }
+
+    #[tokio::test]
+    async fn filters_by_path_like_git_log() {
+        let test = TestRepo::new();
+        commit_by(&test, "Alice", 100, "dir/a", "one");
+        commit_by(&test, "Alice", 200, "b", "two");
+        test.git(&["checkout", "-q", "-b", "side"]);
+        commit_by(&test, "Alice", 300, "dir/a", "side");
+        test.git(&["checkout", "-q", "main"]);
+        commit_by(&test, "Alice", 400, "b", "main");
+        test.git(&["merge", "-q", "--no-edit", "side"]);
+        commit_by(&test, "Alice", 500, "dir/a", "after");
+
+        for path in ["dir/a", "dir", "dir/", "b"] {
+            let filter = LogFilter { path: Some(path.into()), ..Default::default() };
+            assert_eq!(filtered(&test, None, filter).await, git_log(&test, &["--", path]), "{}", path);
+        }
+        let missing = LogFilter { path: Some("missing".into()), ..Default::default() };
+        assert!(filtered(&test, None, missing).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_or_drops_merges() {
+        let test = TestRepo::new();
+        commit_by(&test, "Alice", 100, "a", "one");
+        test.git(&["checkout", "-q", "-b", "side"]);
+        commit_by(&test, "Alice", 200, "b", "side");
+        test.git(&["checkout", "-q", "main"]);
+        commit_by(&test, "Alice", 300, "a", "main");
+        test.git(&["merge", "-q", "--no-edit", "side"]);
+
+        let merges = LogFilter { merges: Some(true), ..Default::default() };
+        assert_eq!(filtered(&test, None, merges).await, git_log(&test, &["--merges"]));
+        let no_merges = LogFilter { merges: Some(false), ..Default::default() };
+        let mut expected = git_log(&test, &["--no-merges"]);
+        let mut found = filtered(&test, None, no_merges).await;
+        expected.sort();
+        found.sort();
+        assert_eq!(found, expected);
+    }
+
+    #[tokio::test]
+    async fn counts_only_the_matching_commits() {
+        let test = TestRepo::new();
+        commit_by(&test, "Alice", 100, "a", "one");
+        commit_by(&test, "Bob", 200, "a", "two");
+        commit_by(&test, "Alice", 300, "a", "three");
+
+        let filter = LogFilter { author: Some("Bob".into()), ..Default::default() };
+        let page = get_commit_page(test.path(), None, None, None, Some(filter)).await.unwrap();
+        assert_eq!((page.total_estimate, page.total_exact), (Some(1), true));
+    }
+
+    #[tokio::test]
+    async fn rejects_an_invalid_pattern() {
+        let test = TestRepo::new();
+        commit_by(&test, "Alice", 100, "a", "one");
+        let filter = LogFilter { message: Some("(".into()), ..Default::default() };
+        let err = get_commit_page(test.path(), None, None, None, Some(filter)).await.err().unwrap();
+        assert!(matches!(err, GitError::InvalidFilter { message } if message.starts_with("message: ")));
+    }
+}
@@ -1,9 +1,32 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 use serde::Serialize;
-use std::process::Command;
-use std::str::from_utf8;
+mod backend;
+mod blob;
+mod branch;
+mod checkout;
+mod combined;
+mod diff;
+mod error;
 mod gitfunction;
-use gitfunction::get_branch_list;
+mod graph;
+mod history;
+mod patch;
+mod snapshot;
+mod status;
+#[cfg(test)]
+mod testutil;
+use backend::{get_backend_settings, set_backend, CommitHeader, DiffSettings, NewBranch, Operation, RestoreSource};
+use blob::get_file_versions;
+use branch::{create_tracking_branch, delete_branches, get_branch_list, rename_branch, set_branch_upstream};
+use checkout::checkout;
+use combined::get_combined_diff;
+use diff::{compare_revisions, get_commit_diff, get_working_diff};
+use error::GitError;
+use graph::get_commit_graph;
+use history::{get_commit_page, stream_commits};
+use patch::{discard_hunks, stage_hunks, unstage_hunks};
+use snapshot::{list_snapshots, restore_snapshot};
+use status::StatusEntry;
 use gitfunction::get_all_commits_from_branch;
 use gitfunction::get_changed_files_in_commit;
 use gitfunction::get_diff_of_file_in_commit;
@@ -12,59 +35,56 @@ use gitfunction::get_diff_of_file;
 use gitfunction::delete_branch;
 
 #[tauri::command]
-async fn discard_changes(directory: String) -> Result<(), String> {
-    let _output = Command::new("git")
-        .arg("reset")
-        .arg("--hard")
-        .current_dir(directory)
-        .output()
-        .expect("Failed to execute git command");
+async fn discard_changes(directory: String) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        snapshot::take(&directory, "discard")?;
+        backend::open(&directory, Operation::Discard)?.discard()?;
 
         Ok(())
+    })
+    .await?
 }
 
 #[tauri::command]
-async fn push_current_branch (directory: String) -> Result<(), String> {
-    let _output = Command::new("git")
-        .arg("push")
-        .current_dir(directory)
-        .output()
-        .expect("Failed to execute git command");
+async fn push_current_branch (directory: String) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        backend::open(&directory, Operation::Push)?.push()?;
 
         Ok(())
+    })
+    .await?
 }
 
 #[tauri::command]
-async fn merge_with_current_branch (directory: String, branch_name: String) -> Result<(), String> {
-    let _output = Command::new("git")
-        .args(["merge", &branch_name])
-        .current_dir(directory)
-        .output()
-        .expect("Failed to execute git command");
+async fn merge_with_current_branch (directory: String, branch_name: String) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        snapshot::take(&directory, "merge")?;
+        backend::open(&directory, Operation::Merge)?.merge(&branch_name)?;
 
         Ok(())
+    })
+    .await?
 }
 
 #[tauri::command]
-async fn fetch (directory: String) -> Result<(), String> {
-    let _output = Command::new("git")
-        .arg("fetch")
-        .current_dir(directory)
-        .output()
-        .expect("Failed to execute git command");
+async fn fetch (directory: String) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        backend::open(&directory, Operation::Fetch)?.fetch()?;
 
         Ok(())
+    })
+    .await?
 }
 
 #[tauri::command]
-async fn pull (directory: String) -> Result<(), String> {
-    let _output = Command::new("git")
-        .args(["pull", "--rebase"])
-        .current_dir(directory)
-        .output()
-        .expect("Failed to execute git command");
+async fn pull (directory: String) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        snapshot::take(&directory, "pull")?;
+        backend::open(&directory, Operation::Pull)?.pull()?;
 
         Ok(())
+    })
+    .await?
 }
 
 #[tauri::command]
@@ -72,34 +92,17 @@ async fn git_add_and_commit(
     directory: String,
     commit_message: String,
     files: Vec<String>,
-) -> Result<(), String> {
-    // Git add
-    let mut add_command = Command::new("git");
-    add_command.current_dir(directory.clone());
-    add_command.arg("add");
-
-    for file in files {
-        add_command.arg(file);
-    }
-
-    let add_output = add_command.output().map_err(|e| e.to_string())?;
-
-    if !add_output.status.success() {
-        return Err(String::from_utf8_lossy(&add_output.stderr).to_string());
-    }
-
-    // Git commit
-    let commit_output = Command::new("git")
-        .args(&["commit", "-m", &commit_message])
-        .current_dir(directory)
-        .output()
-        .map_err(|e| e.to_string())?;
+) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        // Git add
+        backend::open(&directory, Operation::Stage)?.stage(&files)?;
 
-    if !commit_output.status.success() {
-        return Err(String::from_utf8_lossy(&commit_output.stderr).to_string());
-    }
+        // Git commit
+        backend::open(&directory, Operation::Commit)?.commit(&commit_message)?;
 
-    Ok(())
+        Ok(())
+    })
+    .await?
 }
 
 #[derive(Serialize)]
@@ -111,444 +114,246 @@ struct Change {
 
 #[derive(Serialize)]
 struct CommitChanges {
-    id: String,
+    // the summary line, kept under the name the UI already reads
     message: String,
-    author: String,
-    date: String,
+    #[serde(flatten)]
+    header: CommitHeader,
     changes: Vec<Change>,
 }
 
-// a function to create a new branch
+// a function to create a new branch, at HEAD and checked out unless `options` say otherwise
 #[tauri::command]
-async fn create_new_branch(current_path: String, branch_name: String) -> String {
-    let output = Command::new("git")
-        .arg("checkout")
-        .arg("-b")
-        .arg(&branch_name)
-        .current_dir(&current_path)
-        .output()
-        .expect("Failed to execute git command");
-
-    let message = from_utf8(&output.stdout)
-        .expect("Failed to convert output to UTF-8")
-        .to_string();
-
-    message
+async fn create_new_branch(
+    current_path: String,
+    branch_name: String,
+    options: Option<NewBranch>,
+) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        backend::open(&current_path, Operation::Branch)?.create_branch(&branch_name, &options.unwrap_or_default())
+    })
+    .await?
 }
 
 // a function to switch to a branch
 #[tauri::command]
-async fn switch_branch(current_path: String, branch_name: String) -> String {
-    let output = Command::new("git")
-        .arg("checkout")
-        .arg(&branch_name)
-        .current_dir(&current_path)
-        .output()
-        .expect("Failed to execute git command");
-
-    let message = from_utf8(&output.stdout)
-        .expect("Failed to convert output to UTF-8")
-        .to_string();
-
-    message
-}
-
-
-struct GitDiff {
-    filename: String,
-    status: String,
-    diff: String,
+async fn switch_branch(current_path: String, branch_name: String) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        backend::open(&current_path, Operation::Branch)?.switch_branch(&branch_name)
+    })
+    .await?
 }
 
-// a function to get the diffs of a file for a commitId
-
-fn get_file_diffs(commit_id: &str, current_path: &str) -> Vec<GitDiff> {
-    let show_command = Command::new("git")
-        .current_dir(current_path)
-        .args(&["show", "--pretty=", commit_id])
-        .output()
-        .expect("Failed to execute git show command");
-
-    let diff_output = from_utf8(&show_command.stdout).expect("Failed to parse git output");
-
-    let mut diffs = Vec::new();
-    let mut current_file = String::new();
-    let mut current_diff = Vec::new();
-    let mut reading_diff = false;
-
-    for line in diff_output.lines() {
-        if line.starts_with("diff --git") {
-            // Save previous file if exists
-            if !current_file.is_empty() {
-                diffs.push(GitDiff {
-                    filename: current_file.clone(),
-                    status: "added".to_string(), // For first commit, everything is added
-                    diff: current_diff.join("\n"),
-                });
-                current_diff.clear();
-            }
-
-            // Extract new filename
-            current_file = line.split(" b/").last().unwrap_or("").to_string();
-            reading_diff = true;
-            current_diff.push(line);
-        } else if reading_diff {
-            current_diff.push(line);
-        }
-    }
-
-    // Add the last file
-    if !current_file.is_empty() {
-        diffs.push(GitDiff {
-            filename: current_file,
-            status: "added".to_string(),
-            diff: current_diff.join("\n"),
-        });
-    }
-
-    diffs
+// Extract only the actual changes (starting from @@)
+fn strip_patch_header(diff: &str) -> String {
+    diff.lines()
+        .skip_while(|line| !line.starts_with("@@"))
+        .collect::<Vec<&str>>()
+        .join("\n")
 }
 
 // a function to get the changes in a commit with type CommitChanges
 #[tauri::command]
-async fn get_commit_changes(current_path: String, commit_id: String) -> CommitChanges {
-    // Get commit details
-    let commit_info = Command::new("git")
-        .current_dir(&current_path)
-        .args(&["show", "--pretty=format:%H%n%s%n%an%n%aI", &commit_id])
-        .output()
-        .expect("Failed to execute git command");
-
-    let commit_info_str = from_utf8(&commit_info.stdout).expect("Failed to parse commit info");
-    let mut commit_info_lines = commit_info_str.lines();
-
-    // Parse commit details
-    let _id = commit_info_lines.next().unwrap_or("").to_string();
-    let message = commit_info_lines.next().unwrap_or("").to_string();
-    let author = commit_info_lines.next().unwrap_or("").to_string();
-    let date = commit_info_lines.next().unwrap_or("").to_string();
-
-    // Get changed files
-    let changes = get_file_diffs(&commit_id, &current_path)
-        .into_iter()
-        .map(|diff| Change {
-            filename: diff.filename,
-            status: diff.status,
-            diff: diff.diff,
+async fn get_commit_changes(
+    current_path: String,
+    commit_id: String,
+    parent: Option<usize>,
+    options: Option<DiffSettings>,
+) -> Result<CommitChanges, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let backend = backend::open(&current_path, Operation::Diff)?;
+
+        // Get commit details
+        let header = backend.commit_header(&commit_id)?;
+
+        // Get changed files
+        let changes = backend
+            .commit_patches(&commit_id, parent.unwrap_or(0), None, &options.unwrap_or_default())?
+            .into_iter()
+            .map(|patch| Change {
+                filename: patch.path,
+                status: patch.status.to_string(),
+                diff: patch.patch,
+            })
+            .collect();
+
+        Ok(CommitChanges {
+            message: header.summary.clone(),
+            header,
+            changes,
         })
-        .collect();
-
-    CommitChanges {
-        id: commit_id,
-        message,
-        author,
-        date,
-        changes,
-    }
+    })
+    .await?
 }
 
 // a function to get the current change of a file
 #[tauri::command]
-async fn get_current_change_by_filename(current_path: String, filename: String) -> Change {
-    // Get diff for the file with unified format
-    let diff = Command::new("git")
-        .current_dir(&current_path)
-        .args(&["diff", "--unified=3", "--", &filename])
-        .output()
-        .expect("Failed to execute git command");
-
-    let diff_str = from_utf8(&diff.stdout).expect("Failed to parse diff");
-
-    // Extract only the actual changes (starting from @@)
-    let cleaned_diff = diff_str
-        .lines()
-        .skip_while(|line| !line.starts_with("@@"))
-        .collect::<Vec<&str>>()
-        .join("\n");
+async fn get_current_change_by_filename(
+    current_path: String,
+    filename: String,
+    options: Option<DiffSettings>,
+) -> Result<Change, GitError> {
+    tokio::task::spawn_blocking(move || {
+        // Get diff for the file with unified format
+        let diff_str = backend::open(&current_path, Operation::Diff)?
+            .unstaged_patch(&filename, &options.unwrap_or_default())?;
 
-    Change {
-        filename,
-        status: "modified".to_string(),
-        diff: cleaned_diff,
-    }
+        Ok(Change {
+            filename,
+            status: "modified".to_string(),
+            diff: strip_patch_header(&diff_str),
+        })
+    })
+    .await?
 }
 
 // a function to get all the current files, the status of the files without the diff
 #[tauri::command]
-async fn get_current_changes_file_status(current_path: String) -> Vec<Change> {
-    // Get changed files
-    let changed_files = Command::new("git")
-        .current_dir(&current_path)
-        .args(&["status", "--porcelain"])
-        .output()
-        .expect("Failed to execute git command");
-
-    let changed_files_str =
-        from_utf8(&changed_files.stdout).expect("Failed to parse changed files");
-
-    // Process each changed file
-    let mut changes = Vec::new();
-    for line in changed_files_str.lines() {
-        if line.is_empty() {
-            continue;
-        }
-
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 2 {
-            continue;
-        }
-
-        let status = match parts[0] {
-            "M" => "modified",
-            "A" => "added",
-            "D" => "deleted",
-            "R" => "renamed",
-            "??" => "untracked",
-            _ => "unknown",
-        };
-        let filename = parts[1];
-
-        changes.push(Change {
-            filename: filename.to_string(),
-            status: status.to_string(),
-            diff: "".to_string(),
-        });
-    }
+async fn get_current_changes_file_status(current_path: String) -> Result<Vec<Change>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let changes = backend::open(&current_path, Operation::Status)?
+            .status(false)?
+            .into_iter()
+            .map(|entry| Change {
+                status: entry.label().to_string(),
+                filename: entry.path,
+                diff: "".to_string(),
+            })
+            .collect();
+
+        Ok(changes)
+    })
+    .await?
+}
 
-    changes
+// a function to get the staged and unstaged state, modes, size and binary flag of every changed file
+#[tauri::command]
+async fn get_status(directory: String, include_ignored: Option<bool>) -> Result<Vec<StatusEntry>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        backend::open(&directory, Operation::Status)?.status(include_ignored.unwrap_or(false))
+    })
+    .await?
 }
 
 // a function to do exactly the same as get_current_changes but with the command git status
 #[tauri::command]
-async fn get_current_changes_status(current_path: String) -> Vec<Change> {
-    // Get changed files
-    let changed_files = Command::new("git")
-        .current_dir(&current_path)
-        .args(&["status", "--porcelain"])
-        .output()
-        .expect("Failed to execute git command");
-
-    let changed_files_str =
-        from_utf8(&changed_files.stdout).expect("Failed to parse changed files");
-
-    // Process each changed file
-    let mut changes = Vec::new();
-    for line in changed_files_str.lines() {
-        if line.is_empty() {
-            continue;
-        }
-
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 2 {
-            continue;
-        }
-
-        let status = match parts[0] {
-            "M" => "modified",
-            "A" => "added",
-            "D" => "deleted",
-            "R" => "renamed",
-            "??" => "untracked",
-            _ => "unknown",
-        };
-        let filename = parts[1];
+async fn get_current_changes_status(
+    current_path: String,
+    options: Option<DiffSettings>,
+) -> Result<Vec<Change>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let backend = backend::open(&current_path, Operation::Status)?;
+        let settings = options.unwrap_or_default();
 
         // Get diff for the file with unified format for tracked and untracked files
-
-        if status == "untracked" {
-            let diff = Command::new("git")
-                .current_dir(&current_path)
-                .args(&["diff", "--unified=3", "/dev/null", filename])
-                .output()
-                .expect("Failed to execute git command");
-
-            let diff_str = from_utf8(&diff.stdout).expect("Failed to parse diff");
-
-            // Extract only the actual changes (starting from @@)
-            let cleaned_diff = diff_str.lines().collect::<Vec<&str>>().join("\n");
-
+        let mut changes = Vec::new();
+        for entry in backend.status(false)? {
             changes.push(Change {
-                filename: filename.to_string(),
-                status: status.to_string(),
-                diff: cleaned_diff,
-            });
-            continue;
-        } else {
-            let diff = Command::new("git")
-                .current_dir(&current_path)
-                .args(&["diff", "--unified=3", "--", filename])
-                .output()
-                .expect("Failed to execute git command");
-
-            let diff_str = from_utf8(&diff.stdout).expect("Failed to parse diff");
-
-            // Extract only the actual changes (starting from @@)
-            let cleaned_diff = diff_str.lines().collect::<Vec<&str>>().join("\n");
-
-            changes.push(Change {
-                filename: filename.to_string(),
-                status: status.to_string(),
-                diff: cleaned_diff,
+                diff: backend.unstaged_patch(&entry.path, &settings)?.trim_end().to_string(),
+                status: entry.label().to_string(),
+                filename: entry.path,
             });
         }
-    }
 
-    changes
+        Ok(changes)
+    })
+    .await?
 }
 
 // a function to get all the current changes not committed
 #[tauri::command]
-async fn get_current_changes(current_path: String) -> Vec<Change> {
-    // Get changed files
-    let changed_files = Command::new("git")
-        .current_dir(&current_path)
-        .args(&["diff", "--name-status", "--staged"])
-        .output()
-        .expect("Failed to execute git command");
-
-    let changed_files_str =
-        from_utf8(&changed_files.stdout).expect("Failed to parse changed files");
-
-    // Process each changed file
-    let mut changes = Vec::new();
-    for line in changed_files_str.lines() {
-        if line.is_empty() {
-            continue;
-        }
-
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 2 {
-            continue;
+async fn get_current_changes(current_path: String, options: Option<DiffSettings>) -> Result<Vec<Change>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let backend = backend::open(&current_path, Operation::Status)?;
+        let settings = options.unwrap_or_default();
+
+        let mut changes = Vec::new();
+        for entry in backend.status(false)?.into_iter().filter(StatusEntry::is_staged) {
+            // Get diff for the file with unified format
+            changes.push(Change {
+                diff: strip_patch_header(&backend.unstaged_patch(&entry.path, &settings)?),
+                status: entry.label().to_string(),
+                filename: entry.path,
+            });
         }
 
-        let status = match parts[0] {
-            "M" => "modified",
-            "A" => "added",
-            "D" => "deleted",
-            "R" => "renamed",
-            _ => "unknown",
-        };
-        let filename = parts[1];
-
-        // Get diff for the file with unified format
-        let diff = Command::new("git")
-            .current_dir(&current_path)
-            .args(&["diff", "--unified=3", "--", filename])
-            .output()
-            .expect("Failed to execute git command");
-
-        let diff_str = from_utf8(&diff.stdout).expect("Failed to parse diff");
-
-        // Extract only the actual changes (starting from @@)
-        let cleaned_diff = diff_str
-            .lines()
-            .skip_while(|line| !line.starts_with("@@"))
-            .collect::<Vec<&str>>()
-            .join("\n");
-
-        changes.push(Change {
-            filename: filename.to_string(),
-            status: status.to_string(),
-            diff: cleaned_diff,
-        });
-    }
-
-    changes
+        Ok(changes)
+    })
+    .await?
 }
 
 // a function to get all the current changes staged
 #[tauri::command]
-async fn get_staged_changes(current_path: String) -> Vec<Change> {
-    // Get changed files
-    let changed_files = Command::new("git")
-        .current_dir(&current_path)
-        .args(&["diff", "--name-status", "--cached"])
-        .output()
-        .expect("Failed to execute git command");
-
-    let changed_files_str =
-        from_utf8(&changed_files.stdout).expect("Failed to parse changed files");
-
-    // Process each changed file
-    let mut changes = Vec::new();
-    for line in changed_files_str.lines() {
-        if line.is_empty() {
-            continue;
+async fn get_staged_changes(current_path: String, options: Option<DiffSettings>) -> Result<Vec<Change>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let backend = backend::open(&current_path, Operation::Status)?;
+        let settings = options.unwrap_or_default();
+
+        let mut changes = Vec::new();
+        for entry in backend.status(false)?.into_iter().filter(StatusEntry::is_staged) {
+            // Get diff for the file with unified format
+            changes.push(Change {
+                diff: strip_patch_header(&backend.staged_patch(&entry.path, &settings)?),
+                status: entry.label().to_string(),
+                filename: entry.path,
+            });
         }
 
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() < 2 {
-            continue;
-        }
+        Ok(changes)
+    })
+    .await?
+}
 
-        let status = match parts[0] {
-            "M" => "modified",
-            "A" => "added",
-            "D" => "deleted",
-            "R" => "renamed",
-            _ => "unknown",
-        };
-        let filename = parts[1];
+// a function to stage the changes
+#[tauri::command]
+async fn stage_changes(current_path: String, files: Vec<String>) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        backend::open(&current_path, Operation::Stage)?.stage(&files)?;
 
-        // Get diff for the file with unified format
-        let diff = Command::new("git")
-            .current_dir(&current_path)
-            .args(&["diff", "--unified=3", "--cached", "--", filename])
-            .output()
-            .expect("Failed to execute git command");
-
-        let diff_str = from_utf8(&diff.stdout).expect("Failed to parse diff");
-
-        // Extract only the actual changes (starting from @@)
-        let cleaned_diff = diff_str
-            .lines()
-            .skip_while(|line| !line.starts_with("@@"))
-            .collect::<Vec<&str>>()
-            .join("\n");
-
-        changes.push(Change {
-            filename: filename.to_string(),
-            status: status.to_string(),
-            diff: cleaned_diff,
-        });
-    }
+        Ok(String::new())
+    })
+    .await?
+}
 
-    changes
+// a function to unstage files, putting their index entries back to HEAD
+#[tauri::command]
+async fn unstage_files(directory: String, files: Vec<String>) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        backend::open(&directory, Operation::Stage)?.unstage(&files)
+    })
+    .await?
 }
 
-// a function to stage the changes
+// a function to restore working tree files from the index, or from HEAD when asked
+#[tauri::command]
+async fn restore_files(
+    directory: String,
+    files: Vec<String>,
+    source: Option<RestoreSource>,
+) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        snapshot::take(&directory, "restore files")?;
+        backend::open(&directory, Operation::Discard)?.restore(&files, source.unwrap_or(RestoreSource::Index))
+    })
+    .await?
+}
+
+// a function to delete untracked files
 #[tauri::command]
-async fn stage_changes(current_path: String, files: Vec<String>) -> String {
-    let output = Command::new("git")
-        .arg("add")
-        .args(&files)
-        .current_dir(&current_path)
-        .output()
-        .expect("Failed to execute git command");
-
-    let message = from_utf8(&output.stdout)
-        .expect("Failed to convert output to UTF-8")
-        .to_string();
-
-    message
+async fn delete_untracked_files(directory: String, files: Vec<String>) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        snapshot::take(&directory, "delete untracked files")?;
+        backend::open(&directory, Operation::Discard)?.remove_untracked(&files)
+    })
+    .await?
 }
 
 // a function to commit the changes
 #[tauri::command]
-async fn commit_changes(current_path: String, message: String) -> String {
-    let output = Command::new("git")
-        .arg("commit")
-        .arg("-m")
-        .arg(&message)
-        .current_dir(&current_path)
-        .output()
-        .expect("Failed to execute git command");
-
-    let message = from_utf8(&output.stdout)
-        .expect("Failed to convert output to UTF-8")
-        .to_string();
-
-    message
+async fn commit_changes(current_path: String, message: String) -> Result<String, GitError> {
+    tokio::task::spawn_blocking(move || {
+        backend::open(&current_path, Operation::Commit)?.commit(&message)
+    })
+    .await?
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -561,6 +366,11 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .invoke_handler(tauri::generate_handler![
             get_branch_list,
+            delete_branches,
+            create_tracking_branch,
+            rename_branch,
+            set_branch_upstream,
+            checkout,
             get_all_commits_from_branch,
             get_changed_files_in_commit,
             get_diff_of_file_in_commit,
@@ -578,12 +388,128 @@ pub fn run() {
             delete_branch,
             get_current_changes_status,
             get_current_changes_file_status,
+            get_status,
             git_add_and_commit,
             merge_with_current_branch,
             fetch,
             discard_changes,
-            pull
+            pull,
+            get_backend_settings,
+            set_backend,
+            get_working_diff,
+            get_commit_diff,
+            get_combined_diff,
+            compare_revisions,
+            get_file_versions,
+            stage_hunks,
+            unstage_hunks,
+            discard_hunks,
+            unstage_files,
+            restore_files,
+            delete_untracked_files,
+            list_snapshots,
+            restore_snapshot,
+            get_commit_graph,
+            get_commit_page,
+            stream_commits
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    // a repository with `file` committed, run through the backend named by `backend`
+    fn repo_on(backend: &str) -> TestRepo {
+        let test = TestRepo::new();
+        test.commit_file("file", "committed\n", "first");
+        test.git(&["config", "gitaurora.backend", backend]);
+        test
+    }
+
+    #[tokio::test]
+    async fn unstages_changes_and_new_files_on_both_backends() {
+        for backend in ["git2", "cli"] {
+            let test = repo_on(backend);
+            test.write("file", "staged\n");
+            test.write("new", "new\n");
+            test.stage_all();
+
+            unstage_files(test.path(), vec!["file".into(), "new".into()]).await.unwrap();
+            assert_eq!(test.staged("file").as_deref(), Some("committed\n"), "{}", backend);
+            assert_eq!(test.staged("new"), None, "{}", backend);
+            // the working tree keeps the changes
+            assert_eq!(test.read("file"), "staged\n");
+            assert_eq!(test.read("new"), "new\n");
+        }
+    }
+
+    #[tokio::test]
+    async fn restores_from_the_index_or_from_head_on_both_backends() {
+        for backend in ["git2", "cli"] {
+            let test = repo_on(backend);
+            test.write("file", "staged\n");
+            test.stage_all();
+            test.write("file", "unstaged\n");
+
+            restore_files(test.path(), vec!["file".into()], None).await.unwrap();
+            assert_eq!(test.read("file"), "staged\n", "{}", backend);
+
+            test.write("file", "unstaged\n");
+            restore_files(test.path(), vec!["file".into()], Some(RestoreSource::Head)).await.unwrap();
+            assert_eq!(test.read("file"), "committed\n", "{}", backend);
+            assert_eq!(test.staged("file").as_deref(), Some("staged\n"), "{}", backend);
+        }
+    }
+
+    #[tokio::test]
+    async fn deletes_untracked_files_but_refuses_tracked_ones_on_both_backends() {
+        for backend in ["git2", "cli"] {
+            let test = repo_on(backend);
+            test.write("untracked", "new\n");
+
+            let err = delete_untracked_files(test.path(), vec!["untracked".into(), "file".into()])
+                .await
+                .unwrap_err();
+            assert!(matches!(err, GitError::InvalidSelection { .. }), "{}: {:?}", backend, err);
+            assert!(test.join("untracked").exists());
+            assert!(test.join("file").exists());
+
+            delete_untracked_files(test.path(), vec!["untracked".into()]).await.unwrap();
+            assert!(!test.join("untracked").exists(), "{}", backend);
+        }
+    }
+
+    #[tokio::test]
+    async fn creates_branches_from_a_start_point_with_checkout_and_upstream_on_both_backends() {
+        for backend in ["git2", "cli"] {
+            let test = repo_on(backend);
+            let first = test.git(&["rev-parse", "HEAD"]);
+            test.commit_file("file", "second\n", "second");
+            test.git(&["tag", "v1", first.trim()]);
+
+            create_new_branch(test.path(), "here".into(), None).await.unwrap();
+            assert_eq!(test.git(&["branch", "--show-current"]), "here\n", "{}", backend);
+
+            let options = NewBranch {
+                start_point: Some("v1".into()),
+                checkout: false,
+                upstream: Some("main".into()),
+            };
+            create_new_branch(test.path(), "old".into(), Some(options)).await.unwrap();
+            assert_eq!(test.git(&["rev-parse", "old"]), first, "{}", backend);
+            assert_eq!(test.git(&["rev-parse", "--abbrev-ref", "old@{upstream}"]), "main\n", "{}", backend);
+            assert_eq!(test.git(&["branch", "--show-current"]), "here\n", "{}", backend);
+
+            let options = NewBranch { start_point: Some("nowhere".into()), ..NewBranch::default() };
+            let err = create_new_branch(test.path(), "broken".into(), Some(options)).await.unwrap_err();
+            assert!(matches!(err, GitError::RefNotFound { .. }), "{}: {:?}", backend, err);
+            let err = create_new_branch(test.path(), "old".into(), None).await.unwrap_err();
+            assert!(!matches!(err, GitError::RefNotFound { .. }), "{}: {:?}", backend, err);
+            assert_eq!(test.git(&["rev-parse", "old"]), first, "{}", backend);
+        }
+    }
+}
@@ -0,0 +1,363 @@
+// staging, unstaging and discarding single hunks or lines, by applying a patch built from the selection
+use crate::backend::libgit2::{staged_diff, unstaged_diff};
+use crate::backend::DiffSettings;
+use crate::diff::{file_diffs, DiffHunk, FileDiff, LineOrigin};
+use crate::error::GitError;
+use crate::gitfunction::open_repository;
+use git2::{ApplyLocation, Diff, Repository};
+use serde::Deserialize;
+
+// one hunk of a file's structured diff, and optionally which of its lines to take
+#[derive(Debug, Clone, Deserialize)]
+pub struct HunkSelection {
+    // index into the file's hunks
+    pub hunk: usize,
+    // indices into the hunk's lines, every change in the hunk when absent
+    pub lines: Option<Vec<usize>>,
+}
+
+// Forward replays the selected changes onto the old side of the diff,
+// Reverse takes them back out of the new side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Forward,
+    Reverse,
+}
+
+fn invalid(message: String) -> GitError {
+    GitError::InvalidSelection { message }
+}
+
+// find the diff of `filename`, refusing anything a text patch cannot express. Selections index
+// into the default diff, since one that ignores whitespace cannot be applied.
+fn file_to_patch(diff: &Diff, filename: &str) -> Result<FileDiff, GitError> {
+    let file = file_diffs(diff)?
+        .into_iter()
+        .find(|file| file.path == filename)
+        .ok_or_else(|| invalid(format!("{} has no changes here", filename)))?;
+
+    if file.binary {
+        return Err(invalid(format!("{} is binary and cannot be split", filename)));
+    }
+    // the patch is written back as UTF-8, which would corrupt text in any other encoding
+    if file.encoding != Some("UTF-8") {
+        return Err(invalid(format!("{} is not UTF-8 text and cannot be split", filename)));
+    }
+    Ok(file)
+}
+
+// the lines chosen in each hunk, checked against the diff the UI was showing
+fn selected_lines(file: &FileDiff, selections: &[HunkSelection]) -> Result<Vec<Vec<bool>>, GitError> {
+    let mut selected: Vec<Vec<bool>> = file
+        .hunks
+        .iter()
+        .map(|hunk| vec![false; hunk.lines.len()])
+        .collect();
+
+    for selection in selections {
+        let lines = selected.get_mut(selection.hunk).ok_or_else(|| {
+            invalid(format!(
+                "{} has no hunk {}, the diff may have changed",
+                file.path, selection.hunk
+            ))
+        })?;
+
+        match &selection.lines {
+            None => lines.iter_mut().for_each(|line| *line = true),
+            Some(indices) => {
+                for index in indices {
+                    let line = lines.get_mut(*index).ok_or_else(|| {
+                        invalid(format!(
+                            "hunk {} of {} has no line {}, the diff may have changed",
+                            selection.hunk, file.path, index
+                        ))
+                    })?;
+                    *line = true;
+                }
+            }
+        }
+    }
+
+    Ok(selected)
+}
+
+// rewrite one hunk so it carries only the selected changes; None when nothing in it is selected.
+// `offset` is how far earlier hunks of the patch moved the new side.
+fn partial_hunk(hunk: &DiffHunk, selected: &[bool], direction: Direction, offset: i64) -> Option<(String, i64)> {
+    // each line of the patch: its marker, its text, and whether it lacks a newline
+    let mut lines: Vec<(char, &str, bool)> = Vec::new();
+    for (line, &selected) in hunk.lines.iter().zip(selected) {
+        // a change that stays out of the patch is context if its text exists on the side being patched
+        let marker = match (line.origin, direction, selected) {
+            (LineOrigin::Context, _, _) => ' ',
+            (LineOrigin::Addition, Direction::Forward, true) => '+',
+            (LineOrigin::Addition, Direction::Forward, false) => continue,
+            (LineOrigin::Addition, Direction::Reverse, true) => '-',
+            (LineOrigin::Addition, Direction::Reverse, false) => ' ',
+            (LineOrigin::Deletion, Direction::Forward, true) => '-',
+            (LineOrigin::Deletion, Direction::Forward, false) => ' ',
+            (LineOrigin::Deletion, Direction::Reverse, true) => '+',
+            (LineOrigin::Deletion, Direction::Reverse, false) => continue,
+        };
+        lines.push((marker, &line.content, line.no_newline_at_eof));
+    }
+    if lines.iter().all(|(marker, _, _)| *marker == ' ') {
+        return None;
+    }
+
+    // only the last line of a side can lack its newline. A change left out can put a line that
+    // ended its file before lines that are still added, and then that side needs the newline:
+    // context splits into the line without it on one side and with it on the other
+    let last_old = lines.iter().rposition(|(marker, _, _)| *marker != '+');
+    let last_new = lines.iter().rposition(|(marker, _, _)| *marker != '-');
+    let mut body = String::new();
+    let (mut old_lines, mut new_lines): (i64, i64) = (0, 0);
+    for (index, &(marker, content, no_newline)) in lines.iter().enumerate() {
+        let old_end = no_newline && marker != '+' && last_old == Some(index);
+        let new_end = no_newline && marker != '-' && last_new == Some(index);
+        let sides: &[(char, bool)] = if marker == ' ' && old_end != new_end {
+            &[('-', old_end), ('+', new_end)]
+        } else {
+            &[(marker, old_end || new_end)]
+        };
+        for &(marker, no_newline) in sides {
+            body.push(marker);
+            body.push_str(content);
+            body.push('\n');
+            if no_newline {
+                body.push_str("\\ No newline at end of file\n");
+            }
+        }
+        old_lines += i64::from(marker != '+');
+        new_lines += i64::from(marker != '-');
+    }
+
+    let old_start = i64::from(match direction {
+        Direction::Forward => hunk.old_start,
+        Direction::Reverse => hunk.new_start,
+    });
+    // an empty range names the line before it, so `-0,0` is the start of the file
+    let first_line = if old_lines == 0 { old_start + 1 } else { old_start };
+    let new_start = if new_lines == 0 {
+        first_line + offset - 1
+    } else {
+        first_line + offset
+    };
+
+    let header = format!("@@ -{},{} +{},{} @@\n", old_start, old_lines, new_start.max(0), new_lines);
+    Some((header + &body, new_lines - old_lines))
+}
+
+// build the patch for the selection; None when no change was selected
+fn build_patch(file: &FileDiff, selections: &[HunkSelection], direction: Direction) -> Result<Option<String>, GitError> {
+    let selected = selected_lines(file, selections)?;
+    let whole_file = selected.iter().all(|lines| lines.iter().all(|line| *line));
+
+    let mut hunks = String::new();
+    let mut offset = 0;
+    for (hunk, selected) in file.hunks.iter().zip(&selected) {
+        if let Some((text, moved)) = partial_hunk(hunk, selected, direction, offset) {
+            hunks.push_str(&text);
+            offset += moved;
+        }
+    }
+    if hunks.is_empty() {
+        return Ok(None);
+    }
+
+    // the modes of the side being patched and of the side it turns into
+    let (from_mode, to_mode) = match direction {
+        Direction::Forward => (file.old_mode, file.new_mode),
+        Direction::Reverse => (file.new_mode, file.old_mode),
+    };
+
+    let path = &file.path;
+    let mut patch = format!("diff --git a/{} b/{}\n", path, path);
+    if from_mode == 0 {
+        patch.push_str(&format!("new file mode {:o}\n--- /dev/null\n+++ b/{}\n", to_mode, path));
+    } else if to_mode == 0 && whole_file {
+        patch.push_str(&format!("deleted file mode {:o}\n--- a/{}\n+++ /dev/null\n", from_mode, path));
+    } else {
+        patch.push_str(&format!("--- a/{}\n+++ b/{}\n", path, path));
+    }
+    patch.push_str(&hunks);
+
+    Ok(Some(patch))
+}
+
+fn apply_selection(
+    repo: &Repository,
+    file: &FileDiff,
+    selections: &[HunkSelection],
+    direction: Direction,
+    location: ApplyLocation,
+) -> Result<(), GitError> {
+    let Some(patch) = build_patch(file, selections, direction)? else {
+        return Ok(());
+    };
+
+    let diff = Diff::from_buffer(patch.as_bytes())?;
+    repo.apply(&diff, location, None)?;
+    Ok(())
+}
+
+// a function to stage some hunks or lines of a file's unstaged changes
+#[tauri::command]
+pub async fn stage_hunks(
+    directory: String,
+    filename: String,
+    selections: Vec<HunkSelection>,
+) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let file = file_to_patch(&unstaged_diff(&repo, Some(&filename), &DiffSettings::default())?, &filename)?;
+
+        apply_selection(&repo, &file, &selections, Direction::Forward, ApplyLocation::Index)
+    })
+    .await?
+}
+
+// a function to unstage some hunks or lines of a file's staged changes
+#[tauri::command]
+pub async fn unstage_hunks(
+    directory: String,
+    filename: String,
+    selections: Vec<HunkSelection>,
+) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let file = file_to_patch(&staged_diff(&repo, Some(&filename), &DiffSettings::default())?, &filename)?;
+
+        apply_selection(&repo, &file, &selections, Direction::Reverse, ApplyLocation::Index)
+    })
+    .await?
+}
+
+// a function to throw away some hunks or lines of a file's unstaged changes
+#[tauri::command]
+pub async fn discard_hunks(
+    directory: String,
+    filename: String,
+    selections: Vec<HunkSelection>,
+) -> Result<(), GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let file = file_to_patch(&unstaged_diff(&repo, Some(&filename), &DiffSettings::default())?, &filename)?;
+
+        crate::snapshot::create_snapshot(&repo, "discard hunks")?;
+        apply_selection(&repo, &file, &selections, Direction::Reverse, ApplyLocation::WorkDir)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    // a repository with `file` committed holding `head`
+    fn repo_with(head: &str) -> TestRepo {
+        let test = TestRepo::new();
+        test.commit_file("file", head, "init");
+        test
+    }
+
+    fn staged(test: &TestRepo) -> String {
+        test.staged("file").unwrap()
+    }
+
+    fn hunk(hunk: usize, lines: Option<Vec<usize>>) -> HunkSelection {
+        HunkSelection { hunk, lines }
+    }
+
+    const TWELVE: &str = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+    // line 2 and line 11 changed, far enough apart to make two hunks:
+    // ` 1`, `-2`, `+two`, ` 3`, ` 4`, ` 5` and ` 8`, ` 9`, ` 10`, `-11`, `+eleven`, ` 12`
+    const TWO_CHANGES: &str = "1\ntwo\n3\n4\n5\n6\n7\n8\n9\n10\neleven\n12\n";
+
+    #[tokio::test]
+    async fn stages_one_hunk() {
+        let test = repo_with(TWELVE);
+        test.write("file", TWO_CHANGES);
+
+        stage_hunks(test.path(), "file".into(), vec![hunk(1, None)]).await.unwrap();
+        assert_eq!(staged(&test), "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\neleven\n12\n");
+    }
+
+    #[tokio::test]
+    async fn stages_an_added_line_without_its_deletion() {
+        let test = repo_with(TWELVE);
+        test.write("file", TWO_CHANGES);
+
+        stage_hunks(test.path(), "file".into(), vec![hunk(0, Some(vec![2]))]).await.unwrap();
+        assert_eq!(staged(&test), "1\n2\ntwo\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n");
+    }
+
+    #[tokio::test]
+    async fn unstages_one_hunk() {
+        let test = repo_with(TWELVE);
+        test.write("file", TWO_CHANGES);
+        test.stage_all();
+
+        unstage_hunks(test.path(), "file".into(), vec![hunk(0, None)]).await.unwrap();
+        assert_eq!(staged(&test), "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\neleven\n12\n");
+    }
+
+    #[tokio::test]
+    async fn discards_a_deletion_and_keeps_the_addition() {
+        let test = repo_with(TWELVE);
+        test.write("file", TWO_CHANGES);
+
+        discard_hunks(test.path(), "file".into(), vec![hunk(0, Some(vec![1]))]).await.unwrap();
+        let content = test.read("file");
+        assert_eq!(content, "1\n2\ntwo\n3\n4\n5\n6\n7\n8\n9\n10\neleven\n12\n");
+    }
+
+    #[tokio::test]
+    async fn stages_a_newline_added_at_the_end() {
+        let test = repo_with("a\nb");
+        test.write("file", "a\nb\n");
+
+        stage_hunks(test.path(), "file".into(), vec![hunk(0, None)]).await.unwrap();
+        assert_eq!(staged(&test), "a\nb\n");
+    }
+
+    #[tokio::test]
+    async fn discards_a_newline_removed_at_the_end() {
+        let test = repo_with("a\nb\n");
+        test.write("file", "a\nb");
+
+        discard_hunks(test.path(), "file".into(), vec![hunk(0, None)]).await.unwrap();
+        assert_eq!(test.read("file"), "a\nb\n");
+        assert_eq!(staged(&test), "a\nb\n");
+    }
+
+    #[tokio::test]
+    async fn unstages_a_line_added_after_the_last_line() {
+        let test = repo_with("a\nb");
+        test.write("file", "a\nb\nc");
+        test.stage_all();
+
+        // ` a`, `-b`, `+b`, `+c`, with b and c each lacking the newline on their side
+        unstage_hunks(test.path(), "file".into(), vec![hunk(0, Some(vec![3]))]).await.unwrap();
+        assert_eq!(staged(&test), "a\nb\n");
+    }
+
+    // the deletion left out stays, so the line that ended the old file is followed by the added
+    // line and has to gain a newline rather than keep a `\ No newline` marker mid-hunk
+    #[tokio::test]
+    async fn stages_a_line_added_after_a_line_without_newline() {
+        let test = repo_with("a\nb");
+        test.write("file", "a\nb\nc\n");
+
+        let diff = unstaged_diff(&test.repo, Some("file"), &DiffSettings::default()).unwrap();
+        let file = file_to_patch(&diff, "file").unwrap();
+        let patch = build_patch(&file, &[hunk(0, Some(vec![3]))], Direction::Forward)
+            .unwrap()
+            .unwrap();
+        assert!(patch.ends_with("@@ -1,2 +1,3 @@\n a\n-b\n\\ No newline at end of file\n+b\n+c\n"));
+
+        stage_hunks(test.path(), "file".into(), vec![hunk(0, Some(vec![3]))]).await.unwrap();
+        assert_eq!(staged(&test), "a\nb\nc\n");
+    }
+}
@@ -0,0 +1,575 @@
+// safety snapshots of HEAD, the index and the working tree, taken before anything that can destroy work.
+// A snapshot is a commit whose tree is the working tree, untracked files included; its parents are
+// HEAD (absent on an unborn branch) and a commit of the index. It lives under a hidden ref so
+// nothing shows it in branch lists, and it keeps the objects alive until the snapshot is pruned.
+use crate::backend::cli::{command_error, run_git_unchecked, run_git_with_env};
+use crate::backend::libgit2::{format_time, head_tree};
+use crate::backend::unsupported_extension;
+use crate::error::GitError;
+use crate::gitfunction::open_repository;
+use chrono::Utc;
+use git2::{
+    build::CheckoutBuilder, BranchType, Commit, ErrorCode, IndexAddOption, ObjectType, Oid, Repository, Signature, Tree,
+    TreeWalkMode, TreeWalkResult,
+};
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+
+const SNAPSHOT_PREFIX: &str = "refs/gitaurora/snapshots/";
+// older snapshots are deleted once there are more than this many
+const MAX_SNAPSHOTS: usize = 100;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    // the last component of the ref, what `restore_snapshot` takes
+    name: String,
+    reference: String,
+    id: String,
+    // the operation the snapshot was taken before, e.g. "discard"
+    operation: String,
+    created: String,
+    // the commit HEAD pointed at, None on an unborn branch
+    head: Option<String>,
+    // the branch HEAD was on, None when detached
+    branch: Option<String>,
+}
+
+pub fn signature(repo: &Repository) -> Result<Signature<'static>, GitError> {
+    match repo.signature() {
+        Ok(signature) => Ok(signature),
+        // a snapshot must not fail just because user.name is not configured
+        Err(_) => Ok(Signature::now("GitAurora", "gitaurora@localhost")?),
+    }
+}
+
+// the tree of the index; an unmerged index cannot be written, so HEAD stands in for it
+fn index_tree(repo: &Repository) -> Result<Oid, GitError> {
+    let mut index = repo.index()?;
+    if !index.has_conflicts() {
+        return Ok(index.write_tree()?);
+    }
+    match head_tree(repo)? {
+        Some(tree) => Ok(tree.id()),
+        None => Ok(repo.treebuilder(None)?.write()?),
+    }
+}
+
+// the tree of the working tree, tracked changes and untracked files included, ignored ones left out
+fn worktree_tree(repo: &Repository) -> Result<Oid, GitError> {
+    let mut index = repo.index()?;
+    let result = index
+        .add_all(["*"], IndexAddOption::DEFAULT, None)
+        .and_then(|_| index.update_all(["*"], None))
+        .and_then(|_| index.write_tree());
+    // the staged files were only borrowed: drop the in-memory changes, the index on disk is untouched
+    index.read(true)?;
+    Ok(result?)
+}
+
+// the branch HEAD was on when the snapshot was taken, from its `Branch:` line
+fn recorded_branch(commit: &Commit) -> Option<String> {
+    commit
+        .message()
+        .unwrap_or("")
+        .lines()
+        .find_map(|line| line.strip_prefix("Branch: "))
+        .map(str::to_string)
+}
+
+fn snapshot_from(reference: &git2::Reference, commit: &Commit) -> Snapshot {
+    let reference_name = reference.name().unwrap_or("").to_string();
+    let branch = recorded_branch(commit);
+    let head = (commit.parent_count() == 2).then(|| commit.parent_id(0).ok()).flatten();
+
+    Snapshot {
+        name: reference_name.trim_start_matches(SNAPSHOT_PREFIX).to_string(),
+        reference: reference_name,
+        id: commit.id().to_string(),
+        operation: commit
+            .summary()
+            .unwrap_or("")
+            .trim_start_matches("Snapshot before ")
+            .to_string(),
+        created: format_time(&commit.time()),
+        head: head.map(|id| id.to_string()),
+        branch,
+    }
+}
+
+// every snapshot in the repository, newest first
+fn snapshots(repo: &Repository) -> Result<Vec<Snapshot>, GitError> {
+    let mut snapshots = Vec::new();
+    for reference in repo.references_glob(&format!("{}*", SNAPSHOT_PREFIX))? {
+        let reference = reference?;
+        let commit = reference.peel_to_commit()?;
+        snapshots.push(snapshot_from(&reference, &commit));
+    }
+    // the names are UTC timestamps, so they sort by age
+    snapshots.sort_by(|a, b| b.name.cmp(&a.name));
+    Ok(snapshots)
+}
+
+fn prune(repo: &Repository) -> Result<(), GitError> {
+    for snapshot in snapshots(repo)?.iter().skip(MAX_SNAPSHOTS) {
+        repo.find_reference(&snapshot.reference)?.delete()?;
+    }
+    Ok(())
+}
+
+// record HEAD, the index and the working tree under a new snapshot ref
+pub fn create_snapshot(repo: &Repository, operation: &str) -> Result<Snapshot, GitError> {
+    let signature = signature(repo)?;
+    let head = match repo.head() {
+        Ok(head) => Some(head.peel_to_commit()?),
+        Err(err) if matches!(err.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => None,
+        Err(err) => return Err(err.into()),
+    };
+    let branch = repo
+        .head()
+        .ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.shorthand().map(str::to_string));
+
+    let head_parents: Vec<&Commit> = head.iter().collect();
+    let index_tree = repo.find_tree(index_tree(repo)?)?;
+    let index_id = repo.commit(
+        None,
+        &signature,
+        &signature,
+        &format!("Index before {}", operation),
+        &index_tree,
+        &head_parents,
+    )?;
+    let index_commit = repo.find_commit(index_id)?;
+
+    let mut parents = head_parents.clone();
+    parents.push(&index_commit);
+    let mut message = format!("Snapshot before {}\n", operation);
+    if let Some(branch) = &branch {
+        message.push_str(&format!("\nBranch: {}\n", branch));
+    }
+    let worktree_tree = repo.find_tree(worktree_tree(repo)?)?;
+    let id = repo.commit(None, &signature, &signature, &message, &worktree_tree, &parents)?;
+
+    // two snapshots in the same millisecond get a counter appended, padded so names still sort by age
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string();
+    let mut name = stamp.clone();
+    let mut attempt = 1;
+    while repo.find_reference(&format!("{}{}", SNAPSHOT_PREFIX, name)).is_ok() {
+        name = format!("{}-{:04}", stamp, attempt);
+        attempt += 1;
+    }
+    let reference = repo.reference(
+        &format!("{}{}", SNAPSHOT_PREFIX, name),
+        id,
+        false,
+        &format!("snapshot: before {}", operation),
+    )?;
+    let snapshot = snapshot_from(&reference, &repo.find_commit(id)?);
+
+    prune(repo)?;
+    Ok(snapshot)
+}
+
+// the stdout of a git command that is allowed to fail, None when it does or prints nothing
+fn git_optional(directory: &str, args: &[&str]) -> Result<Option<String>, GitError> {
+    let output = run_git_unchecked(directory, args)?;
+    let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((output.status.success() && !stdout.is_empty()).then_some(stdout))
+}
+
+// the trimmed stdout of a git command run with extra environment variables
+fn git_with_env(directory: &str, args: &[&str], env: &[(&str, &str)]) -> Result<String, GitError> {
+    let output = run_git_with_env(directory, args, env)?;
+    if !output.status.success() {
+        return Err(command_error(directory, args, &output));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// the same snapshot as `create_snapshot`, built with the git CLI for repositories libgit2 cannot
+// open, such as partial clones
+fn create_snapshot_with_cli(directory: &str, operation: &str) -> Result<(), GitError> {
+    let git = |args: &[&str]| git_with_env(directory, args, &[]);
+    if git(&["rev-parse", "--is-bare-repository"])? == "true" {
+        return Ok(());
+    }
+
+    let head = git_optional(directory, &["rev-parse", "--verify", "-q", "HEAD^{commit}"])?;
+    let branch = match head {
+        Some(_) => git_optional(directory, &["symbolic-ref", "-q", "--short", "HEAD"])?,
+        None => None,
+    };
+
+    // an unmerged index cannot be written, so HEAD stands in for it as in `index_tree`
+    let index_tree = match (git_optional(directory, &["write-tree"])?, &head) {
+        (Some(tree), _) => tree,
+        (None, Some(head)) => git(&["rev-parse", &format!("{}^{{tree}}", head)])?,
+        // `mktree` with nothing on stdin writes the empty tree
+        (None, None) => git(&["mktree"])?,
+    };
+
+    // the working tree goes through a copy of the index, so the real one is untouched
+    let index = Path::new(directory).join(git(&["rev-parse", "--git-path", "index"])?);
+    let scratch = Path::new(directory).join(git(&["rev-parse", "--git-path", "gitaurora-snapshot-index"])?);
+    if index.exists() {
+        fs::copy(&index, &scratch)?;
+    }
+    let scratch_env = [("GIT_INDEX_FILE", scratch.to_str().unwrap_or_default())];
+    let worktree_tree = git_with_env(directory, &["add", "-A"], &scratch_env)
+        .and_then(|_| git_with_env(directory, &["write-tree"], &scratch_env));
+    let _ = fs::remove_file(&scratch);
+    let worktree_tree = worktree_tree?;
+
+    // a snapshot must not fail just because user.name is not configured
+    let identity: &[(&str, &str)] = match git_optional(directory, &["var", "GIT_COMMITTER_IDENT"])? {
+        Some(_) => &[],
+        None => &[
+            ("GIT_AUTHOR_NAME", "GitAurora"),
+            ("GIT_AUTHOR_EMAIL", "gitaurora@localhost"),
+            ("GIT_COMMITTER_NAME", "GitAurora"),
+            ("GIT_COMMITTER_EMAIL", "gitaurora@localhost"),
+        ],
+    };
+    let commit_tree = |args: &[&str]| git_with_env(directory, args, identity);
+
+    let index_message = format!("Index before {}", operation);
+    let mut args = vec!["commit-tree", index_tree.as_str(), "-m", index_message.as_str()];
+    if let Some(head) = &head {
+        args.extend(["-p", head.as_str()]);
+    }
+    let index_commit = commit_tree(&args)?;
+
+    let message = format!("Snapshot before {}", operation);
+    let branch_line = branch.map(|branch| format!("Branch: {}", branch));
+    let mut args = vec!["commit-tree", worktree_tree.as_str(), "-m", message.as_str()];
+    if let Some(branch_line) = &branch_line {
+        args.extend(["-m", branch_line.as_str()]);
+    }
+    if let Some(head) = &head {
+        args.extend(["-p", head.as_str()]);
+    }
+    args.extend(["-p", index_commit.as_str()]);
+    let id = commit_tree(&args)?;
+
+    // two snapshots in the same millisecond get a counter appended, padded so names still sort by age
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string();
+    let mut name = stamp.clone();
+    let mut attempt = 1;
+    while git_optional(directory, &["rev-parse", "--verify", "-q", &format!("{}{}", SNAPSHOT_PREFIX, name)])?.is_some() {
+        name = format!("{}-{:04}", stamp, attempt);
+        attempt += 1;
+    }
+    let reflog = format!("snapshot: before {}", operation);
+    git(&["update-ref", "-m", &reflog, &format!("{}{}", SNAPSHOT_PREFIX, name), &id])?;
+
+    // the names are UTC timestamps, so sorting by name sorts by age
+    let refs = git(&["for-each-ref", "--sort=-refname", "--format=%(refname)", SNAPSHOT_PREFIX])?;
+    for reference in refs.lines().skip(MAX_SNAPSHOTS) {
+        git(&["update-ref", "-d", reference])?;
+    }
+    Ok(())
+}
+
+// snapshot the repository at `directory` before a destructive operation. Bare repositories have
+// no working tree to lose and are skipped; ones libgit2 cannot open are snapshotted with the CLI.
+pub fn take(directory: &str, operation: &str) -> Result<(), GitError> {
+    let repo = match Repository::open(directory) {
+        Ok(repo) => repo,
+        Err(err) if unsupported_extension(&err) => {
+            return create_snapshot_with_cli(directory, operation);
+        }
+        Err(err) => return Err(GitError::repo_not_found(directory, err)),
+    };
+    if repo.is_bare() {
+        return Ok(());
+    }
+
+    create_snapshot(&repo, operation)?;
+    Ok(())
+}
+
+// point HEAD back at the commit it was on: the recorded branch is moved there and checked out,
+// and no other branch is touched; without a recorded branch HEAD is detached at the commit
+fn move_head(repo: &Repository, commit: Oid, branch: Option<&str>) -> Result<(), GitError> {
+    match branch {
+        Some(branch) => {
+            let refname = format!("refs/heads/{}", branch);
+            repo.reference(&refname, commit, true, "snapshot: restore")?;
+            repo.set_head(&refname)?;
+        }
+        None => repo.set_head_detached(commit)?,
+    }
+    Ok(())
+}
+
+// delete the working tree files of `current` that `target` does not have, and directories left empty
+fn remove_missing_files(repo: &Repository, current: &Tree, target: &Tree) -> Result<(), GitError> {
+    let Some(workdir) = repo.workdir() else {
+        return Ok(());
+    };
+
+    let mut missing = Vec::new();
+    current.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            let path = format!("{}{}", root, entry.name().unwrap_or(""));
+            if target.get_path(Path::new(&path)).is_err() {
+                missing.push(path);
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+
+    for path in missing {
+        let path = workdir.join(path);
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+            _ => {}
+        }
+        // remove_dir only succeeds on empty directories, which is exactly the ones to drop
+        let mut parent = path.parent();
+        while let Some(dir) = parent.filter(|dir| *dir != workdir && fs::remove_dir(dir).is_ok()) {
+            parent = dir.parent();
+        }
+    }
+    Ok(())
+}
+
+// bring back a snapshot; `current` is the working tree being replaced, so files that exist only
+// there, untracked ones included, are removed rather than left behind
+fn restore(repo: &Repository, snapshot: &Commit, current: &Tree) -> Result<(), GitError> {
+    let (head, index_commit) = match snapshot.parent_count() {
+        2 => (Some(snapshot.parent_id(0)?), snapshot.parent(1)?),
+        _ => (None, snapshot.parent(0)?),
+    };
+    let index_tree = index_commit.tree()?;
+
+    // a merge, rebase or cherry-pick in progress belongs to the state being replaced
+    repo.cleanup_state()?;
+    if let Some(head) = head {
+        move_head(repo, head, recorded_branch(snapshot).as_deref())?;
+    }
+
+    let target = snapshot.tree()?;
+    remove_missing_files(repo, current, &target)?;
+    repo.checkout_tree(
+        target.as_object(),
+        Some(CheckoutBuilder::new().force().recreate_missing(true)),
+    )?;
+
+    // the checkout staged the whole working tree; put the index back as it was
+    let mut index = repo.index()?;
+    index.read_tree(&index_tree)?;
+    index.write()?;
+    Ok(())
+}
+
+// a function to list the safety snapshots of a repository, newest first
+#[tauri::command]
+pub async fn list_snapshots(directory: String) -> Result<Vec<Snapshot>, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        snapshots(&repo)
+    })
+    .await?
+}
+
+// a function to put HEAD, the index and the working tree back to a snapshot,
+// returning the snapshot of the state it replaced
+#[tauri::command]
+pub async fn restore_snapshot(directory: String, name: String) -> Result<Snapshot, GitError> {
+    tokio::task::spawn_blocking(move || {
+        let repo = open_repository(&directory)?;
+        let target = repo
+            .find_reference(&format!("{}{}", SNAPSHOT_PREFIX, name))
+            .map_err(|err| GitError::ref_not_found(&name, err))?
+            .peel_to_commit()?;
+
+        // the branch the snapshot was taken on is where HEAD goes back to; recreating a deleted one
+        // is left to the user rather than guessed
+        if let Some(branch) = recorded_branch(&target) {
+            if repo.find_branch(&branch, BranchType::Local).is_err() {
+                return Err(GitError::RefNotFound {
+                    message: format!("The snapshot was taken on branch '{}', which no longer exists.", branch),
+                    name: branch,
+                });
+            }
+        }
+
+        let before = create_snapshot(&repo, "restore")?;
+        let current = repo.find_commit(Oid::from_str(&before.id)?)?.tree()?;
+        restore(&repo, &target, &current)?;
+        Ok(before)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TestRepo;
+
+    fn only_snapshot(test: &TestRepo) -> Snapshot {
+        let mut snapshots = snapshots(&test.repo).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        snapshots.remove(0)
+    }
+
+    fn file_in(repo: &Repository, tree: &Tree, path: &str) -> Option<String> {
+        let entry = tree.get_path(Path::new(path)).ok()?;
+        let blob = repo.find_blob(entry.id()).unwrap();
+        Some(String::from_utf8(blob.content().to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn records_head_the_index_and_the_working_tree() {
+        let test = TestRepo::new();
+        let head = test.commit_file("file", "committed\n", "first");
+        test.write("file", "staged\n");
+        test.stage_all();
+        test.write("file", "unstaged\n");
+        test.write("untracked", "new\n");
+        test.write(".gitignore", "ignored\n");
+        test.write("ignored", "ignored\n");
+
+        take(&test.path(), "discard").unwrap();
+
+        let listed = list_snapshots(test.path()).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        let snapshot = &listed[0];
+        assert_eq!(snapshot.operation, "discard");
+        assert_eq!(snapshot.head, Some(head.to_string()));
+        assert_eq!(snapshot.branch.as_deref(), Some("main"));
+
+        let commit = test.repo.find_commit(Oid::from_str(&snapshot.id).unwrap()).unwrap();
+        let worktree = commit.tree().unwrap();
+        assert_eq!(file_in(&test.repo, &worktree, "file").as_deref(), Some("unstaged\n"));
+        assert_eq!(file_in(&test.repo, &worktree, "untracked").as_deref(), Some("new\n"));
+        assert_eq!(file_in(&test.repo, &worktree, "ignored"), None);
+        let index = commit.parent(1).unwrap().tree().unwrap();
+        assert_eq!(file_in(&test.repo, &index, "file").as_deref(), Some("staged\n"));
+        assert_eq!(file_in(&test.repo, &index, "untracked"), None);
+
+        // taking the snapshot leaves the index alone
+        assert_eq!(test.staged("file").as_deref(), Some("staged\n"));
+        assert_eq!(test.staged("untracked"), None);
+    }
+
+    #[tokio::test]
+    async fn restores_discarded_work_and_removes_what_came_after() {
+        let test = TestRepo::new();
+        test.commit_file("file", "committed\n", "first");
+        test.write("file", "staged\n");
+        test.stage_all();
+        test.write("file", "unstaged\n");
+        test.write("dir/untracked", "new\n");
+        take(&test.path(), "discard").unwrap();
+        let snapshot = only_snapshot(&test);
+
+        test.git(&["reset", "-q", "--hard"]);
+        test.git(&["clean", "-q", "-fd"]);
+        test.write("later/file", "later\n");
+
+        let before = restore_snapshot(test.path(), snapshot.name.clone()).await.unwrap();
+        assert_eq!(before.operation, "restore");
+        assert_eq!(test.read("file"), "unstaged\n");
+        assert_eq!(test.read("dir/untracked"), "new\n");
+        assert_eq!(test.staged("file").as_deref(), Some("staged\n"));
+        assert_eq!(test.staged("dir/untracked"), None);
+        assert!(!test.join("later").exists());
+
+        // the state it replaced can be brought back in turn
+        restore_snapshot(test.path(), before.name).await.unwrap();
+        assert_eq!(test.read("later/file"), "later\n");
+        assert_eq!(test.read("file"), "committed\n");
+    }
+
+    #[tokio::test]
+    async fn moves_the_recorded_branch_back_and_leaves_others_alone() {
+        let test = TestRepo::new();
+        let first = test.commit_file("file", "one\n", "first");
+        test.git(&["branch", "other"]);
+        take(&test.path(), "merge").unwrap();
+        let snapshot = only_snapshot(&test);
+
+        test.commit_file("file", "two\n", "second");
+        test.git(&["checkout", "-q", "other"]);
+        let other = test.commit_file("file", "three\n", "third");
+
+        restore_snapshot(test.path(), snapshot.name).await.unwrap();
+        let head = test.repo.head().unwrap();
+        assert_eq!(head.shorthand(), Some("main"));
+        assert_eq!(head.target(), Some(first));
+        assert_eq!(test.repo.refname_to_id("refs/heads/other").unwrap(), other);
+        assert_eq!(test.read("file"), "one\n");
+    }
+
+    #[tokio::test]
+    async fn refuses_a_snapshot_whose_branch_is_gone() {
+        let test = TestRepo::new();
+        test.commit_file("file", "one\n", "first");
+        test.git(&["checkout", "-q", "-b", "topic"]);
+        take(&test.path(), "pull").unwrap();
+        let snapshot = only_snapshot(&test);
+        test.git(&["checkout", "-q", "main"]);
+        test.git(&["branch", "-q", "-D", "topic"]);
+
+        let err = restore_snapshot(test.path(), snapshot.name).await.unwrap_err();
+        assert!(matches!(err, GitError::RefNotFound { name, .. } if name == "topic"));
+        let err = restore_snapshot(test.path(), "missing".into()).await.unwrap_err();
+        assert!(matches!(err, GitError::RefNotFound { .. }));
+    }
+
+    #[tokio::test]
+    async fn snapshots_and_restores_an_unborn_branch() {
+        let test = TestRepo::new();
+        test.write("file", "staged\n");
+        test.stage_all();
+        take(&test.path(), "discard").unwrap();
+        let snapshot = only_snapshot(&test);
+        assert_eq!(snapshot.head, None);
+
+        std::fs::remove_file(test.join("file")).unwrap();
+        test.git(&["rm", "-q", "--cached", "file"]);
+        restore_snapshot(test.path(), snapshot.name).await.unwrap();
+        assert_eq!(test.read("file"), "staged\n");
+        assert_eq!(test.staged("file").as_deref(), Some("staged\n"));
+    }
+
+    #[test]
+    fn keeps_only_the_newest_snapshots() {
+        let test = TestRepo::new();
+        test.commit_file("file", "one\n", "first");
+        // most of these land in the same millisecond and differ only in their counter
+        let created: Vec<String> = (0..=MAX_SNAPSHOTS)
+            .map(|_| create_snapshot(&test.repo, "discard").unwrap().name)
+            .collect();
+        let kept: Vec<String> = snapshots(&test.repo).unwrap().into_iter().map(|snapshot| snapshot.name).collect();
+        let newest: Vec<String> = created.into_iter().skip(1).rev().collect();
+        assert_eq!(kept, newest);
+    }
+
+    // a repository using an extension libgit2 does not know gets the same snapshot from the CLI
+    #[test]
+    fn snapshots_a_repository_libgit2_cannot_open() {
+        let test = TestRepo::new();
+        let head = test.commit_file("file", "committed\n", "first");
+        test.write("file", "staged\n");
+        test.stage_all();
+        test.write("untracked", "new\n");
+        test.git(&["config", "core.repositoryformatversion", "1"]);
+        test.git(&["config", "extensions.partialclone", "origin"]);
+
+        take(&test.path(), "discard").unwrap();
+
+        let reference = test.git(&["for-each-ref", "--format=%(refname)", SNAPSHOT_PREFIX]);
+        let reference = reference.trim();
+        let parents = test.git(&["rev-list", "--parents", "-n", "1", reference]);
+        assert_eq!(parents.split_whitespace().nth(1), Some(head.to_string().as_str()));
+        assert_eq!(test.git(&["show", &format!("{}:untracked", reference)]), "new\n");
+        assert_eq!(test.git(&["show", &format!("{}^2:file", reference)]), "staged\n");
+        assert!(test.git(&["log", "-1", "--format=%B", reference]).contains("Branch: main"));
+        assert_eq!(test.git(&["diff", "--cached", "--name-only"]).trim(), "file");
+    }
+}
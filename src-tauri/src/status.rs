@@ -0,0 +1,347 @@
+// the status of working tree files, shared by every status command whichever backend produced it
+use serde::Serialize;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+// the state of a file on one side: HEAD to index, or index to working tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileState {
+    Unmodified,
+    Modified,
+    TypeChanged,
+    Added,
+    Deleted,
+    Renamed,
+    Copied,
+    Untracked,
+    Ignored,
+    Unmerged,
+}
+
+impl FileState {
+    // the state behind one letter of a porcelain `XY` code
+    fn from_code(code: char) -> FileState {
+        match code {
+            'M' => FileState::Modified,
+            'T' => FileState::TypeChanged,
+            'A' => FileState::Added,
+            'D' => FileState::Deleted,
+            'R' => FileState::Renamed,
+            'C' => FileState::Copied,
+            'U' => FileState::Unmerged,
+            '?' => FileState::Untracked,
+            '!' => FileState::Ignored,
+            _ => FileState::Unmodified,
+        }
+    }
+}
+
+// which sides of a merge touched an unmerged file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictState {
+    BothDeleted,
+    AddedByUs,
+    DeletedByThem,
+    AddedByThem,
+    DeletedByUs,
+    BothAdded,
+    BothModified,
+}
+
+impl ConflictState {
+    // the conflict behind an unmerged `XY` code, as listed in git-status(1)
+    fn from_code(xy: &str) -> Option<ConflictState> {
+        match xy {
+            "DD" => Some(ConflictState::BothDeleted),
+            "AU" => Some(ConflictState::AddedByUs),
+            "UD" => Some(ConflictState::DeletedByThem),
+            "UA" => Some(ConflictState::AddedByThem),
+            "DU" => Some(ConflictState::DeletedByUs),
+            "AA" => Some(ConflictState::BothAdded),
+            "UU" => Some(ConflictState::BothModified),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SubmoduleState {
+    pub commit_changed: bool,
+    pub has_tracked_changes: bool,
+    pub has_untracked_changes: bool,
+}
+
+impl SubmoduleState {
+    // parse the `<sub>` field: `N...` for a regular file, `S<c><m><u>` for a submodule
+    fn from_code(sub: &str) -> Option<SubmoduleState> {
+        let mut flags = sub.strip_prefix('S')?.chars();
+        Some(SubmoduleState {
+            commit_changed: flags.next() == Some('C'),
+            has_tracked_changes: flags.next() == Some('M'),
+            has_untracked_changes: flags.next() == Some('U'),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusEntry {
+    pub path: String,
+    // the source path of a rename or copy
+    pub old_path: Option<String>,
+    pub index: FileState,
+    pub worktree: FileState,
+    pub conflict: Option<ConflictState>,
+    pub submodule: Option<SubmoduleState>,
+    // rename or copy similarity, in percent
+    pub similarity: Option<u32>,
+    // file modes in HEAD, the index and the working tree, 0 where the file is absent
+    pub head_mode: u32,
+    pub index_mode: u32,
+    pub worktree_mode: u32,
+    pub mode_changed: bool,
+    // None when the content could not be inspected, e.g. a deleted file
+    pub binary: Option<bool>,
+    pub size: Option<u64>,
+}
+
+impl StatusEntry {
+    pub fn new(path: String, index: FileState, worktree: FileState) -> Self {
+        StatusEntry {
+            path,
+            old_path: None,
+            index,
+            worktree,
+            conflict: None,
+            submodule: None,
+            similarity: None,
+            head_mode: 0,
+            index_mode: 0,
+            worktree_mode: 0,
+            mode_changed: false,
+            binary: None,
+            size: None,
+        }
+    }
+
+    // record the three file modes and whether any present pair differs
+    pub fn set_modes(&mut self, head: u32, index: u32, worktree: u32) {
+        self.head_mode = head;
+        self.index_mode = index;
+        self.worktree_mode = worktree;
+
+        let present: Vec<u32> = [head, index, worktree].into_iter().filter(|mode| *mode != 0).collect();
+        self.mode_changed = present.windows(2).any(|pair| pair[0] != pair[1]);
+    }
+
+    // fill in size and binary flag from the file on disk, if it is there
+    pub fn inspect_worktree_file(&mut self, workdir: &Path) {
+        let path = workdir.join(&self.path);
+        let Ok(metadata) = fs::metadata(&path) else {
+            return;
+        };
+        if !metadata.is_file() {
+            return;
+        }
+
+        self.size = Some(metadata.len());
+        self.binary = looks_binary(&path);
+    }
+
+    pub fn is_staged(&self) -> bool {
+        !matches!(
+            self.index,
+            FileState::Unmodified | FileState::Untracked | FileState::Ignored | FileState::Unmerged
+        )
+    }
+
+    // the single label the file lists use, the staged state winning over the unstaged one
+    pub fn label(&self) -> &'static str {
+        if self.conflict.is_some() || self.index == FileState::Unmerged {
+            return "conflicted";
+        }
+
+        let state = if self.index != FileState::Unmodified {
+            self.index
+        } else {
+            self.worktree
+        };
+        match state {
+            FileState::Modified => "modified",
+            FileState::TypeChanged => "typechange",
+            FileState::Added => "added",
+            FileState::Deleted => "deleted",
+            FileState::Renamed => "renamed",
+            FileState::Copied => "copied",
+            FileState::Untracked => "untracked",
+            FileState::Ignored => "ignored",
+            FileState::Unmerged => "conflicted",
+            FileState::Unmodified => "unknown",
+        }
+    }
+}
+
+// git's heuristic: a NUL byte in the first 8000 bytes makes a file binary
+fn looks_binary(path: &Path) -> Option<bool> {
+    let mut head = Vec::with_capacity(8000);
+    fs::File::open(path)
+        .ok()?
+        .take(8000)
+        .read_to_end(&mut head)
+        .ok()?;
+    Some(head.contains(&0))
+}
+
+// parse `git status --porcelain=v2 -z`, where records are NUL-terminated and paths are not quoted
+pub fn parse_porcelain_v2(output: &[u8]) -> Vec<StatusEntry> {
+    let mut entries = Vec::new();
+    let mut records = output
+        .split(|byte| *byte == 0)
+        .map(|record| String::from_utf8_lossy(record).into_owned());
+
+    while let Some(record) = records.next() {
+        let Some((kind, rest)) = record.split_once(' ') else {
+            continue;
+        };
+
+        match kind {
+            // 1 <XY> <sub> <mH> <mI> <mW> <hH> <hI> <path>
+            "1" => {
+                let fields: Vec<&str> = rest.splitn(8, ' ').collect();
+                if fields.len() == 8 {
+                    let mut entry = changed_entry(fields[0], fields[1], fields[7]);
+                    entry.set_modes(octal(fields[2]), octal(fields[3]), octal(fields[4]));
+                    entries.push(entry);
+                }
+            }
+            // 2 <XY> <sub> <mH> <mI> <mW> <hH> <hI> <X><score> <path>, then the original path
+            "2" => {
+                let fields: Vec<&str> = rest.splitn(9, ' ').collect();
+                let old_path = records.next();
+                if fields.len() == 9 {
+                    let mut entry = changed_entry(fields[0], fields[1], fields[8]);
+                    entry.set_modes(octal(fields[2]), octal(fields[3]), octal(fields[4]));
+                    entry.old_path = old_path;
+                    entry.similarity = fields[7].get(1..).and_then(|score| score.parse().ok());
+                    entries.push(entry);
+                }
+            }
+            // u <XY> <sub> <m1> <m2> <m3> <mW> <h1> <h2> <h3> <path>
+            "u" => {
+                let fields: Vec<&str> = rest.splitn(10, ' ').collect();
+                if fields.len() == 10 {
+                    let mut entry = changed_entry(fields[0], fields[1], fields[9]);
+                    // stage 2 is our side, the one the working tree started from
+                    entry.set_modes(octal(fields[3]), octal(fields[3]), octal(fields[5]));
+                    entry.conflict = ConflictState::from_code(fields[0]);
+                    entry.index = FileState::Unmerged;
+                    entry.worktree = FileState::Unmerged;
+                    entries.push(entry);
+                }
+            }
+            "?" => entries.push(StatusEntry::new(
+                rest.to_string(),
+                FileState::Unmodified,
+                FileState::Untracked,
+            )),
+            "!" => entries.push(StatusEntry::new(
+                rest.to_string(),
+                FileState::Unmodified,
+                FileState::Ignored,
+            )),
+            // `#` headers only appear with --branch
+            _ => {}
+        }
+    }
+
+    entries
+}
+
+fn octal(mode: &str) -> u32 {
+    u32::from_str_radix(mode, 8).unwrap_or(0)
+}
+
+fn changed_entry(xy: &str, sub: &str, path: &str) -> StatusEntry {
+    let mut codes = xy.chars();
+    let index = FileState::from_code(codes.next().unwrap_or('.'));
+    let worktree = FileState::from_code(codes.next().unwrap_or('.'));
+
+    let mut entry = StatusEntry::new(path.to_string(), index, worktree);
+    entry.submodule = SubmoduleState::from_code(sub);
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZERO: &str = "0000000000000000000000000000000000000000";
+    const BLOB: &str = "e69de29bb2d1d6434b8b29ae775ad8c2e48c5391";
+
+    #[test]
+    fn parses_ordinary_changes_with_spaces_in_paths() {
+        let output = format!(
+            "1 .M N... 100644 100644 100644 {b} {b} dir with spaces/a file.txt\0\
+             1 A. N... 000000 100755 100755 {z} {b} new script\0",
+            b = BLOB,
+            z = ZERO
+        );
+        let entries = parse_porcelain_v2(output.as_bytes());
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, "dir with spaces/a file.txt");
+        assert_eq!(entries[0].index, FileState::Unmodified);
+        assert_eq!(entries[0].worktree, FileState::Modified);
+        assert_eq!(entries[1].path, "new script");
+        assert_eq!(entries[1].index, FileState::Added);
+        assert_eq!((entries[1].head_mode, entries[1].index_mode), (0, 0o100755));
+    }
+
+    #[test]
+    fn parses_renames_and_copies_with_their_original_path() {
+        let output = format!(
+            "2 R. N... 100644 100644 100644 {b} {b} R87 new name.txt\0old name.txt\0\
+             2 CM N... 100644 100644 100644 {b} {b} C100 copy of it\0it\0\
+             ? untracked file\0",
+            b = BLOB
+        );
+        let entries = parse_porcelain_v2(output.as_bytes());
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].path, "new name.txt");
+        assert_eq!(entries[0].old_path.as_deref(), Some("old name.txt"));
+        assert_eq!(entries[0].index, FileState::Renamed);
+        assert_eq!(entries[0].similarity, Some(87));
+        assert_eq!(entries[1].path, "copy of it");
+        assert_eq!(entries[1].old_path.as_deref(), Some("it"));
+        assert_eq!(entries[1].index, FileState::Copied);
+        assert_eq!(entries[1].worktree, FileState::Modified);
+        assert_eq!(entries[1].similarity, Some(100));
+        // the original path is not mistaken for a record of its own
+        assert_eq!(entries[2].path, "untracked file");
+        assert_eq!(entries[2].worktree, FileState::Untracked);
+    }
+
+    #[test]
+    fn parses_conflicts_and_submodules() {
+        let output = format!(
+            "u UU N... 100644 100644 100644 100644 {b} {b} {b} both changed.txt\0\
+             1 .M SCMU 160000 160000 160000 {b} {b} sub module\0\
+             ! build out/\0",
+            b = BLOB
+        );
+        let entries = parse_porcelain_v2(output.as_bytes());
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].path, "both changed.txt");
+        assert_eq!(entries[0].conflict, Some(ConflictState::BothModified));
+        assert_eq!(entries[0].index, FileState::Unmerged);
+        let submodule = entries[1].submodule.unwrap();
+        assert!(submodule.commit_changed && submodule.has_tracked_changes && submodule.has_untracked_changes);
+        assert_eq!(entries[1].path, "sub module");
+        assert_eq!(entries[2].path, "build out/");
+        assert_eq!(entries[2].worktree, FileState::Ignored);
+    }
+}
@@ -0,0 +1,127 @@
+// a repository in a temporary directory for the tests, removed again when it is dropped
+use git2::{Oid, Repository, RepositoryInitOptions, Signature, Time};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+pub struct TestRepo {
+    pub repo: Repository,
+    dir: TempDir,
+}
+
+impl TestRepo {
+    // an empty repository on `main`, with an identity and no commit signing
+    pub fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let mut options = RepositoryInitOptions::new();
+        options.initial_head("main");
+        let repo = Repository::init_opts(dir.path(), &options).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "Test").unwrap();
+        config.set_str("user.email", "test@example.com").unwrap();
+        config.set_bool("commit.gpgsign", false).unwrap();
+        TestRepo { repo, dir }
+    }
+
+    // the working directory, as the commands take it
+    pub fn path(&self) -> String {
+        self.dir.path().to_string_lossy().into_owned()
+    }
+
+    pub fn join(&self, file: &str) -> PathBuf {
+        self.dir.path().join(file)
+    }
+
+    pub fn write(&self, file: &str, contents: &str) {
+        let path = self.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    pub fn read(&self, file: &str) -> String {
+        fs::read_to_string(self.join(file)).unwrap()
+    }
+
+    // stage every change in the working tree, deletions included
+    pub fn stage_all(&self) {
+        let mut index = self.repo.index().unwrap();
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None).unwrap();
+        index.update_all(["*"], None).unwrap();
+        index.write().unwrap();
+    }
+
+    // stage everything and commit it on top of HEAD
+    pub fn commit(&self, message: &str) -> Oid {
+        self.stage_all();
+        let mut index = self.repo.index().unwrap();
+        let tree = self.repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let signature = self.repo.signature().unwrap();
+        let parent = self.repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+        let parents: Vec<&git2::Commit> = parent.iter().collect();
+        self.repo
+            .commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)
+            .unwrap()
+    }
+
+    // write `file` and commit it
+    pub fn commit_file(&self, file: &str, contents: &str, message: &str) -> Oid {
+        self.write(file, contents);
+        self.commit(message)
+    }
+
+    // an empty commit made at `time` seconds, moving `branch` to it when given
+    pub fn empty_commit(&self, branch: Option<&str>, time: i64, parents: &[Oid]) -> Oid {
+        let signature = Signature::new("Test", "test@example.com", &Time::new(time, 0)).unwrap();
+        let tree = self.repo.find_tree(self.repo.treebuilder(None).unwrap().write().unwrap()).unwrap();
+        let parents: Vec<git2::Commit> = parents.iter().map(|id| self.repo.find_commit(*id).unwrap()).collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        let oid = self
+            .repo
+            .commit(None, &signature, &signature, &time.to_string(), &tree, &parents)
+            .unwrap();
+        if let Some(branch) = branch {
+            self.repo.reference(&format!("refs/heads/{}", branch), oid, true, "test").unwrap();
+        }
+        oid
+    }
+
+    // a bare repository added as remote `name`; the directory is removed when the returned value is dropped
+    pub fn remote(&self, name: &str) -> TempDir {
+        let dir = TempDir::new().unwrap();
+        Repository::init_bare(dir.path()).unwrap();
+        self.git(&["remote", "add", name, &dir.path().to_string_lossy()]);
+        dir
+    }
+
+    // the staged contents of `file`; the commands write the index through their own handle,
+    // so it is read again from disk
+    pub fn staged(&self, file: &str) -> Option<String> {
+        let mut index = self.repo.index().unwrap();
+        index.read(true).unwrap();
+        let entry = index.get_path(Path::new(file), 0)?;
+        Some(String::from_utf8(self.repo.find_blob(entry.id).unwrap().content().to_vec()).unwrap())
+    }
+
+    // run git in the repository, failing the test when it fails, and return its stdout
+    pub fn git(&self, args: &[&str]) -> String {
+        let output = self.git_unchecked(args);
+        assert!(
+            output.status.success(),
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    // run git in the repository whatever its exit status
+    pub fn git_unchecked(&self, args: &[&str]) -> std::process::Output {
+        Command::new("git")
+            .args(args)
+            .env("LC_ALL", "C")
+            .current_dir(self.dir.path())
+            .output()
+            .unwrap()
+    }
+}